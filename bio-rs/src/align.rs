@@ -0,0 +1,411 @@
+use std::cmp;
+
+use bases::{Base, Bases};
+
+/// One column of a pairwise alignment
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AlignOp {
+    Match,
+    Mismatch,
+    /// A query base missing from the reference
+    Insertion,
+    /// A reference base missing from the query
+    Deletion,
+}
+
+/// Scores for global alignment with affine gaps
+/// A gap of length n scores gap_open + n * gap_extend
+#[derive(Clone, Copy, Debug)]
+pub struct Scoring {
+    pub match_score: i32,
+    pub mismatch: i32,
+    pub gap_open: i32,
+    pub gap_extend: i32,
+}
+
+impl Scoring {
+    pub fn new() -> Scoring {
+        Scoring {
+            match_score: 2,
+            mismatch: -4,
+            gap_open: -6,
+            gap_extend: -1,
+        }
+    }
+}
+
+/// A difference between a sequence and its reference, in VCF style
+/// Indels carry the reference base before them and are left-normalized
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Variant {
+    /// 0-based position of the first reference base
+    pub pos: usize,
+    pub ref_allele: Bases,
+    pub alt_allele: Bases,
+}
+
+impl Variant {
+    pub fn is_snp(&self) -> bool {
+        self.ref_allele.len() == 1 && self.alt_allele.len() == 1
+    }
+}
+
+// Alignment states: ending in a (mis)match, a deletion or an insertion
+const MATCH: usize = 0;
+const DELETION: usize = 1;
+const INSERTION: usize = 2;
+
+const NEG_INF: i32 = ::std::i32::MIN / 2;
+
+/// Globally aligns query to reference (Gotoh's algorithm)
+/// Only two rows of scores are kept, with a byte per cell recording where each state's best path
+/// came from, so long amplicons take n * m bytes rather than three score matrices
+pub fn global_align(reference: &[Base], query: &[Base], scoring: &Scoring) -> Vec<AlignOp> {
+    let n = reference.len();
+    let m = query.len();
+    let open = scoring.gap_open + scoring.gap_extend;
+
+    // previous[state][j] is the best score of reference[..i - 1] against query[..j] ending in state,
+    // current[state][j] the same for reference[..i]
+    // traceback[i * (m + 1) + j] holds the state before each state at (i, j), two bits apiece
+    let mut previous = vec![vec![NEG_INF; m + 1]; 3];
+    let mut current = vec![vec![NEG_INF; m + 1]; 3];
+    let mut traceback = vec![0u8; (n + 1) * (m + 1)];
+    previous[MATCH][0] = 0;
+    for j in 1..m + 1 {
+        previous[INSERTION][j] = scoring.gap_open + (j as i32) * scoring.gap_extend;
+        traceback[j] = from(INSERTION, INSERTION);
+    }
+
+    for i in 1..n + 1 {
+        let row = i * (m + 1);
+        current[MATCH][0] = NEG_INF;
+        current[DELETION][0] = scoring.gap_open + (i as i32) * scoring.gap_extend;
+        current[INSERTION][0] = NEG_INF;
+        traceback[row] = from(DELETION, DELETION);
+
+        for j in 1..m + 1 {
+            let substitution = if reference[i - 1] == query[j - 1] { scoring.match_score } else { scoring.mismatch };
+            let diagonal = best_state(&previous, j - 1);
+            current[MATCH][j] = previous[diagonal][j - 1] + substitution;
+
+            // Extending a gap wins ties, then opening it after a match
+            let opened = if previous[MATCH][j] >= previous[INSERTION][j] { MATCH } else { INSERTION };
+            let (deletion, deletion_from) = if previous[DELETION][j] + scoring.gap_extend >= previous[opened][j] + open {
+                (previous[DELETION][j] + scoring.gap_extend, DELETION)
+            } else {
+                (previous[opened][j] + open, opened)
+            };
+            current[DELETION][j] = deletion;
+
+            let opened = if current[MATCH][j - 1] >= current[DELETION][j - 1] { MATCH } else { DELETION };
+            let (insertion, insertion_from) = if current[INSERTION][j - 1] + scoring.gap_extend >= current[opened][j - 1] + open {
+                (current[INSERTION][j - 1] + scoring.gap_extend, INSERTION)
+            } else {
+                (current[opened][j - 1] + open, opened)
+            };
+            current[INSERTION][j] = insertion;
+
+            traceback[row + j] = from(MATCH, diagonal) | from(DELETION, deletion_from) | from(INSERTION, insertion_from);
+        }
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+
+    // Trace back from the best final state
+    let mut ops = vec!();
+    let (mut i, mut j) = (n, m);
+    let mut state = best_state(&previous, m);
+    while i > 0 || j > 0 {
+        let before = (traceback[i * (m + 1) + j] >> (2 * state)) as usize & 3;
+        match state {
+            MATCH => {
+                let same = reference[i - 1] == query[j - 1];
+                ops.push(if same { AlignOp::Match } else { AlignOp::Mismatch });
+                i -= 1;
+                j -= 1;
+            },
+            DELETION => {
+                ops.push(AlignOp::Deletion);
+                i -= 1;
+            },
+            _ => {
+                ops.push(AlignOp::Insertion);
+                j -= 1;
+            },
+        }
+        state = before;
+    }
+
+    ops.reverse();
+    ops
+}
+
+/// Traceback bits recording that state was reached from before
+fn from(state: usize, before: usize) -> u8 {
+    (before << (2 * state)) as u8
+}
+
+/// The state with the best score in column j of a row, preferring a match, then a deletion
+fn best_state(row: &Vec<Vec<i32>>, j: usize) -> usize {
+    if row[MATCH][j] >= row[DELETION][j] && row[MATCH][j] >= row[INSERTION][j] {
+        MATCH
+    } else if row[DELETION][j] >= row[INSERTION][j] {
+        DELETION
+    } else {
+        INSERTION
+    }
+}
+
+/// Aligns a sequence to its reference and lists the SNPs and indels it carries
+pub fn variants(reference: &Bases, sequence: &Bases) -> Vec<Variant> {
+    let ops = global_align(&reference.bases, &sequence.bases, &Scoring::new());
+
+    let mut variants = vec!();
+    let (mut r, mut q) = (0, 0);
+    let mut k = 0;
+    while k < ops.len() {
+        match ops[k] {
+            AlignOp::Match => {
+                r += 1;
+                q += 1;
+                k += 1;
+            },
+            AlignOp::Mismatch => {
+                variants.push(Variant {
+                    pos: r,
+                    ref_allele: Bases { bases: vec![reference.bases[r]] },
+                    alt_allele: Bases { bases: vec![sequence.bases[q]] },
+                });
+                r += 1;
+                q += 1;
+                k += 1;
+            },
+            AlignOp::Deletion | AlignOp::Insertion => {
+                // Take the whole run of gaps as one event
+                let (start_r, start_q) = (r, q);
+                while k < ops.len() && (ops[k] == AlignOp::Deletion || ops[k] == AlignOp::Insertion) {
+                    if ops[k] == AlignOp::Deletion { r += 1; } else { q += 1; }
+                    k += 1;
+                }
+                variants.push(normalize(
+                    &reference.bases,
+                    start_r,
+                    reference.bases[start_r..r].to_vec(),
+                    sequence.bases[start_q..q].to_vec(),
+                ));
+            },
+        }
+    }
+
+    variants.sort();
+    variants
+}
+
+/// Aligns a sequence to its reference and gets the sequence's base at each 0-based reference
+/// position, None where the position is deleted or past the end of the reference
+pub fn bases_at(reference: &Bases, sequence: &Bases, positions: &[usize]) -> Vec<Option<Base>> {
+    let ops = global_align(&reference.bases, &sequence.bases, &Scoring::new());
+
+    // The sequence base aligned to each reference position
+    let mut aligned = vec!();
+    let mut q = 0;
+    for op in ops {
+        match op {
+            AlignOp::Match | AlignOp::Mismatch => {
+                aligned.push(Some(sequence.bases[q]));
+                q += 1;
+            },
+            AlignOp::Deletion => aligned.push(None),
+            AlignOp::Insertion => q += 1,
+        }
+    }
+
+    positions.iter().map(|&pos| aligned.get(pos).cloned().unwrap_or(None)).collect()
+}
+
+/// Counts the substitutions, insertions and deletions needed to turn a into b
+/// Returns None as soon as the distance is known to be above max
+pub fn edit_distance(a: &[Base], b: &[Base], max: usize) -> Option<usize> {
+    if (a.len() as isize - b.len() as isize).abs() as usize > max {
+        return None;
+    }
+
+    // One row of the edit distance matrix at a time
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..a.len() + 1 {
+        current[0] = i;
+        for j in 1..b.len() + 1 {
+            let substitution = previous[j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = cmp::min(substitution, cmp::min(previous[j], current[j - 1]) + 1);
+        }
+        if current.iter().all(|&d| d > max) {
+            return None;
+        }
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+
+    let distance = previous[b.len()];
+    if distance <= max { Some(distance) } else { None }
+}
+
+/// Left-normalizes a variant given as reference[pos..pos + ref_allele.len()] -> alt_allele
+/// Alleles are trimmed to their shortest form, anchored on the preceding reference base when one
+/// would be empty (or the following base at the very start of the reference)
+/// A deletion of the whole reference has no base to anchor on and is left with an empty allele
+pub fn normalize(reference: &[Base], pos: usize, ref_allele: Vec<Base>, alt_allele: Vec<Base>) -> Variant {
+    let (mut pos, mut ref_allele, mut alt_allele) = (pos, ref_allele, alt_allele);
+
+    loop {
+        if ref_allele.len() > 0 && alt_allele.len() > 0 && ref_allele.last() == alt_allele.last() {
+            ref_allele.pop();
+            alt_allele.pop();
+        } else if (ref_allele.len() == 0 || alt_allele.len() == 0) && pos > 0 {
+            pos -= 1;
+            ref_allele.insert(0, reference[pos]);
+            alt_allele.insert(0, reference[pos]);
+        } else {
+            break;
+        }
+    }
+
+    while ref_allele.len() >= 2 && alt_allele.len() >= 2 && ref_allele[0] == alt_allele[0] {
+        ref_allele.remove(0);
+        alt_allele.remove(0);
+        pos += 1;
+    }
+
+    if (ref_allele.len() == 0 || alt_allele.len() == 0) && pos + ref_allele.len() < reference.len() {
+        let next = reference[pos + ref_allele.len()];
+        ref_allele.push(next);
+        alt_allele.push(next);
+    }
+
+    Variant {
+        pos: pos,
+        ref_allele: Bases { bases: ref_allele },
+        alt_allele: Bases { bases: alt_allele },
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn variant(pos: usize, ref_allele: &str, alt_allele: &str) -> Variant {
+    Variant { pos: pos, ref_allele: Bases::from_str(ref_allele), alt_allele: Bases::from_str(alt_allele) }
+}
+
+#[test]
+fn align_identical() {
+    let bases = Bases::from_str("GATACA");
+
+    let ops = global_align(&bases.bases, &bases.bases, &Scoring::new());
+    assert_eq!(ops, vec![AlignOp::Match; 6]);
+}
+
+#[test]
+fn align_with_deletion() {
+    let reference = Bases::from_str("GATTACAGATTACA");
+    let query = Bases::from_str("GATTACATTACA");
+
+    let ops = global_align(&reference.bases, &query.bases, &Scoring::new());
+    assert_eq!(ops.iter().filter(|&&op| op == AlignOp::Deletion).count(), 2);
+    assert_eq!(ops.iter().filter(|&&op| op == AlignOp::Mismatch).count(), 0);
+}
+
+#[test]
+fn finds_snps() {
+    let reference = Bases::from_str("GATTACAGATTACA");
+    let sequence = Bases::from_str("GATCACAGATTAGA");
+
+    assert_eq!(variants(&reference, &sequence), vec![variant(3, "T", "C"), variant(12, "C", "G")]);
+}
+
+#[test]
+fn aligns_long_amplicon() {
+    use bases::Base::*;
+
+    // A kilobase of pseudo-random sequence with a SNP, a deletion and an insertion
+    let mut state: u32 = 12345;
+    let reference: Vec<Base> = (0..1000).map(|_| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        [A, C, G, T][(state >> 16) as usize % 4]
+    }).collect();
+    let mut query = reference.clone();
+    query[100] = if query[100] == A { C } else { A };
+    query.remove(500);
+    query.insert(800, G);
+
+    let ops = global_align(&reference, &query, &Scoring::new());
+    assert_eq!(ops.iter().filter(|&&op| op == AlignOp::Mismatch).count(), 1);
+    assert_eq!(ops.iter().filter(|&&op| op == AlignOp::Deletion).count(), 1);
+    assert_eq!(ops.iter().filter(|&&op| op == AlignOp::Insertion).count(), 1);
+    assert_eq!(ops.len(), 1001);
+}
+
+#[test]
+fn left_normalizes_deletion() {
+    // Deleting either T of the TT run is the same deletion, anchored on the A before it
+    let reference = Bases::from_str("CCGATTACACC");
+    let sequence = Bases::from_str("CCGATACACC");
+
+    let found = variants(&reference, &sequence);
+    assert_eq!(found, vec![variant(3, "AT", "A")]);
+    assert!(!found[0].is_snp());
+}
+
+#[test]
+fn left_normalizes_insertion() {
+    let reference = Bases::from_str("CCGACACACAGG");
+    let sequence = Bases::from_str("CCGACACACACAGG");
+
+    assert_eq!(variants(&reference, &sequence), vec![variant(2, "G", "GAC")]);
+}
+
+#[test]
+fn gets_bases_at_reference_positions() {
+    use bases::Base::*;
+
+    let reference = Bases::from_str("GATTACAGATTACA");
+    let sequence = Bases::from_str("GACTACAGTACG");
+
+    // Position 2 carries a SNP, the AT at 8-9 is deleted, position 13 carries a SNP
+    let found = bases_at(&reference, &sequence, &[0, 2, 9, 13, 20]);
+    assert_eq!(found[0], Some(G));
+    assert_eq!(found[1], Some(C));
+    assert_eq!(found[2], None);
+    assert_eq!(found[3], Some(G));
+    assert_eq!(found[4], None);
+}
+
+#[test]
+fn measures_edit_distance() {
+    let a = Bases::from_str("GATTACAGATTACA");
+
+    assert_eq!(edit_distance(&a.bases, &a.bases, 0), Some(0));
+    assert_eq!(edit_distance(&a.bases, &Bases::from_str("GATCACAGATTACA").bases, 3), Some(1));
+    assert_eq!(edit_distance(&a.bases, &Bases::from_str("GATACAGATTAGCA").bases, 3), Some(2));
+    assert_eq!(edit_distance(&a.bases, &Bases::from_str("GTACAGTTCA").bases, 2), None);
+}
+
+#[test]
+fn normalizes_at_reference_start() {
+    let reference = Bases::from_str("AACGT");
+
+    // Deleting the leading A has no base before it to anchor on
+    let normalized = normalize(&reference.bases, 0, Bases::from_str("A").bases, vec!());
+    assert_eq!(normalized, variant(0, "AA", "A"));
+
+    // Nor does deleting all of it
+    let normalized = normalize(&reference.bases, 0, reference.bases.clone(), vec!());
+    assert_eq!(normalized, variant(0, "AACGT", ""));
+}
+
+#[test]
+fn normalizes_padded_variant() {
+    let reference = Bases::from_str("GGATCCC");
+
+    let normalized = normalize(&reference.bases, 1, Bases::from_str("GATC").bases, Bases::from_str("GAGC").bases);
+    assert_eq!(normalized, variant(3, "T", "G"));
+}
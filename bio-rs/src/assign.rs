@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+
+use fastq::Sequence;
+
+/// Why a read couldn't be put in the matrix
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Unassigned {
+    /// The header has no sample field
+    NoSample,
+    /// The header has no locus field
+    NoLocus,
+    /// The sample isn't in the samples file
+    UnknownSample,
+    /// The locus isn't in the loci file
+    UnknownLocus,
+}
+
+impl Unassigned {
+    pub fn as_str(&self) -> &'static str {
+        use self::Unassigned::*;
+
+        match *self {
+            NoSample => "no_sample",
+            NoLocus => "no_locus",
+            UnknownSample => "unknown_sample",
+            UnknownLocus => "unknown_locus",
+        }
+    }
+}
+
+/// Reads the sample and locus a read was sorted to from its header
+/// The sample is a sample= tag or else the index of the Illumina comment (1:N:0:<sample>), the locus
+/// a locus= tag or else the oligo name yo_deoligo appends, the last field after the comment that
+/// isn't a tag, since joiners append join= after it
+pub fn sample_and_locus(seq: &Sequence) -> (Option<String>, Option<String>) {
+    let fields: Vec<&str> = seq.header.split_whitespace().collect();
+
+    let sample = seq.tag("sample").or_else(|| fields.get(1).and_then(|comment| comment.split(':').nth(3)));
+    let locus = seq.tag("locus").or_else(|| {
+        if fields.len() > 2 {
+            fields[2..].iter().rev().find(|field| !field.contains('=')).map(|field| *field)
+        } else {
+            None
+        }
+    });
+
+    let present = |name: Option<&str>| match name {
+        Some(name) if name.len() > 0 => Some(name.to_string()),
+        _ => None,
+    };
+    (present(sample), present(locus))
+}
+
+/// Assigns reads to the listed samples and loci, tallying the ones that can't be
+pub struct Assigner {
+    samples: HashSet<String>,
+    loci: HashSet<String>,
+    /// Reads seen
+    pub total: usize,
+    /// Unassigned reads by reason and the sample or locus name at fault
+    pub unassigned: BTreeMap<(Unassigned, String), usize>,
+    /// Reads of each listed sample without a listed locus
+    pub off_target: HashMap<String, usize>,
+}
+
+impl Assigner {
+    pub fn new(samples: &[String], loci: &[String]) -> Assigner {
+        Assigner {
+            samples: samples.iter().cloned().collect(),
+            loci: loci.iter().cloned().collect(),
+            total: 0,
+            unassigned: BTreeMap::new(),
+            off_target: HashMap::new(),
+        }
+    }
+
+    /// Gets the (sample, locus) of a read, or the reason it has none
+    pub fn assign(&mut self, seq: &Sequence) -> Result<(String, String), Unassigned> {
+        self.total += 1;
+
+        let (sample, locus) = sample_and_locus(seq);
+        if let Some(ref sample) = sample {
+            let on_target = locus.as_ref().map(|locus| self.loci.contains(locus)).unwrap_or(false);
+            if self.samples.contains(sample) && !on_target {
+                *self.off_target.entry(sample.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let result = match (sample, locus) {
+            (None, _) => Err((Unassigned::NoSample, ".".to_string())),
+            (_, None) => Err((Unassigned::NoLocus, ".".to_string())),
+            (Some(sample), _) if !self.samples.contains(&sample) => Err((Unassigned::UnknownSample, sample)),
+            (_, Some(locus)) if !self.loci.contains(&locus) => Err((Unassigned::UnknownLocus, locus)),
+            (Some(sample), Some(locus)) => Ok((sample, locus)),
+        };
+
+        result.map_err(|(reason, name)| {
+            *self.unassigned.entry((reason, name)).or_insert(0) += 1;
+            reason
+        })
+    }
+
+    pub fn num_unassigned(&self) -> usize {
+        self.unassigned.values().fold(0, |sum, &count| sum + count)
+    }
+
+    pub fn unassigned_fraction(&self) -> f64 {
+        if self.total > 0 { self.num_unassigned() as f64 / self.total as f64 } else { 0.0 }
+    }
+
+    /// Writes the unassigned tally, first by reason and then by reason and name
+    pub fn write_report<W: io::Write>(&self, report: &mut W) -> io::Result<()> {
+        let mut by_reason: BTreeMap<Unassigned, usize> = BTreeMap::new();
+        for (&(reason, _), &count) in &self.unassigned {
+            *by_reason.entry(reason).or_insert(0) += count;
+        }
+
+        try!(writeln!(report, "Reads\t{}", self.total));
+        try!(writeln!(report, "Unassigned\t{}", self.num_unassigned()));
+        try!(writeln!(report, ""));
+        try!(writeln!(report, "reason\treads"));
+        for (reason, count) in &by_reason {
+            try!(writeln!(report, "{}\t{}", reason.as_str(), count));
+        }
+        try!(writeln!(report, ""));
+        try!(writeln!(report, "reason\tname\treads"));
+        for (&(reason, ref name), count) in &self.unassigned {
+            try!(writeln!(report, "{}\t{}\t{}", reason.as_str(), name, count));
+        }
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn read(header: &str) -> Sequence {
+    use bases::Bases;
+
+    Sequence { header: header.to_string(), bases: Bases::from_str("GATACA"), qual: "AAAAAA".to_string() }
+}
+
+#[test]
+fn reads_sample_and_locus() {
+    let names = |header| sample_and_locus(&read(header));
+
+    assert_eq!(names("M1:1:FC:1:1:1:1 1:N:0:S1 join=merged L1"), (Some("S1".to_string()), Some("L1".to_string())));
+    // Paired yo_deoligo appends the oligo before the joiners append their tag
+    assert_eq!(names("M1:1:FC:1:1:1:1 1:N:0:S1 L1 join=merged"), (Some("S1".to_string()), Some("L1".to_string())));
+    assert_eq!(names("M1:1:FC:1:1:1:1 1:N:0:S1 L1 locus=L2"), (Some("S1".to_string()), Some("L2".to_string())));
+    assert_eq!(names("M1:1:FC:1:1:1:1 sample=S2 L1"), (Some("S2".to_string()), Some("L1".to_string())));
+    assert_eq!(names("M1:1:FC:1:1:1:1 1:N:0:S1 join=merged"), (Some("S1".to_string()), None));
+    assert_eq!(names("M1:1:FC:1:1:1:1"), (None, None));
+}
+
+#[test]
+fn tallies_unassigned_reads() {
+    let mut assigner = Assigner::new(&["S1".to_string(), "S10".to_string()], &["L1".to_string()]);
+
+    assert_eq!(assigner.assign(&read("r 1:N:0:S10 L1")), Ok(("S10".to_string(), "L1".to_string())));
+    assert_eq!(assigner.assign(&read("r 1:N:0:S1 L1")), Ok(("S1".to_string(), "L1".to_string())));
+    // A sample whose name starts with a listed one isn't mistaken for it
+    assert_eq!(assigner.assign(&read("r 1:N:0:S100 L1")), Err(Unassigned::UnknownSample));
+    assert_eq!(assigner.assign(&read("r 1:N:0:S1 L2")), Err(Unassigned::UnknownLocus));
+    assert_eq!(assigner.assign(&read("r 1:N:0:S1")), Err(Unassigned::NoLocus));
+
+    assert_eq!(assigner.total, 5);
+    assert_eq!(assigner.num_unassigned(), 3);
+    assert_eq!(assigner.unassigned.get(&(Unassigned::UnknownSample, "S100".to_string())), Some(&1));
+    assert_eq!(assigner.off_target.get("S1"), Some(&2));
+    assert_eq!(assigner.off_target.get("S10"), None);
+}
@@ -0,0 +1,176 @@
+use std::io;
+
+use genotype::GenotypeCall;
+
+/// Read support for the first two alleles of a heterozygous call
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlleleBalance {
+    /// Reads of the first and second called allele
+    pub reads: (u32, u32),
+    /// Fraction of the two alleles' reads showing the second one
+    pub fraction: f64,
+    /// Fraction expected from the called copies of each, 0.5 for a diploid heterozygote
+    pub expected: f64,
+    /// Two-sided exact binomial test of the reads against the expected fraction
+    pub p_value: f64,
+}
+
+impl AlleleBalance {
+    /// Measures the balance of a call made from counts, None if the call is homozygous
+    pub fn of_call(counts: &[u32], call: &GenotypeCall) -> Option<AlleleBalance> {
+        if !call.is_heterozygous() {
+            return None;
+        }
+
+        // Alleles are ascending, so the first allele has at least as many reads as the second
+        let first = call.alleles[0];
+        let second = *call.alleles.iter().find(|&&a| a != first).unwrap();
+        let copies = |allele: usize| call.alleles.iter().filter(|&&a| a == allele).count() as f64;
+
+        let reads = (counts[first], counts[second]);
+        let total = reads.0 + reads.1;
+        if total == 0 {
+            return None;
+        }
+        let expected = copies(second) / (copies(first) + copies(second));
+
+        Some(AlleleBalance {
+            reads: reads,
+            fraction: reads.1 as f64 / total as f64,
+            expected: expected,
+            p_value: binomial_test(reads.1, total, expected),
+        })
+    }
+
+    /// Whether the fraction is more than window away from the expected fraction
+    pub fn is_skewed(&self, window: f64) -> bool {
+        (self.fraction - self.expected).abs() > window
+    }
+}
+
+/// Two-sided exact binomial test of k successes in n trials with success probability p
+/// Sums the probability of every outcome no more likely than the one observed
+pub fn binomial_test(k: u32, n: u32, p: f64) -> f64 {
+    let mut ln_factorials = vec![0.0];
+    for i in 1..(n as usize + 1) {
+        let previous = ln_factorials[i - 1];
+        ln_factorials.push(previous + (i as f64).ln());
+    }
+    let ln_pmf = |i: u32| {
+        ln_factorials[n as usize] - ln_factorials[i as usize] - ln_factorials[(n - i) as usize]
+            + (i as f64) * p.ln() + ((n - i) as f64) * (1.0 - p).ln()
+    };
+
+    // Allow for rounding when comparing outcomes as likely as the observed one
+    let observed = ln_pmf(k) + 1e-7;
+    let p_value = (0..(n + 1))
+        .map(|i| ln_pmf(i))
+        .filter(|&ln_p| ln_p <= observed)
+        .fold(0.0, |sum, ln_p| sum + ln_p.exp());
+    p_value.min(1.0)
+}
+
+/// Allele balance of a locus's heterozygous calls across samples
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocusBalance {
+    pub locus: String,
+    pub heterozygotes: usize,
+    /// Heterozygotes outside the window
+    pub skewed: usize,
+    pub mean_fraction: f64,
+    /// Binomial test of every heterozygote's reads pooled, low when one allele is under-amplified throughout
+    pub pooled_p_value: f64,
+}
+
+/// Column labels of the allele balance summary
+pub const LOCUS_COLUMNS: &'static str = "locus\theterozygotes\tskewed\tskewed_fraction\tmean_fraction\tpooled_p_value";
+
+impl LocusBalance {
+    pub fn new(locus: &str, balances: &[AlleleBalance], window: f64) -> LocusBalance {
+        let fractions = balances.iter().fold(0.0, |sum, balance| sum + balance.fraction);
+        let first = balances.iter().fold(0, |sum, balance| sum + balance.reads.0);
+        let second = balances.iter().fold(0, |sum, balance| sum + balance.reads.1);
+        let expected = balances.iter().fold(0.0, |sum, balance| sum + balance.expected);
+
+        LocusBalance {
+            locus: locus.to_string(),
+            heterozygotes: balances.len(),
+            skewed: balances.iter().filter(|balance| balance.is_skewed(window)).count(),
+            mean_fraction: if balances.len() > 0 { fractions / balances.len() as f64 } else { 0.0 },
+            pooled_p_value: if balances.len() > 0 {
+                binomial_test(second, first + second, expected / balances.len() as f64)
+            } else {
+                1.0
+            },
+        }
+    }
+
+    pub fn write<W: io::Write>(&self, summary: &mut W) -> io::Result<()> {
+        if self.heterozygotes == 0 {
+            return writeln!(summary, "{}\t0\t0\tNA\tNA\tNA", self.locus);
+        }
+        writeln!(
+            summary,
+            "{}\t{}\t{}\t{:.4}\t{:.4}\t{:.3e}",
+            self.locus,
+            self.heterozygotes,
+            self.skewed,
+            self.skewed as f64 / self.heterozygotes as f64,
+            self.mean_fraction,
+            self.pooled_p_value,
+        )
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn call(alleles: Vec<usize>) -> GenotypeCall {
+    GenotypeCall { alleles: alleles, quality: 99.0, log_likelihood: 0.0 }
+}
+
+#[test]
+fn test_binomial_test() {
+    // Symmetric, so both tails count
+    assert!((binomial_test(2, 10, 0.5) - 0.109375).abs() < 1e-9);
+    assert!((binomial_test(8, 10, 0.5) - 0.109375).abs() < 1e-9);
+    assert!((binomial_test(5, 10, 0.5) - 1.0).abs() < 1e-9);
+    assert!(binomial_test(10, 1000, 0.5) < 1e-200);
+}
+
+#[test]
+fn test_allele_balance() {
+    let balance = AlleleBalance::of_call(&[80, 20, 3], &call(vec![0, 1])).unwrap();
+    assert_eq!(balance.reads, (80, 20));
+    assert_eq!(balance.fraction, 0.2);
+    assert_eq!(balance.expected, 0.5);
+    assert!(balance.p_value < 0.001);
+    assert!(balance.is_skewed(0.25));
+    assert!(!balance.is_skewed(0.35));
+
+    assert_eq!(AlleleBalance::of_call(&[80, 20], &call(vec![0, 0])), None);
+}
+
+#[test]
+fn test_polyploid_allele_balance() {
+    // Three copies of the first allele to one of the second
+    let balance = AlleleBalance::of_call(&[150, 50], &call(vec![0, 0, 0, 1])).unwrap();
+    assert_eq!(balance.expected, 0.25);
+    assert!(!balance.is_skewed(0.1));
+    assert!(balance.p_value > 0.5);
+}
+
+#[test]
+fn test_locus_balance() {
+    let balances = vec![
+        AlleleBalance::of_call(&[70, 30], &call(vec![0, 1])).unwrap(),
+        AlleleBalance::of_call(&[90, 10], &call(vec![0, 1])).unwrap(),
+    ];
+
+    let summary = LocusBalance::new("locus1", &balances, 0.3);
+    assert_eq!(summary.heterozygotes, 2);
+    assert_eq!(summary.skewed, 1);
+    assert_eq!(summary.mean_fraction, 0.2);
+    assert!(summary.pooled_p_value < 1e-10);
+}
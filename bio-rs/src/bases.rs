@@ -1,6 +1,6 @@
 use std::ops::Add;
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Base {
     A,
     T,
@@ -35,7 +35,7 @@ impl Base {
     }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Bases {
     pub bases: Vec<Base>,
 }
@@ -63,6 +63,11 @@ impl Bases {
         Bases { bases: bases }
     }
 
+    /// Whether bases holds only AaTtGgCcNn, so from_str can read it
+    pub fn is_valid(bases: &str) -> bool {
+        bases.chars().all(|b| "AaTtGgCcNn".contains(b))
+    }
+
     /// Gets the bases sequence as a String
     pub fn as_string(&self) -> String {
         self.bases.iter().map(|b| b.to_char()).collect()
@@ -95,6 +100,16 @@ impl Bases {
         }
     }
 
+    /// Checks whether the sequence begins with barcode, allowing up to diffs_allowed mismatches
+    pub fn starts_with(&self, barcode: &Bases, diffs_allowed: u16) -> bool {
+        if self.bases.len() < barcode.bases.len() {
+            return false;
+        }
+
+        let diff_count = self.bases.iter().zip(barcode.bases.iter()).filter(|&(a, b)| a != b).count();
+        diff_count <= diffs_allowed as usize
+    }
+
     /// Tries to debarcode the sequence
     /// On success returns debarcoded sequence
     /// On failure returns original sequence
@@ -158,6 +173,8 @@ fn bases_from_str() {
     let bases = Bases::from_str("AaTtGgCcNn");
     let expected = Bases { bases: vec![A, A, T, T, G, G, C, C, N, N] };
     assert_eq!(bases, expected);
+    assert!(Bases::is_valid("AaTtGgCcNn"));
+    assert!(!Bases::is_valid("GATXCA"));
 }
 
 #[test]
@@ -227,6 +244,16 @@ fn debarcode_fails_properly() {
     assert_eq!(bases, Bases::from_str("ATTGGATACACTAT"));
 }
 
+#[test]
+fn starts_with_barcode() {
+    let bases = Bases::from_str("ATAGGATACA");
+
+    assert!(bases.starts_with(&Bases::from_str("ATAG"), 0));
+    assert!(!bases.starts_with(&Bases::from_str("ATTG"), 0));
+    assert!(bases.starts_with(&Bases::from_str("ATTG"), 1));
+    assert!(!bases.starts_with(&Bases::from_str("ATAGGATACAAA"), 2));
+}
+
 #[test]
 fn add_bases_ref() {
     let a = Bases::from_str("ATG");
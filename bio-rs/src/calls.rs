@@ -0,0 +1,163 @@
+use std::io;
+
+use bases::Bases;
+
+/// Column labels of a genotype calls table
+pub const COLUMNS: &'static str = "locus\tsample\tploidy\tdepth\tgenotype\tgq\talleles\thaplotype_counts\tallele_balance\tbalance_p_value\tbalance_flag";
+
+/// A sample's genotype call at a locus
+#[derive(Clone, Debug, PartialEq)]
+pub struct Call {
+    pub locus: String,
+    pub sample: String,
+    pub ploidy: usize,
+    /// Reads assigned to the sample at the locus
+    pub depth: usize,
+    /// Allele numbers counting from 1 and separated by '/', e.g. "1/2"
+    pub genotype: String,
+    pub quality: f64,
+    /// The allele sequence of each copy
+    pub alleles: Vec<Bases>,
+    /// Read counts of the haplotypes considered, most reads first
+    pub haplotype_counts: Vec<u32>,
+    /// Allele balance and its binomial test p-value, for heterozygous calls
+    pub balance: Option<(f64, f64)>,
+    /// Whether a heterozygous call's allele balance is outside the allowed window
+    pub skewed: bool,
+}
+
+/// Writes the column labels of a genotype calls table
+pub fn write_header<W: io::Write>(calls: &mut W) -> io::Result<()> {
+    writeln!(calls, "{}", COLUMNS)
+}
+
+pub fn write_call<W: io::Write>(calls: &mut W, call: &Call) -> io::Result<()> {
+    let alleles: Vec<String> = call.alleles.iter().map(|allele| allele.as_string()).collect();
+    let counts: Vec<String> = call.haplotype_counts.iter().map(|count| count.to_string()).collect();
+    let balance = match call.balance {
+        Some((fraction, p_value)) => {
+            format!("{:.4}\t{:.3e}\t{}", fraction, p_value, if call.skewed { "skewed" } else { "pass" })
+        },
+        None => "NA\tNA\t.".to_string(),
+    };
+    writeln!(
+        calls,
+        "{}\t{}\t{}\t{}\t{}\t{:.0}\t{}\t{}\t{}",
+        call.locus,
+        call.sample,
+        call.ploidy,
+        call.depth,
+        call.genotype,
+        call.quality,
+        alleles.connect("/"),
+        counts.connect(","),
+        balance,
+    )
+}
+
+/// Reads every call of a genotype calls table
+/// Lines starting with '#' and the column labels are skipped
+pub fn read_calls<R: io::Read>(calls: &mut io::BufReader<R>) -> io::Result<Vec<Call>> {
+    use std::io::BufRead;
+
+    let mut parsed = vec!();
+    for (n, line) in calls.lines().enumerate() {
+        let line = try!(line);
+        if line.len() == 0 || line.starts_with("#") || line == COLUMNS {
+            continue;
+        }
+
+        match parse_call(&line) {
+            Some(call) => parsed.push(call),
+            None => {
+                let message = format!("Malformed genotype calls line {}: {}", n + 1, line);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            },
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_call(line: &str) -> Option<Call> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 11 {
+        return None;
+    }
+
+    let mut counts = vec!();
+    for count in fields[7].split(',') {
+        match count.parse() {
+            Ok(count) => counts.push(count),
+            Err(_) => return None,
+        }
+    }
+
+    if !fields[6].split('/').all(|allele| Bases::is_valid(allele)) {
+        return None;
+    }
+
+    let balance = match (fields[8].parse(), fields[9].parse()) {
+        (Ok(fraction), Ok(p_value)) => Some((fraction, p_value)),
+        _ if fields[8] == "NA" => None,
+        _ => return None,
+    };
+
+    Some(Call {
+        locus: fields[0].to_string(),
+        sample: fields[1].to_string(),
+        ploidy: match fields[2].parse() { Ok(ploidy) => ploidy, Err(_) => return None },
+        depth: match fields[3].parse() { Ok(depth) => depth, Err(_) => return None },
+        genotype: fields[4].to_string(),
+        quality: match fields[5].parse() { Ok(quality) => quality, Err(_) => return None },
+        alleles: fields[6].split('/').map(|allele| Bases::from_str(allele)).collect(),
+        haplotype_counts: counts,
+        balance: balance,
+        skewed: fields[10] == "skewed",
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+/// A sample's call with the given allele sequences, for tests of modules that read calls
+#[cfg(test)]
+pub fn test_call(sample: &str, locus: &str, alleles: &[&str]) -> Call {
+    Call {
+        locus: locus.to_string(),
+        sample: sample.to_string(),
+        ploidy: alleles.len(),
+        depth: 100,
+        genotype: "1/2".to_string(),
+        quality: 99.0,
+        alleles: alleles.iter().map(|allele| Bases::from_str(allele)).collect(),
+        haplotype_counts: vec![50, 50],
+        balance: None,
+        skewed: false,
+    }
+}
+
+#[test]
+fn test_calls_round_trip() {
+    let call = Call {
+        locus: "locus1".to_string(),
+        sample: "s1".to_string(),
+        ploidy: 2,
+        depth: 100,
+        genotype: "1/2".to_string(),
+        quality: 99.0,
+        alleles: vec![Bases::from_str("GATACA"), Bases::from_str("GATTCA")],
+        haplotype_counts: vec![52, 45, 3],
+        balance: Some((0.4639, 0.611)),
+        skewed: false,
+    };
+
+    let mut table: Vec<u8> = b"# call_consensus min_depth=42\n".to_vec();
+    write_header(&mut table).unwrap();
+    write_call(&mut table, &call).unwrap();
+
+    let read = read_calls(&mut io::BufReader::new(&table[..])).unwrap();
+    assert_eq!(read, vec![call]);
+
+    let table = b"locus1\ts1\t2\t100\t1/2\t99\tGATACA/GAT.CA\t52,45\tNA\tNA\t.\n";
+    assert!(read_calls(&mut io::BufReader::new(&table[..])).is_err());
+}
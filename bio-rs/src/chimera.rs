@@ -0,0 +1,184 @@
+use std::cmp;
+
+use align::{self, AlignOp, Scoring};
+use bases::{Base, Bases};
+
+/// A haplotype explained as the start of one more abundant haplotype joined to the end of another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Chimera {
+    /// Index of the parent the chimera starts with
+    pub left_parent: usize,
+    /// Index of the parent the chimera ends with
+    pub right_parent: usize,
+    /// Bases of the chimera taken from the left parent
+    pub breakpoint: usize,
+    /// Edits between the chimera and the crossover of its parents
+    pub diffs: usize,
+    /// Edits between the chimera and the closer of its parents
+    pub parent_diffs: usize,
+}
+
+/// Checks each haplotype for being a two-parent crossover of more abundant haplotypes, in the
+/// style of UCHIME's de novo mode
+/// Parents need abundance_skew times the reads of the haplotype and must not be chimeras
+/// themselves. A haplotype is a chimera when a crossover is within max_diffs edits of it and
+/// closer than either parent alone
+pub fn find_chimeras(haplotypes: &[(Bases, u32)], abundance_skew: f64, max_diffs: usize) -> Vec<Option<Chimera>> {
+    // Visit haplotypes most abundant first, keeping the given order between ties
+    let mut order: Vec<usize> = (0..haplotypes.len()).collect();
+    order.sort_by(|&a, &b| haplotypes[b].1.cmp(&haplotypes[a].1));
+
+    let mut chimeras: Vec<Option<Chimera>> = vec![None; haplotypes.len()];
+    let mut parents: Vec<usize> = vec!();
+
+    for &q in &order {
+        let (ref query, count) = haplotypes[q];
+        let candidates: Vec<usize> = parents.iter().cloned()
+            .filter(|&p| haplotypes[p].1 as f64 >= abundance_skew * count as f64)
+            .collect();
+
+        let mut best: Option<Chimera> = None;
+        for &left in &candidates {
+            for &right in &candidates {
+                if left == right {
+                    continue;
+                }
+                let (breakpoint, diffs, parent_diffs) =
+                    crossover(&query.bases, &haplotypes[left].0.bases, &haplotypes[right].0.bases);
+                if diffs <= max_diffs && diffs < parent_diffs && best.map_or(true, |b| diffs < b.diffs) {
+                    best = Some(Chimera {
+                        left_parent: left,
+                        right_parent: right,
+                        breakpoint: breakpoint,
+                        diffs: diffs,
+                        parent_diffs: parent_diffs,
+                    });
+                }
+            }
+        }
+
+        if best.is_some() {
+            chimeras[q] = best;
+        } else {
+            parents.push(q);
+        }
+    }
+
+    chimeras
+}
+
+/// Finds the crossover of left and right closest to query
+/// Returns the bases of query taken from left, the edits to the crossover and the edits to the
+/// closer parent
+fn crossover(query: &[Base], left: &[Base], right: &[Base]) -> (usize, usize, usize) {
+    let n = query.len();
+
+    // prefix[i][j] is the edit distance of query[..i] to left[..j]
+    let mut prefix = vec![vec![0; left.len() + 1]; n + 1];
+    for i in 0..n + 1 {
+        for j in 0..left.len() + 1 {
+            prefix[i][j] = if i == 0 || j == 0 {
+                i + j
+            } else {
+                let substitution = prefix[i - 1][j - 1] + if query[i - 1] == left[j - 1] { 0 } else { 1 };
+                cmp::min(substitution, cmp::min(prefix[i - 1][j], prefix[i][j - 1]) + 1)
+            };
+        }
+    }
+
+    // suffix[i][k] is the edit distance of query[i..] to right[k..]
+    let m = right.len();
+    let mut suffix = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n + 1).rev() {
+        for k in (0..m + 1).rev() {
+            suffix[i][k] = if i == n || k == m {
+                (n - i) + (m - k)
+            } else {
+                let substitution = suffix[i + 1][k + 1] + if query[i] == right[k] { 0 } else { 1 };
+                cmp::min(substitution, cmp::min(suffix[i + 1][k], suffix[i][k + 1]) + 1)
+            };
+        }
+    }
+
+    // The crossover switches parents at the same place in both, so line the parents up first
+    let right_at = aligned_positions(left, right);
+
+    let mut best = (0, usize::max_value());
+    for i in 0..n + 1 {
+        for j in 0..left.len() + 1 {
+            let diffs = prefix[i][j] + suffix[i][right_at[j]];
+            if diffs < best.1 {
+                best = (i, diffs);
+            }
+        }
+    }
+
+    let parent_diffs = cmp::min(prefix[n][left.len()], suffix[0][0]);
+    (best.0, best.1, parent_diffs)
+}
+
+/// Maps each position in a to the position in b that an alignment of the two lines it up with
+fn aligned_positions(a: &[Base], b: &[Base]) -> Vec<usize> {
+    let mut positions = vec![0];
+    let mut k = 0;
+    for op in align::global_align(a, b, &Scoring::new()) {
+        match op {
+            AlignOp::Match | AlignOp::Mismatch => {
+                k += 1;
+                positions.push(k);
+            },
+            AlignOp::Deletion => positions.push(k),
+            AlignOp::Insertion => k += 1,
+        }
+    }
+    positions
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn finds_crossover() {
+    let haplotypes = vec![
+        (Bases::from_str("GATTACAGATTACAGATTACA"), 100),
+        (Bases::from_str("GATCACAGATTACAGATTGCA"), 80),
+        // The start of the first allele then the end of the second
+        (Bases::from_str("GATTACAGATTACAGATTGCA"), 10),
+    ];
+
+    let chimeras = find_chimeras(&haplotypes, 2.0, 0);
+    assert_eq!(chimeras[0], None);
+    assert_eq!(chimeras[1], None);
+
+    let chimera = chimeras[2].unwrap();
+    assert_eq!((chimera.left_parent, chimera.right_parent), (0, 1));
+    assert_eq!((chimera.diffs, chimera.parent_diffs), (0, 1));
+    assert!(chimera.breakpoint >= 4 && chimera.breakpoint <= 18);
+}
+
+#[test]
+fn finds_crossover_across_indel() {
+    let haplotypes = vec![
+        (Bases::from_str("CCGTTACAGATTACAGATTACA"), 100),
+        (Bases::from_str("GATTACAGATTACAGATTAAAGCA"), 80),
+        (Bases::from_str("CCGTTACAGATTACAGATTAAAGCA"), 10),
+    ];
+
+    let chimera = find_chimeras(&haplotypes, 2.0, 0)[2].unwrap();
+    assert_eq!((chimera.left_parent, chimera.right_parent), (0, 1));
+    assert_eq!(chimera.diffs, 0);
+}
+
+#[test]
+fn skips_abundant_haplotypes() {
+    let haplotypes = vec![
+        (Bases::from_str("GATTACAGATTACAGATTACA"), 100),
+        (Bases::from_str("GATCACAGATTACAGATTGCA"), 80),
+        // Too abundant to be a chimera of the other two
+        (Bases::from_str("GATTACAGATTACAGATTGCA"), 60),
+        // A new allele that no crossover explains
+        (Bases::from_str("GATTACAGACTACAGATTACA"), 10),
+    ];
+
+    assert_eq!(find_chimeras(&haplotypes, 2.0, 0), vec![None; 4]);
+}
@@ -0,0 +1,23 @@
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
+
+use getopts::Matches;
+
+/// Prints message to stderr and exits, for errors a command line tool can't go on from
+pub fn fail<T>(message: &str) -> T {
+    let _ = writeln!(io::stderr(), "{}", message);
+    process::exit(1);
+}
+
+/// Parses an option's value, falling back to default when it isn't given
+pub fn parse_opt<T: FromStr>(matches: &Matches, name: &str, default: T) -> T {
+    parse_opt_maybe(matches, name).unwrap_or(default)
+}
+
+/// Parses an option's value if it was given
+pub fn parse_opt_maybe<T: FromStr>(matches: &Matches, name: &str) -> Option<T> {
+    matches.opt_str(name).map(|value| {
+        value.parse().ok().unwrap_or_else(|| fail(&format!("Invalid value for --{}: {}", name, value)))
+    })
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use calls::Call;
+
+/// How often two samples' genotypes agree at the loci called in both
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Concordance {
+    pub shared_loci: usize,
+    pub concordant: usize,
+}
+
+impl Concordance {
+    /// Fraction of shared loci with the same genotype, None without shared loci
+    pub fn fraction(&self) -> Option<f64> {
+        if self.shared_loci > 0 { Some(self.concordant as f64 / self.shared_loci as f64) } else { None }
+    }
+
+    pub fn discordance(&self) -> Option<f64> {
+        self.fraction().map(|fraction| 1.0 - fraction)
+    }
+}
+
+/// Every sample's genotypes, as sorted allele sequences per locus
+pub struct GenotypeTable {
+    /// Samples in the order they first appear
+    pub samples: Vec<String>,
+    genotypes: HashMap<String, HashMap<String, Vec<String>>>,
+}
+
+impl GenotypeTable {
+    pub fn new(calls: &[Call]) -> GenotypeTable {
+        let mut samples: Vec<String> = vec!();
+        let mut genotypes: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+        for call in calls {
+            if !samples.contains(&call.sample) {
+                samples.push(call.sample.clone());
+            }
+            let mut alleles: Vec<String> = call.alleles.iter().map(|allele| allele.as_string()).collect();
+            alleles.sort();
+            genotypes.entry(call.sample.clone()).or_insert(HashMap::new()).insert(call.locus.clone(), alleles);
+        }
+
+        GenotypeTable {
+            samples: samples,
+            genotypes: genotypes,
+        }
+    }
+
+    /// Gets a sample's sorted alleles at a locus, None if it wasn't called there
+    pub fn get(&self, sample: &str, locus: &str) -> Option<&[String]> {
+        self.genotypes.get(sample).and_then(|loci| loci.get(locus)).map(|alleles| &alleles[..])
+    }
+
+    /// Every locus called in any sample, sorted
+    pub fn loci(&self) -> Vec<String> {
+        let mut loci: Vec<String> = self.genotypes.values().flat_map(|loci| loci.keys().cloned()).collect();
+        loci.sort();
+        loci.dedup();
+        loci
+    }
+
+    /// Compares two samples at the loci called in both
+    pub fn compare(&self, a: &str, b: &str) -> Concordance {
+        let mut concordance = Concordance { shared_loci: 0, concordant: 0 };
+        if let (Some(a), Some(b)) = (self.genotypes.get(a), self.genotypes.get(b)) {
+            for (locus, a_alleles) in a {
+                if let Some(b_alleles) = b.get(locus) {
+                    concordance.shared_loci += 1;
+                    if a_alleles == b_alleles {
+                        concordance.concordant += 1;
+                    }
+                }
+            }
+        }
+        concordance
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_concordance() {
+    use calls::test_call;
+
+    let table = GenotypeTable::new(&[
+        test_call("S1", "L1", &["GATT", "GACA"]),
+        test_call("S1", "L2", &["GATT", "GATT"]),
+        test_call("S1", "L3", &["GATT", "GATT"]),
+        // Allele order doesn't matter
+        test_call("S2", "L1", &["GACA", "GATT"]),
+        test_call("S2", "L2", &["GATT", "GACA"]),
+        test_call("S3", "L4", &["GATT", "GATT"]),
+    ]);
+
+    let concordance = table.compare("S1", "S2");
+    assert_eq!(concordance, Concordance { shared_loci: 2, concordant: 1 });
+    assert_eq!(concordance.discordance(), Some(0.5));
+    assert_eq!(table.compare("S1", "S3").fraction(), None);
+    assert_eq!(table.get("S2", "L2"), Some(&["GACA".to_string(), "GATT".to_string()][..]));
+    assert_eq!(table.loci(), vec!["L1".to_string(), "L2".to_string(), "L3".to_string(), "L4".to_string()]);
+}
@@ -0,0 +1,159 @@
+use std::io;
+
+use bases::Bases;
+
+/// Column labels of a consensus table
+pub const COLUMNS: &'static str = "sample\tlocus\trank\tsequence\treads\tfraction\tstatus";
+
+/// What became of a haplotype
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// One of the sample's called alleles
+    Called,
+    /// Seen in a sample that was called, but not called as an allele
+    Filtered,
+    /// Seen in a sample with too few reads at the locus to call
+    BelowDepth,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        use self::Status::*;
+
+        match *self {
+            Called => "called",
+            Filtered => "filtered",
+            BelowDepth => "below_depth",
+        }
+    }
+
+    pub fn from_str(status: &str) -> Option<Status> {
+        use self::Status::*;
+
+        match status {
+            "called" => Some(Called),
+            "filtered" => Some(Filtered),
+            "below_depth" => Some(BelowDepth),
+            _ => None,
+        }
+    }
+}
+
+/// One haplotype seen in a sample at a locus
+#[derive(Clone, Debug, PartialEq)]
+pub struct Haplotype {
+    pub sample: String,
+    pub locus: String,
+    /// 1 for the haplotype with the most reads in the sample at the locus
+    pub rank: usize,
+    pub sequence: Bases,
+    pub reads: u32,
+    /// Fraction of the sample's reads at the locus
+    pub fraction: f64,
+    pub status: Status,
+}
+
+/// Writes the column labels of a consensus table
+pub fn write_header<W: io::Write>(consensus: &mut W) -> io::Result<()> {
+    writeln!(consensus, "{}", COLUMNS)
+}
+
+pub fn write_haplotype<W: io::Write>(consensus: &mut W, haplotype: &Haplotype) -> io::Result<()> {
+    writeln!(
+        consensus,
+        "{}\t{}\t{}\t{}\t{}\t{:.4}\t{}",
+        haplotype.sample,
+        haplotype.locus,
+        haplotype.rank,
+        haplotype.sequence.as_string(),
+        haplotype.reads,
+        haplotype.fraction,
+        haplotype.status.as_str(),
+    )
+}
+
+/// Reads every haplotype of a consensus table
+/// Lines starting with '#' and the column labels are skipped
+pub fn read_consensus<R: io::Read>(consensus: &mut io::BufReader<R>) -> io::Result<Vec<Haplotype>> {
+    use std::io::BufRead;
+
+    let mut haplotypes = vec!();
+    for (n, line) in consensus.lines().enumerate() {
+        let line = try!(line);
+        if line.len() == 0 || line.starts_with("#") || line == COLUMNS {
+            continue;
+        }
+
+        match parse_haplotype(&line) {
+            Some(haplotype) => haplotypes.push(haplotype),
+            None => {
+                let message = format!("Malformed consensus table line {}: {}", n + 1, line);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            },
+        }
+    }
+    Ok(haplotypes)
+}
+
+fn parse_haplotype(line: &str) -> Option<Haplotype> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 7 {
+        return None;
+    }
+
+    Some(Haplotype {
+        sample: fields[0].to_string(),
+        locus: fields[1].to_string(),
+        rank: match fields[2].parse() { Ok(rank) => rank, Err(_) => return None },
+        sequence: if Bases::is_valid(fields[3]) { Bases::from_str(fields[3]) } else { return None },
+        reads: match fields[4].parse() { Ok(reads) => reads, Err(_) => return None },
+        fraction: match fields[5].parse() { Ok(fraction) => fraction, Err(_) => return None },
+        status: match Status::from_str(fields[6]) { Some(status) => status, None => return None },
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_consensus_round_trip() {
+    let haplotypes = vec![
+        Haplotype {
+            sample: "s1".to_string(),
+            locus: "locus1".to_string(),
+            rank: 1,
+            sequence: Bases::from_str("GATACA"),
+            reads: 75,
+            fraction: 0.75,
+            status: Status::Called,
+        },
+        Haplotype {
+            sample: "s1".to_string(),
+            locus: "locus1".to_string(),
+            rank: 2,
+            sequence: Bases::from_str("GATTCA"),
+            reads: 25,
+            fraction: 0.25,
+            status: Status::Filtered,
+        },
+    ];
+
+    let mut table: Vec<u8> = b"# call_consensus min_depth=42\n".to_vec();
+    write_header(&mut table).unwrap();
+    for haplotype in &haplotypes {
+        write_haplotype(&mut table, haplotype).unwrap();
+    }
+
+    let read = read_consensus(&mut io::BufReader::new(&table[..])).unwrap();
+    assert_eq!(read, haplotypes);
+}
+
+#[test]
+fn test_read_malformed_consensus() {
+    let table = b"sample\tlocus\trank\tsequence\treads\tfraction\tstatus\ns1\tlocus1\t1\tGATACA\t75\n";
+
+    assert!(read_consensus(&mut io::BufReader::new(&table[..])).is_err());
+
+    let table = b"s1\tlocus1\t1\tGAT-CA\t75\t0.75\tcalled\n";
+    assert!(read_consensus(&mut io::BufReader::new(&table[..])).is_err());
+}
@@ -0,0 +1,268 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use bases::Bases;
+use consensus::{Haplotype, Status};
+
+/// A well of a plate, e.g. plate P1 well B07
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Well {
+    pub plate: String,
+    /// 0-based row, A being 0
+    pub row: usize,
+    /// 1-based column
+    pub column: usize,
+}
+
+impl Well {
+    /// Parses a well name of a row letter and a column number, like "A1" or "H12"
+    pub fn parse(plate: &str, well: &str) -> Option<Well> {
+        let well = well.trim();
+        let row = match well.chars().next() {
+            Some(row) if row >= 'A' && row <= 'Z' => row as usize - 'A' as usize,
+            Some(row) if row >= 'a' && row <= 'z' => row as usize - 'a' as usize,
+            _ => return None,
+        };
+        well[1..].parse().ok().map(|column| Well { plate: plate.to_string(), row: row, column: column })
+    }
+
+    /// Whether other is one of the up to eight wells around this one
+    pub fn is_neighbour(&self, other: &Well) -> bool {
+        let apart = |a: usize, b: usize| if a > b { a - b } else { b - a };
+        self.plate == other.plate &&
+            apart(self.row, other.row) <= 1 &&
+            apart(self.column, other.column) <= 1 &&
+            *self != *other
+    }
+}
+
+/// Reads the wells of samples from a yo_deoligo oligo table of
+/// "<forward><TAB><reverse><TAB><sample>[<TAB><well>[<TAB><plate>]]" lines
+/// Samples without a well column are left out
+pub fn read_wells<R: io::Read>(oligos: &mut io::BufReader<R>) -> io::Result<HashMap<String, Well>> {
+    use std::io::BufRead;
+
+    let mut wells = HashMap::new();
+    for (n, line) in oligos.lines().enumerate() {
+        let line = try!(line);
+        let fields: Vec<&str> = line.trim_right_matches('\r').split('\t').collect();
+        if line.len() == 0 || fields.len() < 4 || fields[3].len() == 0 {
+            continue;
+        }
+
+        match Well::parse(fields.get(4).map(|plate| *plate).unwrap_or(""), fields[3]) {
+            Some(well) => { wells.insert(fields[2].to_string(), well); },
+            None => {
+                let message = format!("Oligos line {} has an unreadable well: {}", n + 1, line);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            },
+        }
+    }
+    Ok(wells)
+}
+
+/// How much of a sample's reads another sample could have leaked
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceEstimate {
+    pub source: String,
+    /// Estimated fraction of the sample's reads that came from the source
+    pub fraction: f64,
+    /// Loci where the source has an allele the sample doesn't
+    pub informative_loci: usize,
+}
+
+/// A sample's haplotypes at one locus
+struct SampleLocus {
+    alleles: Vec<Bases>,
+    reads: Vec<(Bases, u32)>,
+    depth: u32,
+}
+
+/// Haplotype read counts of every sample at every locus, for telling which reads belong to whom
+pub struct HaplotypeMatrix {
+    /// Samples in the order they first appear
+    pub samples: Vec<String>,
+    loci: BTreeMap<String, HashMap<String, SampleLocus>>,
+}
+
+impl HaplotypeMatrix {
+    pub fn new(haplotypes: &[Haplotype]) -> HaplotypeMatrix {
+        let mut samples: Vec<String> = vec!();
+        let mut loci: BTreeMap<String, HashMap<String, SampleLocus>> = BTreeMap::new();
+        for haplotype in haplotypes {
+            if !samples.contains(&haplotype.sample) {
+                samples.push(haplotype.sample.clone());
+            }
+            let entry = loci.entry(haplotype.locus.clone()).or_insert(HashMap::new())
+                .entry(haplotype.sample.clone())
+                .or_insert(SampleLocus { alleles: vec!(), reads: vec!(), depth: 0 });
+            if haplotype.status == Status::Called {
+                entry.alleles.push(haplotype.sequence.clone());
+            }
+            entry.reads.push((haplotype.sequence.clone(), haplotype.reads));
+            entry.depth += haplotype.reads;
+        }
+
+        HaplotypeMatrix {
+            samples: samples,
+            loci: loci,
+        }
+    }
+
+    /// Loci where the sample was called, with the sample's haplotypes there
+    fn called<'a>(&'a self, sample: &str) -> Vec<(&'a HashMap<String, SampleLocus>, &'a SampleLocus)> {
+        self.loci.values()
+            .filter_map(|samples| samples.get(sample).map(|own| (samples, own)))
+            .filter(|&(_, own)| own.alleles.len() > 0)
+            .collect()
+    }
+
+    /// Reads of the sample at loci where it was called
+    pub fn depth(&self, sample: &str) -> u32 {
+        self.called(sample).iter().fold(0, |sum, &(_, own)| sum + own.depth)
+    }
+
+    /// Loci where the sample was called
+    pub fn num_called(&self, sample: &str) -> usize {
+        self.called(sample).len()
+    }
+
+    /// Fraction of a sample's reads, at loci where it was called, showing an allele it doesn't have
+    /// but one of the sources was called with
+    pub fn foreign_fraction(&self, sample: &str, sources: &[&str]) -> f64 {
+        let (mut foreign, mut depth) = (0, 0);
+        for (samples, own) in self.called(sample) {
+            depth += own.depth;
+            for &(ref haplotype, reads) in &own.reads {
+                if own.alleles.contains(haplotype) {
+                    continue;
+                }
+                let carried = sources.iter()
+                    .filter(|&&source| source != sample)
+                    .filter_map(|&source| samples.get(source))
+                    .any(|other| other.alleles.contains(haplotype));
+                if carried {
+                    foreign += reads;
+                }
+            }
+        }
+        if depth > 0 { foreign as f64 / depth as f64 } else { 0.0 }
+    }
+
+    /// Estimates the fraction of a sample's reads that leaked in from a source
+    /// At each locus where the source has alleles the sample lacks, a leak of c shows those alleles
+    /// in c times the share of the source's alleles they make up of the sample's reads
+    pub fn source_estimate(&self, sample: &str, source: &str) -> SourceEstimate {
+        let (mut foreign, mut expected, mut informative) = (0.0, 0.0, 0);
+        for (samples, own) in self.called(sample) {
+            let other = match samples.get(source) {
+                Some(other) if other.alleles.len() > 0 => other,
+                _ => continue,
+            };
+            let lacked: Vec<&Bases> = other.alleles.iter().filter(|allele| !own.alleles.contains(allele)).collect();
+            if lacked.len() == 0 {
+                continue;
+            }
+
+            informative += 1;
+            expected += own.depth as f64 * lacked.len() as f64 / other.alleles.len() as f64;
+            foreign += own.reads.iter()
+                .filter(|&&(ref haplotype, _)| lacked.contains(&haplotype))
+                .fold(0.0, |sum, &(_, reads)| sum + reads as f64);
+        }
+
+        SourceEstimate {
+            source: source.to_string(),
+            fraction: if expected > 0.0 { foreign / expected } else { 0.0 },
+            informative_loci: informative,
+        }
+    }
+
+    /// Estimates every other sample as a source, most likely first, leaving out ones with no evidence
+    pub fn likely_sources(&self, sample: &str) -> Vec<SourceEstimate> {
+        let mut sources: Vec<SourceEstimate> = self.samples.iter()
+            .filter(|source| *source != sample)
+            .map(|source| self.source_estimate(sample, source))
+            .filter(|estimate| estimate.fraction > 0.0)
+            .collect();
+        sources.sort_by(|a, b| {
+            match b.fraction.partial_cmp(&a.fraction).unwrap() {
+                Ordering::Equal => a.source.cmp(&b.source),
+                order => order,
+            }
+        });
+        sources
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn haplotype(sample: &str, locus: &str, sequence: &str, reads: u32, status: Status) -> Haplotype {
+    Haplotype {
+        sample: sample.to_string(),
+        locus: locus.to_string(),
+        rank: 1,
+        sequence: Bases::from_str(sequence),
+        reads: reads,
+        fraction: 0.0,
+        status: status,
+    }
+}
+
+#[test]
+fn test_wells() {
+    let b7 = Well::parse("P1", "B07").unwrap();
+    assert_eq!(b7, Well { plate: "P1".to_string(), row: 1, column: 7 });
+    assert!(b7.is_neighbour(&Well::parse("P1", "a8").unwrap()));
+    assert!(!b7.is_neighbour(&Well::parse("P1", "B9").unwrap()));
+    assert!(!b7.is_neighbour(&Well::parse("P2", "B8").unwrap()));
+    assert!(!b7.is_neighbour(&b7));
+    assert_eq!(Well::parse("P1", "7B"), None);
+}
+
+#[test]
+fn test_read_wells() {
+    let mut oligos = io::BufReader::new(&b"GATT\tACCA\tS1\tA01\tP1\nGACA\tACCA\tS2\tB02\nGGCA\tACCA\tS3\n"[..]);
+
+    let wells = read_wells(&mut oligos).unwrap();
+    assert_eq!(wells.len(), 2);
+    assert_eq!(wells["S1"], Well { plate: "P1".to_string(), row: 0, column: 1 });
+    assert_eq!(wells["S2"], Well { plate: "".to_string(), row: 1, column: 2 });
+
+    let mut oligos = io::BufReader::new(&b"GATT\tACCA\tS1\t1A\n"[..]);
+    assert!(read_wells(&mut oligos).is_err());
+}
+
+#[test]
+fn test_contamination() {
+    use consensus::Status::*;
+
+    // S2 carries GGGG and TTTT, and S1 shows them at a few percent
+    let matrix = HaplotypeMatrix::new(&[
+        haplotype("S1", "L1", "AAAA", 95, Called),
+        haplotype("S1", "L1", "GGGG", 5, Filtered),
+        haplotype("S1", "L2", "CCCC", 47, Called),
+        haplotype("S1", "L2", "ACCC", 48, Called),
+        haplotype("S1", "L2", "TTTT", 5, Filtered),
+        haplotype("S2", "L1", "GGGG", 200, Called),
+        haplotype("S2", "L2", "TTTT", 100, Called),
+        haplotype("S2", "L2", "CCCC", 100, Called),
+        haplotype("S3", "L1", "AAAA", 50, Called),
+        haplotype("S3", "L2", "CCCC", 10, BelowDepth),
+    ]);
+
+    assert_eq!(matrix.depth("S1"), 200);
+    assert_eq!(matrix.num_called("S3"), 1);
+    assert_eq!(matrix.foreign_fraction("S1", &["S2", "S3"]), 0.05);
+    assert_eq!(matrix.foreign_fraction("S1", &["S3"]), 0.0);
+
+    let sources = matrix.likely_sources("S1");
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].source, "S2");
+    assert_eq!(sources[0].informative_loci, 2);
+    // 10 foreign reads where 100 + 100 / 2 were exposed
+    assert!((sources[0].fraction - 10.0 / 150.0).abs() < 1e-9);
+}
@@ -0,0 +1,101 @@
+use align;
+use bases::Bases;
+
+/// Where a haplotype was folded to by denoising
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Assignment {
+    /// Index of the haplotype it was folded into, its own index if it was kept
+    pub parent: usize,
+    /// Edits between the haplotype and its parent
+    pub distance: usize,
+}
+
+/// Folds haplotypes carrying sequencing errors into the more abundant haplotypes they came from,
+/// in the style of UNOISE
+/// A haplotype d edits away from a more abundant one is folded into it when its abundance is at
+/// most 1 / 2^(alpha * d + 1) of the parent's, parents being tried most abundant first
+pub fn denoise(haplotypes: &[(Bases, u32)], alpha: f64) -> Vec<Assignment> {
+    // Visit haplotypes most abundant first, keeping the given order between ties
+    let mut order: Vec<usize> = (0..haplotypes.len()).collect();
+    order.sort_by(|&a, &b| haplotypes[b].1.cmp(&haplotypes[a].1));
+
+    let mut assignments: Vec<Assignment> = (0..haplotypes.len()).map(|i| Assignment { parent: i, distance: 0 }).collect();
+    let mut centroids: Vec<usize> = vec!();
+
+    for &i in &order {
+        let (ref bases, count) = haplotypes[i];
+
+        for &c in &centroids {
+            let skew = count as f64 / haplotypes[c].1 as f64;
+            let max_distance = max_distance(skew, alpha);
+
+            // Centroids only get less abundant, so no later one can take this haplotype either
+            if max_distance < 1 {
+                break;
+            }
+            if let Some(distance) = align::edit_distance(&bases.bases, &haplotypes[c].0.bases, max_distance) {
+                assignments[i] = Assignment { parent: c, distance: distance };
+                break;
+            }
+        }
+
+        if assignments[i].parent == i {
+            centroids.push(i);
+        }
+    }
+
+    assignments
+}
+
+/// Most edits a haplotype can be from a parent it is folded into, given its abundance relative to
+/// the parent
+fn max_distance(skew: f64, alpha: f64) -> usize {
+    if skew <= 0.0 {
+        return usize::max_value();
+    }
+    let max = ((1.0 / skew).log2() - 1.0) / alpha;
+    if max < 0.0 { 0 } else { max.floor() as usize }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn folds_errors_into_parents() {
+    let haplotypes = vec![
+        (Bases::from_str("GATTACAGATTACA"), 500),
+        (Bases::from_str("CCGTACAGATTACA"), 400),
+        // One edit from the first allele
+        (Bases::from_str("GATTACAGATTTCA"), 20),
+        // Two edits from the second allele
+        (Bases::from_str("CCGTACAGAATACAA"), 3),
+    ];
+
+    let assignments = denoise(&haplotypes, 2.0);
+    assert_eq!(assignments, vec![
+        Assignment { parent: 0, distance: 0 },
+        Assignment { parent: 1, distance: 0 },
+        Assignment { parent: 0, distance: 1 },
+        Assignment { parent: 1, distance: 2 },
+    ]);
+}
+
+#[test]
+fn keeps_abundant_neighbours() {
+    // A second allele one edit away is too abundant to be an error
+    let haplotypes = vec![
+        (Bases::from_str("GATTACAGATTACA"), 500),
+        (Bases::from_str("GATTACAGATTTCA"), 300),
+    ];
+
+    let assignments = denoise(&haplotypes, 2.0);
+    assert_eq!(assignments[1], Assignment { parent: 1, distance: 0 });
+}
+
+#[test]
+fn computes_max_distance() {
+    // 1/8 is the most a haplotype one edit away can have with alpha 2
+    assert_eq!(max_distance(1.0 / 8.0, 2.0), 1);
+    assert_eq!(max_distance(1.0 / 7.0, 2.0), 0);
+    assert_eq!(max_distance(1.0 / 32.0, 2.0), 2);
+}
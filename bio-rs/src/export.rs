@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use std::io;
+
+/// A locus or SNP genotyped across samples
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    pub name: String,
+    /// Contig and 1-based position of SNP markers
+    pub position: Option<(String, usize)>,
+    pub ploidy: usize,
+}
+
+/// Allele codes of every sample at every marker
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenotypeMatrix {
+    pub markers: Vec<Marker>,
+    pub samples: Vec<String>,
+    /// genotypes[sample][marker] holds a code per allele copy, None where the allele is missing
+    pub genotypes: Vec<Vec<Vec<Option<usize>>>>,
+}
+
+/// Integer codes for the alleles of each marker, counting from 1
+/// Codes are kept between runs by reading the table back in, new alleles get the next free codes
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlleleCodes {
+    alleles: BTreeMap<String, Vec<String>>,
+}
+
+impl AlleleCodes {
+    pub fn new() -> AlleleCodes {
+        AlleleCodes { alleles: BTreeMap::new() }
+    }
+
+    /// Reads a table of "<marker><TAB><code><TAB><allele>" lines
+    pub fn read<R: io::Read>(table: &mut io::BufReader<R>) -> io::Result<AlleleCodes> {
+        use std::io::BufRead;
+
+        let mut codes = AlleleCodes::new();
+        for (n, line) in table.lines().enumerate() {
+            let line = try!(line);
+            if line.len() == 0 || line.starts_with("#") || line.starts_with("marker\t") {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            let code: Option<usize> = fields.get(1).and_then(|code| code.parse().ok());
+            let alleles = codes.alleles.entry(fields[0].to_string()).or_insert(vec!());
+            match code {
+                Some(code) if fields.len() == 3 && code == alleles.len() + 1 => alleles.push(fields[2].to_string()),
+                _ => {
+                    let message = format!("Allele codes line {} is malformed or out of order: {}", n + 1, line);
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+                },
+            }
+        }
+        Ok(codes)
+    }
+
+    pub fn write<W: io::Write>(&self, table: &mut W) -> io::Result<()> {
+        try!(writeln!(table, "marker\tcode\tallele"));
+        for (marker, alleles) in &self.alleles {
+            for (i, allele) in alleles.iter().enumerate() {
+                try!(writeln!(table, "{}\t{}\t{}", marker, i + 1, allele));
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives codes to the alleles of a marker that don't have one yet, in sorted order
+    pub fn add(&mut self, marker: &str, alleles: &[String]) {
+        let coded = self.alleles.entry(marker.to_string()).or_insert(vec!());
+        let mut new: Vec<&String> = alleles.iter().filter(|allele| !coded.contains(allele)).collect();
+        new.sort();
+        new.dedup();
+        coded.extend(new.into_iter().cloned());
+    }
+
+    pub fn code(&self, marker: &str, allele: &str) -> Option<usize> {
+        self.alleles.get(marker).and_then(|alleles| alleles.iter().position(|a| a == allele)).map(|i| i + 1)
+    }
+}
+
+/// Lists population names in the order they first appear
+fn population_order(populations: &[String]) -> Vec<String> {
+    let mut order: Vec<String> = vec!();
+    for population in populations {
+        if !order.contains(population) {
+            order.push(population.clone());
+        }
+    }
+    order
+}
+
+/// Writes Genepop, with alleles as 3 digit codes and 000 where missing
+/// Samples are grouped by population, in the order populations first appear
+pub fn write_genepop<W: io::Write>(
+    genepop: &mut W,
+    matrix: &GenotypeMatrix,
+    populations: Option<&[String]>,
+    title: &str,
+) -> io::Result<()>
+{
+    try!(writeln!(genepop, "{}", title));
+    for marker in &matrix.markers {
+        try!(writeln!(genepop, "{}", marker.name));
+    }
+
+    let all = vec![String::new(); matrix.samples.len()];
+    let populations = populations.unwrap_or(&all);
+    for population in population_order(populations) {
+        try!(writeln!(genepop, "Pop"));
+        for (s, sample) in matrix.samples.iter().enumerate().filter(|&(s, _)| populations[s] == population) {
+            try!(write!(genepop, "{} ,", sample));
+            for genotype in &matrix.genotypes[s] {
+                let alleles: Vec<String> = genotype.iter().map(|code| format!("{:03}", code.unwrap_or(0))).collect();
+                try!(write!(genepop, " {}", alleles.concat()));
+            }
+            try!(writeln!(genepop, ""));
+        }
+    }
+    Ok(())
+}
+
+/// Writes STRUCTURE input with one row per allele copy and -9 where missing
+/// The first row names the markers, population numbers follow sample names when given
+pub fn write_structure<W: io::Write>(
+    structure: &mut W,
+    matrix: &GenotypeMatrix,
+    populations: Option<&[String]>,
+) -> io::Result<()>
+{
+    let names: Vec<&str> = matrix.markers.iter().map(|marker| &marker.name[..]).collect();
+    try!(writeln!(structure, "{}", names.connect("\t")));
+
+    let order = populations.map(|populations| population_order(populations));
+    let copies = matrix.markers.iter().map(|marker| marker.ploidy).max().unwrap_or(0);
+    for (s, sample) in matrix.samples.iter().enumerate() {
+        for copy in 0..copies {
+            try!(write!(structure, "{}", sample));
+            if let (Some(populations), Some(order)) = (populations, order.as_ref()) {
+                let number = order.iter().position(|p| *p == populations[s]).unwrap() + 1;
+                try!(write!(structure, "\t{}", number));
+            }
+            for genotype in &matrix.genotypes[s] {
+                match genotype.get(copy).cloned().unwrap_or(None) {
+                    Some(code) => try!(write!(structure, "\t{}", code)),
+                    None => try!(write!(structure, "\t-9")),
+                }
+            }
+            try!(writeln!(structure, ""));
+        }
+    }
+    Ok(())
+}
+
+/// Indices of the markers PLINK can take, diploid or haploid with only allele codes 1 and 2
+pub fn plink_markers(matrix: &GenotypeMatrix) -> Vec<usize> {
+    (0..matrix.markers.len()).filter(|&m| {
+        matrix.markers[m].ploidy <= 2 && matrix.genotypes.iter().all(|genotypes| {
+            genotypes[m].iter().all(|code| code.map(|code| code <= 2).unwrap_or(true))
+        })
+    }).collect()
+}
+
+/// Writes a PLINK ped file of the markers from plink_markers, with 0 0 where an allele is missing
+/// Family IDs are populations when given, haploid genotypes are written as homozygous
+pub fn write_ped<W: io::Write>(ped: &mut W, matrix: &GenotypeMatrix, populations: Option<&[String]>) -> io::Result<()> {
+    let markers = plink_markers(matrix);
+    for (s, sample) in matrix.samples.iter().enumerate() {
+        let family = populations.map(|populations| &populations[s][..]).unwrap_or(sample);
+        try!(write!(ped, "{}\t{}\t0\t0\t0\t-9", family, sample));
+        for &m in &markers {
+            let genotype = &matrix.genotypes[s][m];
+            match (genotype.first().cloned().unwrap_or(None), genotype.last().cloned().unwrap_or(None)) {
+                (Some(first), Some(last)) => try!(write!(ped, "\t{}\t{}", first, last)),
+                _ => try!(write!(ped, "\t0\t0")),
+            }
+        }
+        try!(writeln!(ped, ""));
+    }
+    Ok(())
+}
+
+/// Writes the PLINK map file to go with a ped file
+/// Markers without a position are placed on chromosome 0
+pub fn write_map<W: io::Write>(map: &mut W, matrix: &GenotypeMatrix) -> io::Result<()> {
+    for m in plink_markers(matrix) {
+        let marker = &matrix.markers[m];
+        match marker.position {
+            Some((ref contig, pos)) => try!(writeln!(map, "{}\t{}\t0\t{}", contig, marker.name, pos)),
+            None => try!(writeln!(map, "0\t{}\t0\t0", marker.name)),
+        }
+    }
+    Ok(())
+}
+
+/// Writes a CSV for adegenet's df2genind, with alleles separated by '/' and NA where missing
+pub fn write_adegenet<W: io::Write>(
+    csv: &mut W,
+    matrix: &GenotypeMatrix,
+    populations: Option<&[String]>,
+) -> io::Result<()>
+{
+    let names: Vec<&str> = matrix.markers.iter().map(|marker| &marker.name[..]).collect();
+    match populations {
+        Some(_) => try!(writeln!(csv, "sample,population,{}", names.connect(","))),
+        None => try!(writeln!(csv, "sample,{}", names.connect(","))),
+    }
+
+    for (s, sample) in matrix.samples.iter().enumerate() {
+        try!(write!(csv, "{}", sample));
+        if let Some(populations) = populations {
+            try!(write!(csv, ",{}", populations[s]));
+        }
+        for genotype in &matrix.genotypes[s] {
+            if genotype.iter().any(|code| code.is_none()) {
+                try!(write!(csv, ",NA"));
+            } else {
+                let alleles: Vec<String> = genotype.iter().map(|code| code.unwrap().to_string()).collect();
+                try!(write!(csv, ",{}", alleles.connect("/")));
+            }
+        }
+        try!(writeln!(csv, ""));
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn test_matrix() -> GenotypeMatrix {
+    GenotypeMatrix {
+        markers: vec![
+            Marker { name: "locus1".to_string(), position: None, ploidy: 2 },
+            Marker { name: "locus2".to_string(), position: None, ploidy: 2 },
+        ],
+        samples: vec!["s1".to_string(), "s2".to_string(), "s3".to_string()],
+        genotypes: vec![
+            vec![vec![Some(1), Some(2)], vec![Some(1), Some(1)]],
+            vec![vec![None, None], vec![Some(2), Some(3)]],
+            vec![vec![Some(2), Some(2)], vec![Some(1), Some(3)]],
+        ],
+    }
+}
+
+#[cfg(test)]
+fn written<F: Fn(&mut Vec<u8>) -> io::Result<()>>(write: F) -> String {
+    let mut out: Vec<u8> = vec!();
+    write(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_allele_codes() {
+    let mut codes = AlleleCodes::read(&mut io::BufReader::new(&b"marker\tcode\tallele\nlocus1\t1\tGATT\n"[..])).unwrap();
+    codes.add("locus1", &["GATC".to_string(), "GATT".to_string(), "GAAA".to_string()]);
+
+    // Existing codes are kept and new alleles follow in sorted order
+    assert_eq!(codes.code("locus1", "GATT"), Some(1));
+    assert_eq!(codes.code("locus1", "GAAA"), Some(2));
+    assert_eq!(codes.code("locus1", "GATC"), Some(3));
+    assert_eq!(codes.code("locus2", "GATC"), None);
+
+    let table = written(|out| codes.write(out));
+    assert_eq!(AlleleCodes::read(&mut io::BufReader::new(table.as_bytes())).unwrap(), codes);
+}
+
+#[test]
+fn test_write_genepop() {
+    let populations = vec!["north".to_string(), "south".to_string(), "north".to_string()];
+
+    let genepop = written(|out| write_genepop(out, &test_matrix(), Some(&populations), "test"));
+    assert_eq!(genepop, "test\nlocus1\nlocus2\nPop\ns1 , 001002 001001\ns3 , 002002 001003\nPop\ns2 , 000000 002003\n");
+}
+
+#[test]
+fn test_write_structure() {
+    let structure = written(|out| write_structure(out, &test_matrix(), None));
+    let lines: Vec<&str> = structure.lines().collect();
+    assert_eq!(lines[0], "locus1\tlocus2");
+    assert_eq!(lines[3], "s2\t-9\t2");
+    assert_eq!(lines[4], "s2\t-9\t3");
+}
+
+#[test]
+fn test_write_plink() {
+    let mut matrix = test_matrix();
+    matrix.markers.push(Marker { name: "locus3".to_string(), position: Some(("contig1".to_string(), 10)), ploidy: 2 });
+    matrix.genotypes[0].push(vec![Some(1), None]);
+    matrix.genotypes[1].push(vec![Some(2), Some(2)]);
+    matrix.genotypes[2].push(vec![Some(1), Some(2)]);
+
+    // locus2 has a third allele and is left out, a half missing genotype is missing altogether
+    assert_eq!(plink_markers(&matrix), vec![0, 2]);
+    let ped = written(|out| write_ped(out, &matrix, None));
+    assert_eq!(ped.lines().nth(0), Some("s1\ts1\t0\t0\t0\t-9\t1\t2\t0\t0"));
+    assert_eq!(ped.lines().nth(1), Some("s2\ts2\t0\t0\t0\t-9\t0\t0\t2\t2"));
+
+    let map = written(|out| write_map(out, &matrix));
+    assert_eq!(map, "0\tlocus1\t0\t0\ncontig1\tlocus3\t0\t10\n");
+
+    // Polyploid markers are left out too
+    matrix.markers[0].ploidy = 4;
+    assert_eq!(plink_markers(&matrix), vec![2]);
+}
+
+#[test]
+fn test_write_adegenet() {
+    let populations = vec!["north".to_string(), "south".to_string(), "north".to_string()];
+
+    let csv = written(|out| write_adegenet(out, &test_matrix(), Some(&populations)));
+    assert_eq!(csv, "sample,population,locus1,locus2\ns1,north,1/2,1/1\ns2,south,NA,2/3\ns3,north,2/2,1/3\n");
+}
@@ -0,0 +1,76 @@
+use std::io;
+use bases::Bases;
+
+/// Reads every record of a fasta file as (name, bases) pairs
+/// The name is the header up to the first whitespace, sequence lines are joined
+/// Sequences with bases other than ACGTN, such as IUPAC ambiguity codes, are an error
+pub fn read_fasta<R: io::Read>(fasta: &mut io::BufReader<R>) -> io::Result<Vec<(String, Bases)>> {
+    use std::io::BufRead;
+
+    let mut records = vec!();
+    let mut name: Option<String> = None;
+    let mut bases = String::new();
+
+    for line in fasta.lines() {
+        let line = try!(line);
+        let line = line.trim();
+
+        if line.len() == 0 {
+            continue;
+        } else if line.starts_with(">") {
+            if let Some(name) = name.take() {
+                records.push(try!(record(name, &bases)));
+            }
+            name = Some(line[1..].split_whitespace().next().unwrap_or("").to_string());
+            bases.clear();
+        } else {
+            if name.is_none() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Sequence found before the first fasta header"));
+            }
+            bases.push_str(line);
+        }
+    }
+
+    if let Some(name) = name {
+        records.push(try!(record(name, &bases)));
+    }
+
+    Ok(records)
+}
+
+fn record(name: String, bases: &str) -> io::Result<(String, Bases)> {
+    if !Bases::is_valid(bases) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: unsupported base", name)));
+    }
+    Ok((name, Bases::from_str(bases)))
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_read_fasta() {
+    let mut fasta = io::BufReader::new(&b">locus1 some description\nGATA\nCA\n\n>locus2\nTTT\n"[..]);
+
+    let records = read_fasta(&mut fasta).unwrap();
+    assert_eq!(records, vec![
+        ("locus1".to_string(), Bases::from_str("GATACA")),
+        ("locus2".to_string(), Bases::from_str("TTT")),
+    ]);
+}
+
+#[test]
+fn test_read_fasta_without_header() {
+    let mut fasta = io::BufReader::new(&b"GATACA\n"[..]);
+
+    assert!(read_fasta(&mut fasta).is_err());
+}
+
+#[test]
+fn test_read_fasta_with_iupac_base() {
+    let mut fasta = io::BufReader::new(&b">locus1\nGATACA\n>locus2\nGARACA\n"[..]);
+
+    let error = read_fasta(&mut fasta).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(error.to_string().contains("locus2"));
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::io;
 use bases::Bases;
 
@@ -25,6 +26,40 @@ impl Sequence {
         }
     }
 
+    /// Shortens the sequence to at most n bases
+    pub fn truncate(&mut self, n: usize) {
+        self.bases.bases.truncate(n);
+        self.qual.truncate(n);
+    }
+
+    /// Trims bases off the 3' end until one with a quality of at least min_qual is reached
+    pub fn quality_trim(&mut self, min_qual: u8) {
+        let keep = self.qual.bytes().rposition(|q| q >= min_qual + 33).map(|i| i + 1).unwrap_or(0);
+        self.truncate(keep);
+    }
+
+    /// Reverse complements the bases and reverses the quality string to match
+    pub fn reverse_complement(&mut self) {
+        self.bases.reverse_complement();
+        self.qual = self.qual.chars().rev().collect();
+    }
+
+    /// Appends a key=value tag to the header
+    pub fn add_tag(&mut self, key: &str, value: &str) {
+        self.header.push_str(&format!(" {}={}", key, value));
+    }
+
+    /// Looks up the value of a key=value tag in the header
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.header.split(' ').filter_map(|field| {
+            let mut key_value = field.splitn(2, '=');
+            match (key_value.next(), key_value.next()) {
+                (Some(k), Some(v)) if k == key => Some(v),
+                _ => None,
+            }
+        }).next()
+    }
+
     pub fn debarcode(&mut self, forward_barcode: &Bases, reverse_barcode: &Bases, diffs_allowed: u16) -> bool {
         let start = forward_barcode.bases.len();
         let end = self.bases.bases.len() - reverse_barcode.bases.len();
@@ -39,38 +74,105 @@ impl Sequence {
     }
 }
 
+/// Tries to debarcode a read pair whose forward barcode starts the forward read and whose
+/// reverse barcode starts the reverse read
+/// Both reads are trimmed only if both barcodes match
+pub fn debarcode_pair(
+    forward: &mut Sequence,
+    reverse: &mut Sequence,
+    forward_barcode: &Bases,
+    reverse_barcode: &Bases,
+    diffs_allowed: u16,
+) -> bool
+{
+    if !forward.bases.starts_with(forward_barcode, diffs_allowed) || !reverse.bases.starts_with(reverse_barcode, diffs_allowed) {
+        return false;
+    }
+
+    *forward = forward.tail(forward.bases.len() - forward_barcode.len());
+    *reverse = reverse.tail(reverse.bases.len() - reverse_barcode.len());
+
+    true
+}
+
+/// Encodes a Phred quality score as a Sanger (offset 33) quality character
+/// Scores above 93 are capped there, as '~' is the highest printable character
+pub fn qual_char(phred: u8) -> char {
+    (cmp::min(phred, 93) + 33) as char
+}
+
+/// Reads every sequence of a fastq file
+/// Panics if the file can't be read or holds a malformed record
 pub fn read_fastq<R: io::Read>(fastq: &mut io::BufReader<R>) -> Vec<Sequence> {
-    use std::io::BufRead;
+    let mut records = records(fastq);
+    let seqs = records.by_ref().collect();
+    if let Some(error) = records.error() {
+        panic!("Failed to read fastq: {}", error);
+    }
+    seqs
+}
+
+/// Iterates over the sequences of a fastq file one at a time, without reading it all in
+/// Iteration stops at a read error or malformed record as well as at the end of the file, so check
+/// error() afterwards
+pub fn records<R: io::Read>(fastq: &mut io::BufReader<R>) -> Records<R> {
+    Records { fastq: fastq, error: None }
+}
+
+pub struct Records<'a, R: 'a> {
+    fastq: &'a mut io::BufReader<R>,
+    error: Option<io::Error>,
+}
+
+impl<'a, R> Records<'a, R> {
+    /// The error that stopped iteration before the end of the file, if there was one
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl<'a, R: io::Read> Iterator for Records<'a, R> {
+    type Item = Sequence;
+
+    fn next(&mut self) -> Option<Sequence> {
+        use std::io::BufRead;
+
+        if self.error.is_some() {
+            return None;
+        }
 
-    let mut seqs = vec!();
-    loop {
         // Read the four lines making up a fastq sequence
-        let mut header_line = String::new();
-        let mut bases_line = String::new();
-        let mut plus_line = String::new();
-        let mut qual_line = String::new();
-        
-        if fastq.read_line(&mut header_line).is_err() { break; }
-        if fastq.read_line(&mut bases_line).is_err() { break; }
-        if fastq.read_line(&mut plus_line).is_err() { break; }
-        if fastq.read_line(&mut qual_line).is_err() { break; }
+        let mut lines = vec!();
+        for _ in 0..4 {
+            let mut line = String::new();
+            if let Err(error) = self.fastq.read_line(&mut line) {
+                self.error = Some(error);
+                return None;
+            }
+            lines.push(line);
+        }
 
-        if header_line.len() == 0 {
-            break;
+        if lines[0].len() == 0 {
+            return None;
         }
 
         // Trim the '@' off the header line
-        let header = header_line[1..header_line.len()].trim_right().to_string();
+        let header = lines[0][1..].trim_right().to_string();
+
+        // A record cut short or with bases other than ACGTN means the file is damaged
+        let bases = lines[1].trim_right();
+        if !lines[2].starts_with("+") || !Bases::is_valid(bases) {
+            let message = format!("Malformed fastq record {}", header);
+            self.error = Some(io::Error::new(io::ErrorKind::InvalidInput, message));
+            return None;
+        }
 
-        // Add the sequence
-        seqs.push(Sequence {
+        Some(Sequence {
             header: header,
-            bases: Bases::from_str(bases_line.as_str().trim_right()),
-            qual: qual_line.as_str().trim_right().to_string(),
-        });
+            bases: Bases::from_str(bases),
+            qual: lines[3].trim_right().to_string(),
+        })
     }
-
-    seqs
 }
 
 pub fn write_fastq<'a, W, I>(fastq: &mut io::BufWriter<W>, seqs: I) -> io::Result<()>
@@ -167,6 +269,99 @@ fn test_sequence_fails_debarcode_properly() {
     assert_eq!(seq.qual, "ABCDEFGHIJK".to_string());
 }
 
+#[test]
+fn test_debarcode_pair() {
+    let mut forward = Sequence {
+        header: "foo".to_string(),
+        bases: Bases::from_str("ATGAAAAA"),
+        qual: "ABCDEFGH".to_string(),
+    };
+    let mut reverse = Sequence {
+        header: "foo".to_string(),
+        bases: Bases::from_str("CCTTTTTT"),
+        qual: "ABCDEFGH".to_string(),
+    };
+
+    assert!(!debarcode_pair(&mut forward, &mut reverse, &Bases::from_str("ATG"), &Bases::from_str("GGT"), 0));
+    assert_eq!(forward.bases, Bases::from_str("ATGAAAAA"));
+    assert_eq!(reverse.bases, Bases::from_str("CCTTTTTT"));
+
+    assert!(debarcode_pair(&mut forward, &mut reverse, &Bases::from_str("ATG"), &Bases::from_str("CCT"), 0));
+    assert_eq!(forward.bases, Bases::from_str("AAAAA"));
+    assert_eq!(forward.qual, "DEFGH".to_string());
+    assert_eq!(reverse.bases, Bases::from_str("TTTTT"));
+    assert_eq!(reverse.qual, "DEFGH".to_string());
+}
+
+#[test]
+fn test_sequence_truncate() {
+    let mut seq = Sequence {
+        header: "foo".to_string(),
+        bases: Bases::from_str("GATACA"),
+        qual: "ABCDEF".to_string(),
+    };
+
+    seq.truncate(10);
+    assert_eq!(seq.bases, Bases::from_str("GATACA"));
+
+    seq.truncate(4);
+    assert_eq!(seq.bases, Bases::from_str("GATA"));
+    assert_eq!(seq.qual, "ABCD".to_string());
+}
+
+#[test]
+fn test_sequence_quality_trim() {
+    let mut seq = Sequence {
+        header: "foo".to_string(),
+        bases: Bases::from_str("GATACAGA"),
+        qual: "II#II###".to_string(),
+    };
+
+    // '#' is Q2, 'I' is Q40
+    seq.quality_trim(20);
+    assert_eq!(seq.bases, Bases::from_str("GATAC"));
+    assert_eq!(seq.qual, "II#II".to_string());
+
+    seq.quality_trim(41);
+    assert_eq!(seq.bases.len(), 0);
+    assert_eq!(seq.qual, "".to_string());
+}
+
+#[test]
+fn test_sequence_reverse_complement() {
+    let mut seq = Sequence {
+        header: "foo".to_string(),
+        bases: Bases::from_str("GATACA"),
+        qual: "ABCDEF".to_string(),
+    };
+
+    seq.reverse_complement();
+    assert_eq!(seq.bases, Bases::from_str("TGTATC"));
+    assert_eq!(seq.qual, "FEDCBA".to_string());
+}
+
+#[test]
+fn test_sequence_tags() {
+    let mut seq = Sequence {
+        header: "M00123:1:000:1:1:1:1 1:N:0:7".to_string(),
+        bases: Bases::from_str("GATACA"),
+        qual: "ABCDEF".to_string(),
+    };
+
+    seq.add_tag("join", "merged");
+    assert_eq!(seq.header, "M00123:1:000:1:1:1:1 1:N:0:7 join=merged");
+    assert_eq!(seq.tag("join"), Some("merged"));
+    assert_eq!(seq.tag("locus"), None);
+}
+
+#[test]
+fn test_qual_char() {
+    assert_eq!(qual_char(0), '!');
+    assert_eq!(qual_char(40), 'I');
+    assert_eq!(qual_char(93), '~');
+    assert_eq!(qual_char(255), '~');
+}
+
 #[test]
 fn test_read_fastq_qual_header() {
     use std::old_io::MemReader;
@@ -197,6 +392,16 @@ fn test_read_fastq_no_qual_header() {
     assert_eq!(vec![expected_seq], read_fastq(&mut fastq));
 }
 
+#[test]
+fn test_fastq_records() {
+    let mut fastq = io::BufReader::new(&b"@read1\nGATACA\n+\nAAAAAA\n@read2\nTTG\n+\nBBB\n"[..]);
+
+    let mut records = records(&mut fastq);
+    assert_eq!(records.next().map(|seq| seq.header), Some("read1".to_string()));
+    assert_eq!(records.next().map(|seq| seq.bases), Some(Bases::from_str("TTG")));
+    assert_eq!(records.next(), None);
+}
+
 #[test]
 fn test_write_fastq() {
     use std::old_io::MemWriter;
@@ -232,3 +437,18 @@ fn test_write_fastq() {
 
     assert_eq!(from_utf8(fastq.into_inner().into_inner().as_str()).unwrap(), expected_fastq);
 }
+
+#[test]
+fn test_records_stop_at_malformed_record() {
+    let mut fastq = io::BufReader::new(&b"@read1\nGATACA\n+\nIIIIII\n@read2\nGAT"[..]);
+
+    let mut iter = records(&mut fastq);
+    assert_eq!(iter.next().map(|seq| seq.header), Some("read1".to_string()));
+    assert!(iter.next().is_none());
+    assert_eq!(iter.error().map(|error| error.kind()), Some(io::ErrorKind::InvalidInput));
+
+    let mut fastq = io::BufReader::new(&b"@read1\nGATACA\n+\nIIIIII\n"[..]);
+    let mut iter = records(&mut fastq);
+    assert_eq!(iter.by_ref().count(), 1);
+    assert!(iter.error().is_none());
+}
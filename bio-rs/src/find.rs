@@ -0,0 +1,304 @@
+use std::cmp;
+
+/// A hit reported by the approximate search functions
+/// start..end is the matched region of the searched sequence, dist is its distance to the pattern
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub dist: usize,
+}
+
+/// Substring search over sequences of any comparable element
+/// Patterns of up to 64 elements are searched bit-parallel, longer patterns fall back to
+/// plain dynamic programming with the same results
+pub trait Find<T: PartialEq> {
+    /// Returns the position of the first exact occurrence of to_find
+    fn find(&self, to_find: &[T]) -> Option<usize>;
+
+    /// Returns the position of the last exact occurrence of to_find
+    fn rfind(&self, to_find: &[T]) -> Option<usize>;
+
+    /// Returns the positions of all exact (possibly overlapping) occurrences of to_find
+    fn find_all(&self, to_find: &[T]) -> Vec<usize>;
+
+    /// Returns every occurrence of to_find with at most k mismatches (no insertions or deletions)
+    fn find_mismatches(&self, to_find: &[T], k: usize) -> Vec<Match>;
+
+    /// Returns, for every end position, the best occurrence of to_find with at most k edits
+    /// (mismatches, insertions and deletions)
+    fn find_edits(&self, to_find: &[T], k: usize) -> Vec<Match>;
+}
+
+impl<T: PartialEq> Find<T> for [T] {
+    fn find(&self, to_find: &[T]) -> Option<usize> {
+        self.find_all(to_find).into_iter().next()
+    }
+
+    fn rfind(&self, to_find: &[T]) -> Option<usize> {
+        self.find_all(to_find).pop()
+    }
+
+    fn find_all(&self, to_find: &[T]) -> Vec<usize> {
+        self.find_mismatches(to_find, 0).into_iter().map(|m| m.start).collect()
+    }
+
+    fn find_mismatches(&self, to_find: &[T], k: usize) -> Vec<Match> {
+        if to_find.len() == 0 || to_find.len() > self.len() {
+            return vec!();
+        }
+        if to_find.len() > 64 {
+            return hamming_naive(self, to_find, k);
+        }
+        hamming_bitap(self, to_find, k)
+    }
+
+    fn find_edits(&self, to_find: &[T], k: usize) -> Vec<Match> {
+        if to_find.len() == 0 || self.len() == 0 {
+            return vec!();
+        }
+        let ends = if to_find.len() > 64 {
+            edit_ends_naive(self, to_find, k)
+        } else {
+            edit_ends_myers(self, to_find, k)
+        };
+        ends.into_iter().map(|(end, dist)| {
+            Match {
+                start: edit_start(self, to_find, end, dist),
+                end: end,
+                dist: dist,
+            }
+        }).collect()
+    }
+}
+
+/// Per-symbol match masks for a pattern of at most 64 elements
+/// Bit i of a symbol's mask is set when pattern[i] equals that symbol
+struct PatternMasks<'a, T: 'a> {
+    masks: Vec<(&'a T, u64)>,
+}
+
+impl<'a, T: PartialEq> PatternMasks<'a, T> {
+    fn new(pattern: &'a [T]) -> PatternMasks<'a, T> {
+        let mut masks: Vec<(&'a T, u64)> = vec!();
+        for (i, element) in pattern.iter().enumerate() {
+            match masks.iter().position(|&(e, _)| e == element) {
+                Some(index) => { masks[index].1 |= 1 << i; },
+                None => { masks.push((element, 1 << i)); },
+            }
+        }
+        PatternMasks { masks: masks }
+    }
+
+    fn get(&self, element: &T) -> u64 {
+        self.masks.iter().find(|&&(e, _)| e == element).map(|&(_, mask)| mask).unwrap_or(0)
+    }
+}
+
+// Shift-And with one state vector per allowed mismatch count
+fn hamming_bitap<T: PartialEq>(text: &[T], pattern: &[T], k: usize) -> Vec<Match> {
+    let m = pattern.len();
+    let k = cmp::min(k, m);
+    let masks = PatternMasks::new(pattern);
+    let high = 1u64 << (m - 1);
+
+    let mut states = vec![0u64; k + 1];
+    let mut matches = vec!();
+
+    for (j, element) in text.iter().enumerate() {
+        let eq = masks.get(element);
+        let mut previous = 0u64;
+        for d in 0..k + 1 {
+            let old = states[d];
+            states[d] = ((old << 1) | 1) & eq;
+            if d > 0 {
+                states[d] |= (previous << 1) | 1;
+            }
+            previous = old;
+        }
+        if j + 1 >= m {
+            if let Some(dist) = states.iter().position(|&s| s & high != 0) {
+                matches.push(Match { start: j + 1 - m, end: j + 1, dist: dist });
+            }
+        }
+    }
+
+    matches
+}
+
+fn hamming_naive<T: PartialEq>(text: &[T], pattern: &[T], k: usize) -> Vec<Match> {
+    let m = pattern.len();
+    let mut matches = vec!();
+    for start in 0..text.len() - m + 1 {
+        let dist = text[start..start + m].iter().zip(pattern.iter()).filter(|&(a, b)| a != b).count();
+        if dist <= k {
+            matches.push(Match { start: start, end: start + m, dist: dist });
+        }
+    }
+    matches
+}
+
+// Myers' bit-vector algorithm, returns (exclusive end, distance) pairs
+fn edit_ends_myers<T: PartialEq>(text: &[T], pattern: &[T], k: usize) -> Vec<(usize, usize)> {
+    let m = pattern.len();
+    let masks = PatternMasks::new(pattern);
+    let high = 1u64 << (m - 1);
+
+    let mut pv = !0u64;
+    let mut mv = 0u64;
+    let mut score = m;
+    let mut ends = vec!();
+
+    for (j, element) in text.iter().enumerate() {
+        let eq = masks.get(element);
+        let xv = eq | mv;
+        let xh = ((eq & pv).wrapping_add(pv) ^ pv) | eq;
+        let mut ph = mv | !(xh | pv);
+        let mut mh = pv & xh;
+
+        if ph & high != 0 {
+            score += 1;
+        } else if mh & high != 0 {
+            score -= 1;
+        }
+
+        ph <<= 1;
+        mh <<= 1;
+        pv = mh | !(xv | ph);
+        mv = ph & xv;
+
+        if score <= k {
+            ends.push((j + 1, score));
+        }
+    }
+
+    ends
+}
+
+// Sellers' semi-global dynamic programming, same output as edit_ends_myers
+fn edit_ends_naive<T: PartialEq>(text: &[T], pattern: &[T], k: usize) -> Vec<(usize, usize)> {
+    let mut column: Vec<usize> = (0..pattern.len() + 1).collect();
+    let mut ends = vec!();
+
+    for (j, element) in text.iter().enumerate() {
+        let mut diagonal = column[0];
+        for i in 1..pattern.len() + 1 {
+            let cost = if pattern[i - 1] == *element { 0 } else { 1 };
+            let value = cmp::min(diagonal + cost, cmp::min(column[i] + 1, column[i - 1] + 1));
+            diagonal = column[i];
+            column[i] = value;
+        }
+        if column[pattern.len()] <= k {
+            ends.push((j + 1, column[pattern.len()]));
+        }
+    }
+
+    ends
+}
+
+// Finds the start of the shortest alignment of pattern ending at end with distance dist
+fn edit_start<T: PartialEq>(text: &[T], pattern: &[T], end: usize, dist: usize) -> usize {
+    let window = cmp::min(end, pattern.len() + dist);
+
+    // Align the reversed pattern globally against a prefix of the reversed text
+    let mut row: Vec<usize> = (0..window + 1).collect();
+    for i in 1..pattern.len() + 1 {
+        let mut diagonal = row[0];
+        row[0] = i;
+        for j in 1..window + 1 {
+            let cost = if pattern[pattern.len() - i] == text[end - j] { 0 } else { 1 };
+            let value = cmp::min(diagonal + cost, cmp::min(row[j] + 1, row[j - 1] + 1));
+            diagonal = row[j];
+            row[j] = value;
+        }
+    }
+
+    let length = row.iter().position(|&d| d == dist).unwrap_or(pattern.len());
+    end - cmp::min(end, length)
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn find_exact() {
+    let text = b"GATACAGATACA";
+
+    assert_eq!(text.find(b"TACA"), Some(2));
+    assert_eq!(text.rfind(b"TACA"), Some(8));
+    assert_eq!(text.find_all(b"ACA"), vec![3, 9]);
+    assert_eq!(text.find(b"CCC"), None);
+}
+
+#[test]
+fn find_longer_than_text() {
+    let text = b"GAT";
+
+    assert_eq!(text.find(b"GATACA"), None);
+    assert_eq!(text.find_mismatches(b"GATACA", 3), vec!());
+}
+
+#[test]
+fn find_overlapping() {
+    let text = b"AAAA";
+
+    assert_eq!(text.find_all(b"AA"), vec![0, 1, 2]);
+}
+
+#[test]
+fn find_with_mismatches() {
+    let text = b"TTGATTCTTT";
+
+    assert_eq!(text.find_mismatches(b"GATACA", 0), vec!());
+    assert_eq!(text.find_mismatches(b"GATACA", 2), vec![Match { start: 2, end: 8, dist: 2 }]);
+}
+
+#[test]
+fn find_with_edits() {
+    // GATACA with the second A deleted
+    let text = b"TTGATCATT";
+
+    let matches = text.find_edits(b"GATACA", 1);
+    assert_eq!(matches, vec![Match { start: 2, end: 7, dist: 1 }]);
+}
+
+#[test]
+fn find_edits_insertion() {
+    // GATACA with an extra G inserted
+    let text = b"CCGATGACACC";
+
+    let matches = text.find_edits(b"GATACA", 1);
+    assert_eq!(matches, vec![Match { start: 2, end: 9, dist: 1 }]);
+}
+
+#[test]
+fn find_long_pattern_matches_bit_parallel() {
+    let pattern: Vec<u8> = (0..80).map(|i| b"ACGT"[(i * 7 + i / 3) % 4]).collect();
+    let mut text: Vec<u8> = b"TTTT".to_vec();
+    text.extend(pattern.iter().cloned());
+    text.extend(b"GGGG".iter().cloned());
+    text[30] = b'N';
+
+    let matches = text.find_mismatches(&pattern, 1);
+    assert_eq!(matches, vec![Match { start: 4, end: 84, dist: 1 }]);
+
+    let matches = text.find_edits(&pattern, 1);
+    assert_eq!(matches, vec![Match { start: 4, end: 84, dist: 1 }]);
+
+    // Compare the two edit distance implementations on a short pattern
+    assert_eq!(
+        edit_ends_myers(&text, &pattern[10..40], 3),
+        edit_ends_naive(&text, &pattern[10..40], 3)
+    );
+}
+
+#[test]
+fn find_bases() {
+    use bases::Bases;
+
+    let bases = Bases::from_str("GATACAGATACA");
+    let pattern = Bases::from_str("ATAC");
+
+    assert_eq!(bases.bases.find(&pattern.bases), Some(1));
+    assert_eq!(bases.bases.rfind(&pattern.bases), Some(7));
+}
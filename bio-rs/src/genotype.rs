@@ -0,0 +1,216 @@
+use std::cmp;
+
+/// Highest genotype quality reported
+pub const MAX_QUALITY: f64 = 99.0;
+
+/// Likelihood model for calling genotypes from per-haplotype read counts
+/// A read from an allele shows that allele with probability 1 - error_rate, and one of the other
+/// observed haplotypes otherwise
+#[derive(Clone, Copy, Debug)]
+pub struct GenotypeModel {
+    /// Number of alleles in a genotype
+    pub ploidy: usize,
+    /// Chance that a read doesn't show the allele it came from
+    pub error_rate: f64,
+    /// Only this many of the most abundant haplotypes are considered as alleles
+    pub max_candidates: usize,
+}
+
+/// A maximum likelihood genotype
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenotypeCall {
+    /// Indices into the haplotype counts of the called alleles, one per allele copy, ascending
+    pub alleles: Vec<usize>,
+    /// Phred-scaled probability that the call is wrong
+    pub quality: f64,
+    /// Natural log likelihood of the called genotype
+    pub log_likelihood: f64,
+}
+
+impl GenotypeCall {
+    /// Formats the call as allele numbers separated by '/', counting from 1
+    pub fn as_string(&self) -> String {
+        let alleles: Vec<String> = self.alleles.iter().map(|a| (a + 1).to_string()).collect();
+        alleles.connect("/")
+    }
+
+    pub fn is_heterozygous(&self) -> bool {
+        self.alleles.iter().any(|&a| a != self.alleles[0])
+    }
+}
+
+impl GenotypeModel {
+    pub fn new(ploidy: usize, error_rate: f64) -> GenotypeModel {
+        GenotypeModel {
+            ploidy: ploidy,
+            error_rate: error_rate,
+            max_candidates: 6,
+        }
+    }
+
+    /// Natural log likelihood of the counts under a genotype
+    /// genotype holds one index into counts per allele copy
+    pub fn log_likelihood(&self, counts: &[u32], genotype: &[usize]) -> f64 {
+        let num_classes = self.num_classes(counts);
+        let other_rate = if num_classes > 1 { self.error_rate / ((num_classes - 1) as f64) } else { 0.0 };
+
+        let class_counts = self.class_counts(counts);
+        class_counts.iter().enumerate().filter(|&(_, &count)| count > 0).map(|(class, &count)| {
+            // Chance of a read showing this class, averaged over the genotype's allele copies
+            let p = genotype.iter().map(|&allele| {
+                if allele == class { 1.0 - self.error_rate } else { other_rate }
+            }).fold(0.0, |sum, p| sum + p) / (genotype.len() as f64);
+
+            (count as f64) * p.ln()
+        }).fold(0.0, |sum, ll| sum + ll)
+    }
+
+    /// Calls the maximum likelihood genotype
+    /// counts must be sorted most abundant first
+    /// Returns None if there are no reads
+    pub fn call(&self, counts: &[u32]) -> Option<GenotypeCall> {
+        if self.ploidy == 0 || counts.iter().all(|&count| count == 0) {
+            return None;
+        }
+
+        let num_candidates = cmp::min(self.max_candidates, counts.len());
+        let genotypes = multisets(num_candidates, self.ploidy);
+        let log_likelihoods: Vec<f64> = genotypes.iter().map(|g| self.log_likelihood(counts, g)).collect();
+
+        Some(best_call(&genotypes, &log_likelihoods))
+    }
+
+    // Haplotypes beyond the candidates are lumped into one extra class
+    fn num_classes(&self, counts: &[u32]) -> usize {
+        self.class_counts(counts).len()
+    }
+
+    fn class_counts(&self, counts: &[u32]) -> Vec<u32> {
+        if counts.len() <= self.max_candidates {
+            return counts.to_vec();
+        }
+        let mut class_counts = counts[..self.max_candidates].to_vec();
+        class_counts.push(counts[self.max_candidates..].iter().fold(0, |sum, &count| sum + count));
+        class_counts
+    }
+}
+
+/// Picks the maximum likelihood genotype, with its quality, from every genotype considered
+/// genotypes and log_likelihoods must be the same length and not empty
+pub fn best_call(genotypes: &[Vec<usize>], log_likelihoods: &[f64]) -> GenotypeCall {
+    let mut best = 0;
+    for (i, &ll) in log_likelihoods.iter().enumerate() {
+        if ll > log_likelihoods[best] {
+            best = i;
+        }
+    }
+
+    // With a flat prior, the chance the best genotype is wrong is the relative weight of the rest
+    let others = log_likelihoods.iter().enumerate()
+        .filter(|&(i, _)| i != best)
+        .map(|(_, &ll)| (ll - log_likelihoods[best]).exp())
+        .fold(0.0, |sum, p| sum + p);
+    let quality = if others > 0.0 {
+        let error = others / (1.0 + others);
+        (-10.0 * error.log10()).min(MAX_QUALITY)
+    } else {
+        MAX_QUALITY
+    };
+
+    GenotypeCall {
+        alleles: genotypes[best].clone(),
+        quality: quality,
+        log_likelihood: log_likelihoods[best],
+    }
+}
+
+/// Every multiset of size k drawn from 0..n, each sorted ascending
+pub fn multisets(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![vec!()];
+    }
+    let mut sets = vec!();
+    for smaller in multisets(n, k - 1) {
+        let start = smaller.last().cloned().unwrap_or(0);
+        for i in start..n {
+            let mut set = smaller.clone();
+            set.push(i);
+            sets.push(set);
+        }
+    }
+    sets
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn enumerates_multisets() {
+    assert_eq!(multisets(3, 2), vec![vec![0, 0], vec![0, 1], vec![0, 2], vec![1, 1], vec![1, 2], vec![2, 2]]);
+    assert_eq!(multisets(4, 3).len(), 20);
+}
+
+#[test]
+fn calls_homozygote_over_artifact() {
+    let model = GenotypeModel::new(2, 0.01);
+
+    let call = model.call(&[100, 12]).unwrap();
+    assert_eq!(call.alleles, vec![0, 0]);
+    assert!(!call.is_heterozygous());
+    assert!(call.quality > 20.0);
+}
+
+#[test]
+fn calls_heterozygote() {
+    let model = GenotypeModel::new(2, 0.01);
+
+    let call = model.call(&[60, 55, 2]).unwrap();
+    assert_eq!(call.alleles, vec![0, 1]);
+    assert_eq!(call.as_string(), "1/2");
+    assert!(call.is_heterozygous());
+    assert_eq!(call.quality, MAX_QUALITY);
+}
+
+#[test]
+fn low_depth_has_low_quality() {
+    let model = GenotypeModel::new(2, 0.01);
+
+    let call = model.call(&[2, 1]).unwrap();
+    assert!(call.quality < 20.0);
+}
+
+#[test]
+fn calls_polyploid_dosage() {
+    let model = GenotypeModel::new(4, 0.01);
+
+    // Three copies of the first haplotype, one of the second
+    let call = model.call(&[150, 50]).unwrap();
+    assert_eq!(call.alleles, vec![0, 0, 0, 1]);
+}
+
+#[test]
+fn calls_haploid() {
+    let model = GenotypeModel::new(1, 0.01);
+
+    let call = model.call(&[80, 3]).unwrap();
+    assert_eq!(call.alleles, vec![0]);
+}
+
+#[test]
+fn no_call_without_reads() {
+    let model = GenotypeModel::new(2, 0.01);
+
+    assert!(model.call(&[]).is_none());
+    assert!(model.call(&[0]).is_none());
+}
+
+#[test]
+fn lumps_rare_haplotypes() {
+    let mut model = GenotypeModel::new(2, 0.01);
+    model.max_candidates = 2;
+
+    let counts = [50, 45, 1, 1, 1];
+    let call = model.call(&counts).unwrap();
+    assert_eq!(call.alleles, vec![0, 1]);
+    assert_eq!(model.class_counts(&counts), vec![50, 45, 3]);
+}
@@ -0,0 +1,305 @@
+use std::cmp;
+use std::iter;
+
+use bases::{Base, Bases};
+use fastq::{qual_char, Sequence};
+use find::{Find, Match};
+
+/// Header tag recording how a read pair was joined
+pub const JOIN_TAG: &'static str = "join";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JoinKind {
+    /// The mates overlapped and were merged into a single read
+    Merged,
+    /// The mates were concatenated around a spacer of N bases
+    Concatenated,
+}
+
+impl JoinKind {
+    pub fn as_str(self) -> &'static str {
+        use self::JoinKind::*;
+
+        match self {
+            Merged => "merged",
+            Concatenated => "concatenated",
+        }
+    }
+
+    pub fn from_str(kind: &str) -> Option<JoinKind> {
+        use self::JoinKind::*;
+
+        match kind {
+            "merged" => Some(Merged),
+            "concatenated" => Some(Concatenated),
+            _ => None,
+        }
+    }
+}
+
+/// The run of N bases placed between concatenated mates
+#[derive(Clone, Copy, Debug)]
+pub struct Spacer {
+    pub len: usize,
+    /// Phred quality given to every spacer base
+    pub qual: u8,
+}
+
+impl Spacer {
+    pub fn new(len: usize, qual: u8) -> Spacer {
+        Spacer { len: len, qual: qual }
+    }
+}
+
+/// Header tag recording why a read pair could not be merged
+pub const UNMERGED_TAG: &'static str = "unmerged";
+
+/// Why a read pair could not be merged
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MergeFailure {
+    /// The start of the reverse read wasn't found in the forward read
+    NoOverlap,
+    /// The start of the reverse read runs off the end of the forward read
+    ShortOverlap,
+    /// The start of the reverse read was found, but with more mismatches than allowed
+    TooManyMismatches,
+    /// The start of the reverse read was found equally well at several places, when those are rejected
+    AmbiguousOverlap,
+    /// One of the reads is too short to search for an overlap
+    ReadTooShort,
+    /// One of the reads has too many N bases
+    HighN,
+}
+
+impl MergeFailure {
+    /// Every failure, in the order they are reported in
+    pub fn all() -> Vec<MergeFailure> {
+        use self::MergeFailure::*;
+
+        vec![NoOverlap, ShortOverlap, TooManyMismatches, AmbiguousOverlap, ReadTooShort, HighN]
+    }
+
+    /// The reason code written to headers and reports
+    pub fn as_str(self) -> &'static str {
+        use self::MergeFailure::*;
+
+        match self {
+            NoOverlap => "no_overlap",
+            ShortOverlap => "short_overlap",
+            TooManyMismatches => "mismatches",
+            AmbiguousOverlap => "ambiguous",
+            ReadTooShort => "short_read",
+            HighN => "high_n",
+        }
+    }
+}
+
+/// Settings for overlap merging
+#[derive(Clone, Copy, Debug)]
+pub struct MergeParams {
+    /// Bases from the start of the reverse read searched for in the forward read
+    pub oligo_size: usize,
+    /// Mismatches allowed between that oligo and the forward read
+    pub max_mismatches: usize,
+    /// Reads shorter than this (or than oligo_size) can't be merged
+    pub min_length: usize,
+    /// Fraction of N bases above which a failed pair is blamed on its N content
+    pub max_n_fraction: f64,
+    /// Fail pairs whose oligo is found equally well at several places instead of merging at the
+    /// first, which tandem repeats do
+    pub reject_ambiguous: bool,
+}
+
+impl MergeParams {
+    pub fn new(oligo_size: usize) -> MergeParams {
+        MergeParams {
+            oligo_size: oligo_size,
+            max_mismatches: 0,
+            min_length: 0,
+            max_n_fraction: 0.1,
+            reject_ambiguous: false,
+        }
+    }
+}
+
+/// Tries to merge a read pair by locating the start of the reverse read in the forward read
+/// The first oligo_size bases of the reverse read are reverse complemented and searched for
+/// On success returns the forward read cut at the end of the overlap, tagged as merged
+pub fn merge(forward: &Sequence, reverse: &Sequence, oligo_size: usize) -> Option<Sequence> {
+    try_merge(forward, reverse, &MergeParams::new(oligo_size)).ok()
+}
+
+/// Like merge, but reports why the pair couldn't be merged on failure
+pub fn try_merge(forward: &Sequence, reverse: &Sequence, params: &MergeParams) -> Result<Sequence, MergeFailure> {
+    use self::MergeFailure::*;
+
+    let min_length = cmp::max(params.oligo_size, params.min_length);
+    if forward.bases.len() < min_length || reverse.bases.len() < min_length {
+        return Err(ReadTooShort);
+    }
+
+    let mut reverse_oligo = reverse.bases.head(params.oligo_size);
+    reverse_oligo.reverse_complement();
+
+    let hits = forward.bases.bases.find_mismatches(&reverse_oligo.bases, params.max_mismatches);
+    if let Some(best) = hits.iter().map(|hit| hit.dist).min() {
+        let best_hits: Vec<&Match> = hits.iter().filter(|hit| hit.dist == best).collect();
+        if params.reject_ambiguous && best_hits.len() > 1 {
+            return Err(AmbiguousOverlap);
+        }
+
+        let mut merged = forward.head(best_hits[0].end);
+        merged.add_tag(JOIN_TAG, JoinKind::Merged.as_str());
+        return Ok(merged);
+    }
+
+    // Work out why the oligo wasn't found
+    if n_fraction(forward) > params.max_n_fraction || n_fraction(reverse) > params.max_n_fraction {
+        return Err(HighN);
+    }
+
+    // A near miss has at most a quarter of the oligo mismatched
+    let near_miss = cmp::max(params.max_mismatches + 1, params.oligo_size / 4);
+    if forward.bases.bases.find_mismatches(&reverse_oligo.bases, near_miss).len() > 0 {
+        return Err(TooManyMismatches);
+    }
+
+    // Check whether at least half of the oligo hangs off the end of the forward read
+    let forward_bases = &forward.bases.bases;
+    let overhangs = (params.oligo_size / 2..params.oligo_size).rev().any(|overlap| {
+        overlap > 0 && forward_bases[forward_bases.len() - overlap..] == reverse_oligo.bases[..overlap]
+    });
+    if overhangs {
+        return Err(ShortOverlap);
+    }
+
+    Err(NoOverlap)
+}
+
+/// Fraction of a read's bases that are N
+fn n_fraction(seq: &Sequence) -> f64 {
+    if seq.bases.len() == 0 {
+        return 0.0;
+    }
+    let n_count = seq.bases.bases.iter().filter(|&&base| base == Base::N).count();
+    (n_count as f64) / (seq.bases.len() as f64)
+}
+
+/// Joins the forward read to the reverse complemented reverse read around a spacer
+/// The result is tagged as concatenated
+pub fn concatenate(forward: &Sequence, reverse: &Sequence, spacer: &Spacer) -> Sequence {
+    let mut reverse = reverse.clone();
+    reverse.reverse_complement();
+
+    let spacer_bases: String = iter::repeat('N').take(spacer.len).collect();
+    let spacer_qual: String = iter::repeat(qual_char(spacer.qual)).take(spacer.len).collect();
+
+    let mut joined = Sequence {
+        header: forward.header.clone(),
+        bases: forward.bases.clone() + &Bases::from_str(&spacer_bases) + &reverse.bases,
+        qual: forward.qual.clone() + &spacer_qual + &reverse.qual,
+    };
+    joined.add_tag(JOIN_TAG, JoinKind::Concatenated.as_str());
+    joined
+}
+
+/// Merges the pair if the mates overlap, otherwise concatenates them around the spacer
+pub fn merge_or_concatenate(forward: &Sequence, reverse: &Sequence, oligo_size: usize, spacer: &Spacer) -> Sequence {
+    match merge(forward, reverse, oligo_size) {
+        Some(merged) => merged,
+        None => concatenate(forward, reverse, spacer),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn test_pair(forward: &str, reverse: &str) -> (Sequence, Sequence) {
+    let forward = Sequence {
+        header: "pair".to_string(),
+        bases: Bases::from_str(forward),
+        qual: iter::repeat('I').take(forward.len()).collect(),
+    };
+    let reverse = Sequence {
+        header: "pair".to_string(),
+        bases: Bases::from_str(reverse),
+        qual: iter::repeat('5').take(reverse.len()).collect(),
+    };
+    (forward, reverse)
+}
+
+#[test]
+fn merge_overlapping_pair() {
+    // The reverse read starts with the reverse complement of GATACA
+    let (forward, reverse) = test_pair("CCCGATACATT", "TGTATCGGG");
+
+    let merged = merge(&forward, &reverse, 6).unwrap();
+    assert_eq!(merged.bases, Bases::from_str("CCCGATACA"));
+    assert_eq!(merged.qual, "IIIIIIIII");
+    assert_eq!(merged.tag(JOIN_TAG), Some("merged"));
+}
+
+#[test]
+fn merge_fails_without_overlap() {
+    let (forward, reverse) = test_pair("CCCCCCCCCC", "AAAAAAAAAA");
+
+    assert!(merge(&forward, &reverse, 6).is_none());
+}
+
+#[test]
+fn merge_with_mismatch() {
+    // GATACA in the forward read carries one mismatch
+    let (forward, reverse) = test_pair("CCCGAAACATT", "TGTATCGGG");
+
+    let mut params = MergeParams::new(6);
+    assert_eq!(try_merge(&forward, &reverse, &params), Err(MergeFailure::TooManyMismatches));
+
+    params.max_mismatches = 1;
+    let merged = try_merge(&forward, &reverse, &params).unwrap();
+    assert_eq!(merged.bases, Bases::from_str("CCCGAAACA"));
+}
+
+#[test]
+fn merge_failure_reasons() {
+    let params = MergeParams::new(6);
+
+    // GATACA occurs twice in the forward read, which merges at the first unless rejected
+    let (forward, reverse) = test_pair("GATACAGATACA", "TGTATCGGG");
+    assert_eq!(try_merge(&forward, &reverse, &params).unwrap().bases, Bases::from_str("GATACA"));
+    let mut strict = params;
+    strict.reject_ambiguous = true;
+    assert_eq!(try_merge(&forward, &reverse, &strict), Err(MergeFailure::AmbiguousOverlap));
+
+    // Only GATA, the first 4 bases of GATACA, made it onto the end of the forward read
+    let (forward, reverse) = test_pair("CCCCCCGATA", "TGTATCGGG");
+    assert_eq!(try_merge(&forward, &reverse, &params), Err(MergeFailure::ShortOverlap));
+
+    let (forward, reverse) = test_pair("CCCC", "TGTATCGGG");
+    assert_eq!(try_merge(&forward, &reverse, &params), Err(MergeFailure::ReadTooShort));
+
+    let (forward, reverse) = test_pair("CCCNNCCCCC", "TGTATCGGG");
+    assert_eq!(try_merge(&forward, &reverse, &params), Err(MergeFailure::HighN));
+
+    let (forward, reverse) = test_pair("CCCCCCCCCC", "TGTATCGGG");
+    assert_eq!(try_merge(&forward, &reverse, &params), Err(MergeFailure::NoOverlap));
+}
+
+#[test]
+fn concatenate_pair() {
+    let (forward, reverse) = test_pair("GATT", "AAC");
+
+    let joined = concatenate(&forward, &reverse, &Spacer::new(3, 2));
+    assert_eq!(joined.bases, Bases::from_str("GATTNNNGTT"));
+    assert_eq!(joined.qual, "IIII###555");
+    assert_eq!(joined.tag(JOIN_TAG), Some("concatenated"));
+}
+
+#[test]
+fn merge_or_concatenate_falls_back() {
+    let (forward, reverse) = test_pair("CCCCCCCCCC", "AAAAAAAAAA");
+
+    let joined = merge_or_concatenate(&forward, &reverse, 6, &Spacer::new(1, 2));
+    assert_eq!(JoinKind::from_str(joined.tag(JOIN_TAG).unwrap()), Some(JoinKind::Concatenated));
+    assert_eq!(joined.bases.len(), 21);
+}
@@ -2,5 +2,32 @@
 #![feature(convert)]
 #![feature(core)]
 
+extern crate getopts;
+extern crate rustc_serialize;
+
+pub mod align;
+pub mod assign;
+pub mod balance;
 pub mod bases;
+pub mod calls;
+pub mod chimera;
+pub mod cli;
+pub mod concordance;
+pub mod consensus;
+pub mod contamination;
+pub mod denoise;
+pub mod export;
+pub mod fasta;
 pub mod fastq;
+pub mod find;
+pub mod genotype;
+pub mod join;
+pub mod loci;
+pub mod metadata;
+pub mod parentage;
+pub mod pileup;
+pub mod popstats;
+pub mod qc;
+pub mod sex;
+pub mod ssr;
+pub mod vcf;
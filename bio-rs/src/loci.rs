@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::io;
+
+/// The loci of a panel, either one name per line or a tab separated table
+/// A table's first line names the columns, the first of which must be "locus"
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocusTable {
+    pub columns: Vec<String>,
+    /// Locus names in the order they were listed
+    pub loci: Vec<String>,
+    rows: HashMap<String, Vec<String>>,
+}
+
+impl LocusTable {
+    pub fn read<R: io::Read>(table: &mut io::BufReader<R>) -> io::Result<LocusTable> {
+        use std::io::BufRead;
+
+        let mut columns = vec!["locus".to_string()];
+        let mut loci = vec!();
+        let mut rows = HashMap::new();
+        for (n, line) in table.lines().enumerate() {
+            let line = try!(line);
+            let line = line.trim_right();
+            if line.len() == 0 || line.starts_with("#") {
+                continue;
+            }
+
+            let row: Vec<String> = line.split('\t').map(|field| field.trim().to_string()).collect();
+            if loci.len() == 0 && columns.len() == 1 && row[0] == "locus" {
+                columns = row;
+                continue;
+            }
+            if row.len() > columns.len() {
+                let message = format!("Locus table line {} has {} columns, expected {}", n + 1, row.len(), columns.len());
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+            loci.push(row[0].clone());
+            rows.insert(row[0].clone(), row);
+        }
+
+        Ok(LocusTable {
+            columns: columns,
+            loci: loci,
+            rows: rows,
+        })
+    }
+
+    pub fn has_column(&self, column: &str) -> bool {
+        self.columns.iter().any(|c| c == column)
+    }
+
+    /// Gets a locus's value in a column, None if either isn't listed or the value is empty
+    pub fn get(&self, locus: &str, column: &str) -> Option<&str> {
+        let column = match self.columns.iter().position(|c| c == column) {
+            Some(column) => column,
+            None => return None,
+        };
+        self.rows.get(locus).and_then(|row| row.get(column)).map(|value| &value[..]).and_then(|value| {
+            if value.len() > 0 { Some(value) } else { None }
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_read_locus_list() {
+    let mut list = io::BufReader::new(&b"L1\nL2 \n\nL3\n"[..]);
+
+    let table = LocusTable::read(&mut list).unwrap();
+    assert_eq!(table.loci, vec!["L1".to_string(), "L2".to_string(), "L3".to_string()]);
+    assert_eq!(table.get("L1", "type"), None);
+}
+
+#[test]
+fn test_read_locus_table() {
+    let mut list = io::BufReader::new(&b"locus\ttype\tmotif\nL1\nL2\tssr\tAC\nL3\tssr\n"[..]);
+
+    let table = LocusTable::read(&mut list).unwrap();
+    assert_eq!(table.loci, vec!["L1".to_string(), "L2".to_string(), "L3".to_string()]);
+    assert!(table.has_column("motif"));
+    assert_eq!(table.get("L1", "type"), None);
+    assert_eq!(table.get("L2", "motif"), Some("AC"));
+    assert_eq!(table.get("L3", "motif"), None);
+}
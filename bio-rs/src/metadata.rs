@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::io;
+
+/// A tab separated table of facts about samples, one row per sample
+/// The first line names the columns, one of which must be "sample"
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleMetadata {
+    pub columns: Vec<String>,
+    /// Sample names in the order they were listed
+    pub samples: Vec<String>,
+    rows: HashMap<String, Vec<String>>,
+}
+
+impl SampleMetadata {
+    pub fn read<R: io::Read>(metadata: &mut io::BufReader<R>) -> io::Result<SampleMetadata> {
+        use std::io::BufRead;
+
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+
+        let mut lines = metadata.lines();
+        let columns: Vec<String> = match lines.next() {
+            Some(line) => try!(line).trim_right_matches('\r').split('\t').map(|column| column.to_string()).collect(),
+            None => return Err(invalid("Sample metadata is empty".to_string())),
+        };
+        let sample_column = match columns.iter().position(|column| column == "sample") {
+            Some(column) => column,
+            None => return Err(invalid("Sample metadata has no sample column".to_string())),
+        };
+
+        let mut samples = vec!();
+        let mut rows = HashMap::new();
+        for (n, line) in lines.enumerate() {
+            let line = try!(line);
+            // Only the line ending is stripped, trailing tabs end empty columns
+            let line = line.trim_right_matches('\r');
+            if line.len() == 0 || line.starts_with("#") {
+                continue;
+            }
+
+            let row: Vec<String> = line.split('\t').map(|field| field.to_string()).collect();
+            if row.len() != columns.len() {
+                return Err(invalid(format!("Sample metadata line {} has {} columns, expected {}", n + 2, row.len(), columns.len())));
+            }
+            samples.push(row[sample_column].clone());
+            rows.insert(row[sample_column].clone(), row);
+        }
+
+        Ok(SampleMetadata {
+            columns: columns,
+            samples: samples,
+            rows: rows,
+        })
+    }
+
+    pub fn has_column(&self, column: &str) -> bool {
+        self.columns.iter().any(|c| c == column)
+    }
+
+    /// Gets a sample's value in a column, None if either isn't listed or the value is empty
+    pub fn get(&self, sample: &str, column: &str) -> Option<&str> {
+        let column = match self.columns.iter().position(|c| c == column) {
+            Some(column) => column,
+            None => return None,
+        };
+        self.rows.get(sample).map(|row| &row[column][..]).and_then(|value| {
+            if value.len() > 0 { Some(value) } else { None }
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_read_metadata() {
+    let mut table = io::BufReader::new(&b"sample\tpopulation\tplate\r\ns1\tnorth\tP1\n\ns2\t\tP1\ns3\tsouth\t\n"[..]);
+
+    let metadata = SampleMetadata::read(&mut table).unwrap();
+    assert_eq!(metadata.samples, vec!["s1".to_string(), "s2".to_string(), "s3".to_string()]);
+    assert!(metadata.has_column("plate"));
+    assert_eq!(metadata.get("s1", "population"), Some("north"));
+    assert_eq!(metadata.get("s2", "population"), None);
+    assert_eq!(metadata.get("s1", "plate"), Some("P1"));
+    assert_eq!(metadata.get("s3", "plate"), None);
+    assert_eq!(metadata.get("s4", "plate"), None);
+}
+
+#[test]
+fn test_read_metadata_without_sample_column() {
+    let mut table = io::BufReader::new(&b"name\tpopulation\ns1\tnorth\n"[..]);
+
+    assert!(SampleMetadata::read(&mut table).is_err());
+}
@@ -0,0 +1,193 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use concordance::GenotypeTable;
+
+/// How well one candidate parent, or a pair of them, explains an offspring's genotypes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assignment {
+    pub offspring: String,
+    pub parents: Vec<String>,
+    /// Diploid loci called in the offspring and every candidate
+    pub loci: usize,
+    /// Loci where the offspring couldn't have inherited its genotype from the candidates
+    pub exclusions: usize,
+    /// Log10 likelihood ratio of the candidates being the parents over being unrelated
+    pub lod: f64,
+}
+
+/// Scores candidate parents of offspring from their genotype calls
+pub struct Parentage<'a> {
+    table: &'a GenotypeTable,
+    loci: Vec<String>,
+    /// Allele frequencies of every locus, over every sample's diploid calls
+    frequencies: HashMap<String, HashMap<String, f64>>,
+    /// Chance an offspring genotype is a random draw from the population instead of inherited
+    error_rate: f64,
+}
+
+impl<'a> Parentage<'a> {
+    pub fn new(table: &'a GenotypeTable, error_rate: f64) -> Parentage<'a> {
+        let loci = table.loci();
+        let mut frequencies = HashMap::new();
+        for locus in &loci {
+            let mut counts: HashMap<String, f64> = HashMap::new();
+            let mut total = 0.0;
+            for sample in &table.samples {
+                if let Some(alleles) = table.get(sample, locus) {
+                    if alleles.len() != 2 {
+                        continue;
+                    }
+                    for allele in alleles {
+                        *counts.entry(allele.clone()).or_insert(0.0) += 1.0;
+                        total += 1.0;
+                    }
+                }
+            }
+            for count in counts.values_mut() {
+                *count /= total;
+            }
+            frequencies.insert(locus.clone(), counts);
+        }
+
+        Parentage {
+            table: table,
+            loci: loci,
+            frequencies: frequencies,
+            error_rate: error_rate,
+        }
+    }
+
+    pub fn frequency(&self, locus: &str, allele: &str) -> f64 {
+        self.frequencies.get(locus).and_then(|counts| counts.get(allele)).map(|&f| f).unwrap_or(0.0)
+    }
+
+    /// Chance of an offspring genotype given its parents' genotypes, an unknown parent passing on
+    /// alleles at their population frequencies
+    fn transmission(&self, locus: &str, offspring: &[String], parents: &[Option<&[String]>; 2]) -> f64 {
+        let passes = |parent: Option<&[String]>, allele: &str| -> f64 {
+            match parent {
+                Some(alleles) => alleles.iter().filter(|a| *a == allele).count() as f64 / alleles.len() as f64,
+                None => self.frequency(locus, allele),
+            }
+        };
+        let (a, b) = (&offspring[0], &offspring[1]);
+        let mut chance = passes(parents[0], a) * passes(parents[1], b);
+        if a != b {
+            chance += passes(parents[0], b) * passes(parents[1], a);
+        }
+        chance
+    }
+
+    /// Scores one or two candidate parents of an offspring over the loci called in all of them
+    pub fn assess(&self, offspring: &str, parents: &[&str]) -> Assignment {
+        let mut assignment = Assignment {
+            offspring: offspring.to_string(),
+            parents: parents.iter().map(|parent| parent.to_string()).collect(),
+            loci: 0,
+            exclusions: 0,
+            lod: 0.0,
+        };
+
+        for locus in &self.loci {
+            let child = match self.table.get(offspring, locus) {
+                Some(alleles) if alleles.len() == 2 => alleles,
+                _ => continue,
+            };
+            let genotypes: Vec<Option<&[String]>> = parents.iter().map(|parent| self.table.get(parent, locus)).collect();
+            if genotypes.iter().any(|genotype| genotype.map(|alleles| alleles.len() != 2).unwrap_or(true)) {
+                continue;
+            }
+
+            let known = [genotypes.get(0).and_then(|&g| g), genotypes.get(1).and_then(|&g| g)];
+            let inherited = self.transmission(locus, child, &known);
+            let unrelated = self.transmission(locus, child, &[None, None]);
+
+            assignment.loci += 1;
+            if inherited == 0.0 {
+                assignment.exclusions += 1;
+            }
+            assignment.lod += (((1.0 - self.error_rate) * inherited + self.error_rate * unrelated) / unrelated).log10();
+        }
+        assignment
+    }
+}
+
+/// Sorts assignments most likely first, by LOD then fewest exclusions
+pub fn rank(assignments: &mut Vec<Assignment>) {
+    assignments.sort_by(|a, b| {
+        match b.lod.partial_cmp(&a.lod).unwrap() {
+            Ordering::Equal => match a.exclusions.cmp(&b.exclusions) {
+                Ordering::Equal => a.parents.cmp(&b.parents),
+                order => order,
+            },
+            order => order,
+        }
+    });
+}
+
+/// LOD of the most likely of ranked assignments over the next most likely, or its own LOD when
+/// there's no other; None without assignments
+pub fn delta(ranked: &[Assignment]) -> Option<f64> {
+    match ranked.len() {
+        0 => None,
+        1 => Some(ranked[0].lod),
+        _ => Some(ranked[0].lod - ranked[1].lod),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn table_of(genotypes: &[(&str, &str, &str, &str)]) -> GenotypeTable {
+    use calls::{self, Call};
+
+    let calls: Vec<Call> = genotypes.iter().map(|&(sample, locus, a, b)| calls::test_call(sample, locus, &[a, b])).collect();
+    GenotypeTable::new(&calls)
+}
+
+#[test]
+fn test_transmission() {
+    let table = table_of(&[("O", "L1", "A", "C"), ("M", "L1", "A", "A"), ("F", "L1", "C", "G"), ("X", "L1", "G", "G")]);
+    let parentage = Parentage::new(&table, 0.0);
+    assert_eq!(parentage.frequency("L1", "A"), 0.375);
+
+    let child = ["A".to_string(), "C".to_string()];
+    let mother = ["A".to_string(), "A".to_string()];
+    let father = ["C".to_string(), "G".to_string()];
+    // A from the mother, C at its population frequency of 1/4
+    assert_eq!(parentage.transmission("L1", &child, &[Some(&mother[..]), None]), 0.25);
+    assert_eq!(parentage.transmission("L1", &child, &[Some(&mother[..]), Some(&father[..])]), 0.5);
+    assert_eq!(parentage.transmission("L1", &child, &[None, None]), 2.0 * 0.375 * 0.25);
+}
+
+#[test]
+fn test_parentage() {
+    let table = table_of(&[
+        ("O", "L1", "A", "C"), ("O", "L2", "G", "T"), ("O", "L3", "A", "A"),
+        ("M", "L1", "A", "A"), ("M", "L2", "G", "G"), ("M", "L3", "A", "C"),
+        ("F", "L1", "C", "C"), ("F", "L2", "T", "T"), ("F", "L3", "A", "T"),
+        ("X", "L1", "G", "G"), ("X", "L2", "T", "T"), ("X", "L3", "C", "C"),
+    ]);
+    let parentage = Parentage::new(&table, 0.01);
+
+    let mother = parentage.assess("O", &["M"]);
+    assert_eq!((mother.loci, mother.exclusions), (3, 0));
+    assert!(mother.lod > 0.0);
+    let stranger = parentage.assess("O", &["X"]);
+    assert_eq!(stranger.exclusions, 2);
+    assert!(stranger.lod < 0.0);
+
+    // F alone is compatible, but not alongside X
+    assert_eq!(parentage.assess("O", &["F"]).exclusions, 0);
+    assert_eq!(parentage.assess("O", &["M", "F"]).exclusions, 0);
+    assert_eq!(parentage.assess("O", &["F", "X"]).exclusions, 3);
+
+    let mut pairs = vec![parentage.assess("O", &["F", "X"]), parentage.assess("O", &["M", "F"]), parentage.assess("O", &["M", "X"])];
+    rank(&mut pairs);
+    assert_eq!(pairs[0].parents, vec!["M".to_string(), "F".to_string()]);
+    assert!(delta(&pairs).unwrap() > 0.0);
+    assert_eq!(delta(&pairs[..1]), Some(pairs[0].lod));
+    assert_eq!(delta(&[]), None);
+}
@@ -0,0 +1,256 @@
+use std::cmp::Ordering;
+use std::io;
+
+use align::AlignOp;
+use bases::Base;
+use fastq::Sequence;
+
+/// Index of deletions in a column's weights, after A, C, G and T
+const DELETION: usize = 4;
+
+const COLUMN_BASES: [Base; 4] = [Base::A, Base::C, Base::G, Base::T];
+
+/// Quality weighted base counts at one reference position
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    /// Summed weights of A, C, G, T and deletions
+    pub weights: [f64; 5],
+    /// Reads covering the position
+    pub depth: usize,
+}
+
+/// Reads stacked on a reference, one column per reference base
+/// Insertions relative to the reference aren't counted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pileup {
+    pub columns: Vec<Column>,
+}
+
+impl Pileup {
+    pub fn new(reference_len: usize) -> Pileup {
+        Pileup { columns: vec![Column { weights: [0.0; 5], depth: 0 }; reference_len] }
+    }
+
+    /// Adds a read given its alignment to the reference
+    /// Each base counts for the chance its quality gives of it being right, N counts for nothing,
+    /// and deletions count fully; reference positions before or after the read aren't covered
+    pub fn add(&mut self, ops: &[AlignOp], read: &Sequence) {
+        let qualities = read.qual.as_bytes();
+        let first = ops.iter().position(|&op| op != AlignOp::Deletion).unwrap_or(ops.len());
+        let last = ops.iter().rposition(|&op| op != AlignOp::Deletion).map(|i| i + 1).unwrap_or(0);
+
+        let (mut r, mut q) = (0, 0);
+        for (i, &op) in ops.iter().enumerate() {
+            match op {
+                AlignOp::Match | AlignOp::Mismatch => {
+                    let column = &mut self.columns[r];
+                    column.depth += 1;
+                    if let Some(b) = COLUMN_BASES.iter().position(|&base| base == read.bases.bases[q]) {
+                        let quality = qualities.get(q).map(|&qual| qual.saturating_sub(33)).unwrap_or(0);
+                        column.weights[b] += 1.0 - 10.0f64.powf(-(quality as f64) / 10.0);
+                    }
+                    r += 1;
+                    q += 1;
+                },
+                AlignOp::Deletion => {
+                    if i > first && i < last {
+                        self.columns[r].depth += 1;
+                        self.columns[r].weights[DELETION] += 1.0;
+                    }
+                    r += 1;
+                },
+                AlignOp::Insertion => q += 1,
+            }
+        }
+    }
+
+    /// Calls the consensus, heterozygous where the second most weighted base has at least
+    /// het_fraction of a column's weight
+    pub fn consensus(&self, het_fraction: f64) -> PileupConsensus {
+        let mut consensus = PileupConsensus {
+            sequence: String::new(),
+            alleles: (String::new(), String::new()),
+            heterozygous_sites: vec!(),
+            allele_weights: (0.0, 0.0),
+            depth: self.columns.iter().map(|column| column.depth).collect(),
+        };
+
+        for (pos, column) in self.columns.iter().enumerate() {
+            let total = column.weights.iter().fold(0.0, |sum, &weight| sum + weight);
+            if total == 0.0 {
+                consensus.sequence.push('N');
+                consensus.alleles.0.push('N');
+                consensus.alleles.1.push('N');
+                continue;
+            }
+
+            // Most weight first, ties going to A, C, G, T then deletions
+            let mut order: Vec<usize> = (0..5).collect();
+            order.sort_by(|&a, &b| {
+                match column.weights[b].partial_cmp(&column.weights[a]).unwrap() {
+                    Ordering::Equal => a.cmp(&b),
+                    order => order,
+                }
+            });
+            let (major, minor) = (order[0], order[1]);
+            let heterozygous = column.weights[minor] > 0.0 && column.weights[minor] >= het_fraction * total;
+
+            let base = |i: usize| if i == DELETION { None } else { Some(COLUMN_BASES[i].to_char()) };
+            if heterozygous {
+                consensus.heterozygous_sites.push(pos);
+                consensus.allele_weights.0 += column.weights[major];
+                consensus.allele_weights.1 += column.weights[minor];
+                match (base(major), base(minor)) {
+                    (Some(a), Some(b)) => consensus.sequence.push(iupac(a, b)),
+                    (Some(a), None) | (None, Some(a)) => consensus.sequence.push(a),
+                    (None, None) => {},
+                }
+                if let Some(b) = base(minor) {
+                    consensus.alleles.1.push(b);
+                }
+            } else {
+                if let Some(b) = base(major) {
+                    consensus.sequence.push(b);
+                    consensus.alleles.1.push(b);
+                }
+            }
+            if let Some(b) = base(major) {
+                consensus.alleles.0.push(b);
+            }
+        }
+        consensus
+    }
+}
+
+/// Consensus of a pileup
+#[derive(Clone, Debug, PartialEq)]
+pub struct PileupConsensus {
+    /// Consensus with IUPAC codes at heterozygous SNPs, N where no read covers the reference
+    /// Deletions are left out, and at sites heterozygous for a deletion the base is kept
+    pub sequence: String,
+    /// Unphased sequences of the most and second most weighted bases at heterozygous sites
+    pub alleles: (String, String),
+    /// 0-based reference positions of heterozygous sites
+    pub heterozygous_sites: Vec<usize>,
+    /// Summed weights of the first and second allele over heterozygous sites
+    pub allele_weights: (f64, f64),
+    /// Reads covering each reference position
+    pub depth: Vec<usize>,
+}
+
+impl PileupConsensus {
+    pub fn is_heterozygous(&self) -> bool {
+        self.heterozygous_sites.len() > 0
+    }
+}
+
+/// The IUPAC code for either of two bases
+pub fn iupac(a: char, b: char) -> char {
+    let mut pair = [a, b];
+    pair.sort();
+    match (pair[0], pair[1]) {
+        (x, y) if x == y => x,
+        ('A', 'C') => 'M',
+        ('A', 'G') => 'R',
+        ('A', 'T') => 'W',
+        ('C', 'G') => 'S',
+        ('C', 'T') => 'Y',
+        ('G', 'T') => 'K',
+        _ => 'N',
+    }
+}
+
+/// Column labels of a positional consensus table
+pub const COLUMNS: &'static str = "locus\tsample\tdepth\treference\tconsensus\theterozygous_sites\tallele_1\tallele_2";
+
+pub fn write_header<W: io::Write>(table: &mut W) -> io::Result<()> {
+    writeln!(table, "{}", COLUMNS)
+}
+
+/// Writes a row of the positional consensus table
+/// reference says what the reads were aligned to, heterozygous sites are written 1-based
+pub fn write_consensus<W: io::Write>(
+    table: &mut W,
+    locus: &str,
+    sample: &str,
+    depth: usize,
+    reference: &str,
+    consensus: &PileupConsensus,
+) -> io::Result<()>
+{
+    let sites: Vec<String> = consensus.heterozygous_sites.iter().map(|pos| (pos + 1).to_string()).collect();
+    writeln!(
+        table,
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        locus,
+        sample,
+        depth,
+        reference,
+        consensus.sequence,
+        if sites.len() > 0 { sites.connect(",") } else { ".".to_string() },
+        consensus.alleles.0,
+        consensus.alleles.1,
+    )
+}
+
+/// Writes "<locus><TAB><sample><TAB><position><TAB><depth>" lines, positions 1-based
+pub fn write_depth<W: io::Write>(track: &mut W, locus: &str, sample: &str, depth: &[usize]) -> io::Result<()> {
+    for (pos, depth) in depth.iter().enumerate() {
+        try!(writeln!(track, "{}\t{}\t{}\t{}", locus, sample, pos + 1, depth));
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn pileup_of(reference: &str, reads: &[&str]) -> Pileup {
+    use align::{self, Scoring};
+    use bases::Bases;
+
+    let reference = Bases::from_str(reference);
+    let mut pileup = Pileup::new(reference.len());
+    for read in reads {
+        let read = Sequence { header: "r".to_string(), bases: Bases::from_str(read), qual: read.chars().map(|_| 'I').collect() };
+        let ops = align::global_align(&reference.bases, &read.bases.bases, &Scoring::new());
+        pileup.add(&ops, &read);
+    }
+    pileup
+}
+
+#[test]
+fn test_iupac() {
+    assert_eq!(iupac('A', 'G'), 'R');
+    assert_eq!(iupac('T', 'C'), 'Y');
+    assert_eq!(iupac('G', 'G'), 'G');
+}
+
+#[test]
+fn test_homozygous_consensus() {
+    let pileup = pileup_of("GATTACAGATTACA", &["GATTACAGATTACA", "GATTACAGATTACA", "GATTCCAGATTACA"]);
+
+    let consensus = pileup.consensus(0.4);
+    assert_eq!(consensus.sequence, "GATTACAGATTACA");
+    assert!(!consensus.is_heterozygous());
+    assert_eq!(consensus.depth, vec![3; 14]);
+}
+
+#[test]
+fn test_heterozygous_consensus() {
+    let pileup = pileup_of("GATTACAGATTACA", &["GATTACAGATTACA", "GATTACAGATTACA", "GATTCCAGATTACA", "GATTCCAGATTACA"]);
+
+    let consensus = pileup.consensus(0.2);
+    assert_eq!(consensus.sequence, "GATTMCAGATTACA");
+    assert_eq!(consensus.heterozygous_sites, vec![4]);
+    assert_eq!(consensus.alleles, ("GATTACAGATTACA".to_string(), "GATTCCAGATTACA".to_string()));
+}
+
+#[test]
+fn test_deletion_consensus() {
+    let pileup = pileup_of("GATTACAGATTACA", &["GATTACGATTACA", "GATTACGATTACA", "GATTACGATTACA"]);
+
+    let consensus = pileup.consensus(0.2);
+    assert_eq!(consensus.sequence, "GATTACGATTACA");
+    assert_eq!(consensus.depth, vec![3; 14]);
+}
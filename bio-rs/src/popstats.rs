@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Allele frequencies, heterozygosity and Hardy-Weinberg test of a locus in one population
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocusStats {
+    /// Diploid individuals typed
+    pub individuals: usize,
+    /// Copies of every allele seen, by sequence
+    pub allele_counts: BTreeMap<String, usize>,
+    pub observed_het: f64,
+    /// Heterozygosity expected under Hardy-Weinberg, corrected for sample size
+    pub expected_het: f64,
+    /// Exact test p-value, None when the locus is monomorphic
+    pub hwe_p_value: Option<f64>,
+}
+
+impl LocusStats {
+    /// Summarizes diploid genotypes of a population, testing multi-allelic loci by permutation
+    pub fn new(genotypes: &[&[String]], permutations: usize) -> LocusStats {
+        let mut allele_counts = BTreeMap::new();
+        let mut hets = 0;
+        for genotype in genotypes {
+            for allele in genotype.iter() {
+                *allele_counts.entry(allele.clone()).or_insert(0) += 1;
+            }
+            if genotype[0] != genotype[1] {
+                hets += 1;
+            }
+        }
+
+        let n = genotypes.len() as f64;
+        let copies = 2.0 * n;
+        let homozygosity = allele_counts.values().fold(0.0, |sum, &count| {
+            let p = count as f64 / copies;
+            sum + p * p
+        });
+
+        LocusStats {
+            individuals: genotypes.len(),
+            observed_het: if n > 0.0 { hets as f64 / n } else { 0.0 },
+            expected_het: if n > 0.0 { copies / (copies - 1.0) * (1.0 - homozygosity) } else { 0.0 },
+            hwe_p_value: hwe_exact(genotypes, &allele_counts, permutations),
+            allele_counts: allele_counts,
+        }
+    }
+
+    pub fn frequency(&self, allele: &str) -> f64 {
+        let copies = 2 * self.individuals;
+        self.allele_counts.get(allele).map(|&count| count as f64 / copies as f64).unwrap_or(0.0)
+    }
+}
+
+/// Hardy-Weinberg exact test of diploid genotypes, None with fewer than two alleles
+/// Biallelic loci are tested exactly (Wigginton et al. 2005), others by permuting alleles among
+/// individuals (Guo & Thompson 1992)
+fn hwe_exact(genotypes: &[&[String]], allele_counts: &BTreeMap<String, usize>, permutations: usize) -> Option<f64> {
+    if allele_counts.len() < 2 {
+        return None;
+    }
+
+    if allele_counts.len() == 2 {
+        let first = allele_counts.keys().next().unwrap();
+        let (mut hom1, mut hets, mut hom2) = (0, 0, 0);
+        for genotype in genotypes {
+            match (genotype[0] == *first, genotype[1] == *first) {
+                (true, true) => hom1 += 1,
+                (false, false) => hom2 += 1,
+                _ => hets += 1,
+            }
+        }
+        return Some(hwe_biallelic(hets, hom1, hom2));
+    }
+
+    // Alleles by index, two per individual
+    let index: HashMap<&String, usize> = allele_counts.keys().enumerate().map(|(i, allele)| (allele, i)).collect();
+    let mut alleles: Vec<usize> = genotypes.iter().flat_map(|genotype| genotype.iter().map(|allele| index[allele])).collect();
+    let observed = genotype_log_probability(&alleles);
+
+    let mut random = XorShift(0x9e3779b97f4a7c15);
+    let mut as_extreme = 0;
+    for _ in 0..permutations {
+        for i in (1..alleles.len()).rev() {
+            let j = (random.next() % (i as u64 + 1)) as usize;
+            alleles.swap(i, j);
+        }
+        if genotype_log_probability(&alleles) <= observed + 1e-9 {
+            as_extreme += 1;
+        }
+    }
+    Some((as_extreme + 1) as f64 / (permutations + 1) as f64)
+}
+
+/// Log probability of pairing consecutive alleles into genotypes, up to terms fixed by the allele
+/// counts: ln(2) per heterozygote less ln(count!) of every genotype
+fn genotype_log_probability(alleles: &[usize]) -> f64 {
+    let mut counts: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut hets = 0;
+    for pair in alleles.chunks(2) {
+        let genotype = if pair[0] <= pair[1] { (pair[0], pair[1]) } else { (pair[1], pair[0]) };
+        if genotype.0 != genotype.1 {
+            hets += 1;
+        }
+        *counts.entry(genotype).or_insert(0) += 1;
+    }
+    let log_factorial = |n: usize| (2..(n + 1)).fold(0.0, |sum, i| sum + (i as f64).ln());
+    counts.values().fold(hets as f64 * 2.0f64.ln(), |sum, &count| sum - log_factorial(count))
+}
+
+/// Exact Hardy-Weinberg test of a biallelic locus: the chance, given the allele counts, of
+/// heterozygote counts no likelier than the one observed
+pub fn hwe_biallelic(hets: usize, hom1: usize, hom2: usize) -> f64 {
+    let (rare_homs, common_homs) = if hom1 < hom2 { (hom1, hom2) } else { (hom2, hom1) };
+    let n = hets + rare_homs + common_homs;
+    let rare = 2 * rare_homs + hets;
+    if n == 0 {
+        return 1.0;
+    }
+
+    // Start at the likeliest heterozygote count and walk out both ways
+    let mut probabilities = vec![0.0; rare + 1];
+    let mut mid = rare * (2 * n - rare) / (2 * n);
+    if mid % 2 != rare % 2 {
+        mid += 1;
+    }
+    probabilities[mid] = 1.0;
+
+    let (mut h, mut r, mut c) = (mid, (rare - mid) / 2, n - mid - (rare - mid) / 2);
+    while h >= 2 {
+        probabilities[h - 2] = probabilities[h] * (h * (h - 1)) as f64 / (4 * (r + 1) * (c + 1)) as f64;
+        h -= 2;
+        r += 1;
+        c += 1;
+    }
+    let (mut h, mut r, mut c) = (mid, (rare - mid) / 2, n - mid - (rare - mid) / 2);
+    while h + 2 <= rare {
+        probabilities[h + 2] = probabilities[h] * (4 * r * c) as f64 / ((h + 2) * (h + 1)) as f64;
+        h += 2;
+        r -= 1;
+        c -= 1;
+    }
+
+    let total = probabilities.iter().fold(0.0, |sum, &p| sum + p);
+    let observed = probabilities[hets];
+    let p = probabilities.iter().filter(|&&p| p <= observed * (1.0 + 1e-9)).fold(0.0, |sum, &p| sum + p) / total;
+    if p > 1.0 { 1.0 } else { p }
+}
+
+/// Weir & Cockerham (1984) variance components, summed over alleles and optionally loci
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FstComponents {
+    /// Between populations
+    pub a: f64,
+    /// Between individuals within populations
+    pub b: f64,
+    /// Within individuals
+    pub c: f64,
+}
+
+impl FstComponents {
+    pub fn zero() -> FstComponents {
+        FstComponents { a: 0.0, b: 0.0, c: 0.0 }
+    }
+
+    /// Components of diploid genotypes at one locus in two or more populations, zero when any
+    /// population has fewer than two individuals
+    pub fn new(populations: &[Vec<&[String]>]) -> FstComponents {
+        let mut components = FstComponents::zero();
+        let r = populations.len() as f64;
+        if populations.len() < 2 || populations.iter().any(|genotypes| genotypes.len() < 2) {
+            return components;
+        }
+
+        let sizes: Vec<f64> = populations.iter().map(|genotypes| genotypes.len() as f64).collect();
+        let total = sizes.iter().fold(0.0, |sum, &n| sum + n);
+        let n_bar = total / r;
+        let n_c = (total - sizes.iter().fold(0.0, |sum, &n| sum + n * n) / total) / (r - 1.0);
+
+        let mut alleles: Vec<&String> = populations.iter().flat_map(|genotypes| genotypes.iter().flat_map(|g| g.iter())).collect();
+        alleles.sort();
+        alleles.dedup();
+
+        for allele in alleles {
+            // Frequency of the allele and of heterozygotes carrying it, per population
+            let (mut p, mut h): (Vec<f64>, Vec<f64>) = (vec!(), vec!());
+            for genotypes in populations {
+                let n = genotypes.len() as f64;
+                let copies = genotypes.iter().fold(0, |sum, g| sum + g.iter().filter(|a| *a == allele).count());
+                let hets = genotypes.iter().filter(|g| (g[0] == *allele) != (g[1] == *allele)).count();
+                p.push(copies as f64 / (2.0 * n));
+                h.push(hets as f64 / n);
+            }
+
+            let p_bar = (0..p.len()).fold(0.0, |sum, i| sum + sizes[i] * p[i]) / total;
+            let s2 = (0..p.len()).fold(0.0, |sum, i| sum + sizes[i] * (p[i] - p_bar) * (p[i] - p_bar)) / ((r - 1.0) * n_bar);
+            let h_bar = (0..h.len()).fold(0.0, |sum, i| sum + sizes[i] * h[i]) / total;
+            let pq = p_bar * (1.0 - p_bar);
+
+            components.a += n_bar / n_c * (s2 - (pq - (r - 1.0) / r * s2 - h_bar / 4.0) / (n_bar - 1.0));
+            components.b += n_bar / (n_bar - 1.0) * (pq - (r - 1.0) / r * s2 - (2.0 * n_bar - 1.0) / (4.0 * n_bar) * h_bar);
+            components.c += h_bar / 2.0;
+        }
+        components
+    }
+
+    pub fn add(&mut self, other: &FstComponents) {
+        self.a += other.a;
+        self.b += other.b;
+        self.c += other.c;
+    }
+
+    /// The F_ST estimate theta, None without variation
+    pub fn fst(&self) -> Option<f64> {
+        let total = self.a + self.b + self.c;
+        if total > 0.0 { Some(self.a / total) } else { None }
+    }
+}
+
+/// Small deterministic generator for permutation tests, so reruns give the same p-values
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn genotypes_of(counts: &[(&str, &str, usize)]) -> Vec<Vec<String>> {
+    let mut genotypes = vec!();
+    for &(a, b, count) in counts {
+        for _ in 0..count {
+            genotypes.push(vec![a.to_string(), b.to_string()]);
+        }
+    }
+    genotypes
+}
+
+#[cfg(test)]
+fn view(genotypes: &[Vec<String>]) -> Vec<&[String]> {
+    genotypes.iter().map(|g| &g[..]).collect()
+}
+
+#[test]
+fn test_hwe_biallelic() {
+    // Of 3 individuals with 2 A and 4 B, AA/BB/BB has a chance of 0.2 and AB/AB/BB 0.8
+    assert!((hwe_biallelic(0, 1, 2) - 0.2).abs() < 1e-9);
+    assert!((hwe_biallelic(2, 0, 1) - 1.0).abs() < 1e-9);
+    assert!(hwe_biallelic(50, 0, 0) < 1e-10);
+}
+
+#[test]
+fn test_locus_stats() {
+    let genotypes = genotypes_of(&[("A", "A", 25), ("A", "C", 50), ("C", "C", 25)]);
+    let genotypes = view(&genotypes);
+    let stats = LocusStats::new(&genotypes, 100);
+    assert_eq!(stats.individuals, 100);
+    assert_eq!(stats.frequency("A"), 0.5);
+    assert_eq!(stats.observed_het, 0.5);
+    assert!((stats.expected_het - 0.5 * 200.0 / 199.0).abs() < 1e-9);
+    assert!(stats.hwe_p_value.unwrap() > 0.5);
+
+    // No homozygotes of three equally common alleles
+    let excess = genotypes_of(&[("A", "C", 10), ("A", "G", 10), ("C", "G", 10)]);
+    let excess = view(&excess);
+    assert!(LocusStats::new(&excess, 1000).hwe_p_value.unwrap() < 0.01);
+
+    let balanced = genotypes_of(&[("A", "A", 7), ("C", "C", 7), ("G", "G", 6), ("A", "C", 13), ("A", "G", 14), ("C", "G", 13)]);
+    let balanced = view(&balanced);
+    assert!(LocusStats::new(&balanced, 1000).hwe_p_value.unwrap() > 0.05);
+
+    let monomorphic = genotypes_of(&[("A", "A", 10)]);
+    let monomorphic = view(&monomorphic);
+    assert_eq!(LocusStats::new(&monomorphic, 100).hwe_p_value, None);
+}
+
+#[test]
+fn test_fst() {
+    let fixed_a = genotypes_of(&[("A", "A", 10)]);
+    let fixed_c = genotypes_of(&[("C", "C", 10)]);
+    let mixed = genotypes_of(&[("A", "A", 3), ("A", "C", 4), ("C", "C", 3)]);
+
+    let fixed = FstComponents::new(&[view(&fixed_a), view(&fixed_c)]);
+    assert!((fixed.fst().unwrap() - 1.0).abs() < 1e-9);
+
+    let same = FstComponents::new(&[view(&mixed), view(&mixed)]);
+    assert!(same.fst().unwrap() < 0.0);
+
+    let mut total = FstComponents::zero();
+    total.add(&fixed);
+    total.add(&same);
+    assert!(total.fst().unwrap() > 0.0 && total.fst().unwrap() < 1.0);
+    assert_eq!(FstComponents::new(&[view(&fixed_a), view(&fixed_a)]).fst(), None);
+}
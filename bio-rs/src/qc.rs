@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use rustc_serialize::json::{Json, ToJson};
+
+/// What was seen for one sample at one locus
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    /// Reads assigned to the sample at the locus
+    pub depth: usize,
+    /// Reads of the two haplotypes with the most reads
+    pub top_two_reads: usize,
+    /// Called allele sequences, one per copy, empty if the sample wasn't called
+    pub alleles: Vec<String>,
+    /// Reads of each called allele, in the same order
+    pub allele_reads: Vec<u32>,
+}
+
+impl Observation {
+    pub fn is_called(&self) -> bool {
+        self.alleles.len() > 0
+    }
+
+    pub fn is_heterozygous(&self) -> bool {
+        self.alleles.iter().any(|allele| *allele != self.alleles[0])
+    }
+
+    /// Reads of the less supported allele over reads of both, for heterozygous calls
+    pub fn allele_balance(&self) -> Option<f64> {
+        if !self.is_heterozygous() {
+            return None;
+        }
+        let mut reads: Vec<u32> = vec!();
+        for (allele, &count) in self.alleles.iter().zip(self.allele_reads.iter()) {
+            if self.alleles.iter().position(|a| a == allele) == Some(reads.len()) {
+                reads.push(count);
+            }
+        }
+        reads.sort();
+        let minor = reads[reads.len() - 2] as f64;
+        let major = reads[reads.len() - 1] as f64;
+        if minor + major > 0.0 { Some(minor / (minor + major)) } else { None }
+    }
+}
+
+/// Quality figures for one sample across loci
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleQc {
+    pub sample: String,
+    /// Reads of the sample, including reads for loci that aren't listed
+    pub total_reads: usize,
+    /// Fraction of the sample's reads assigned to a listed locus
+    pub on_target_fraction: f64,
+    pub loci_genotyped: usize,
+    pub call_rate: f64,
+    /// Mean reads per listed locus
+    pub mean_depth: f64,
+    /// Fraction of genotyped loci called heterozygous
+    pub observed_heterozygosity: f64,
+}
+
+/// Quality figures for one locus across samples
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocusQc {
+    pub locus: String,
+    pub mean_depth: f64,
+    pub median_depth: f64,
+    pub call_rate: f64,
+    /// Distinct alleles among the calls
+    pub alleles: usize,
+    pub heterozygotes: usize,
+    /// Quartiles (first, median, third) of allele balance in heterozygous calls
+    pub allele_balance: Option<(f64, f64, f64)>,
+    /// Fraction of the locus's reads belonging to each sample's top two haplotypes
+    pub top_two_fraction: f64,
+}
+
+/// Summarizes a sample from its observations at every listed locus
+/// off_target_reads are the sample's reads that couldn't be assigned to a listed locus
+pub fn sample_qc(sample: &str, observations: &[&Observation], off_target_reads: usize) -> SampleQc {
+    let on_target: usize = observations.iter().map(|o| o.depth).fold(0, |sum, depth| sum + depth);
+    let total = on_target + off_target_reads;
+    let genotyped = observations.iter().filter(|o| o.is_called()).count();
+    let heterozygous = observations.iter().filter(|o| o.is_heterozygous()).count();
+
+    SampleQc {
+        sample: sample.to_string(),
+        total_reads: total,
+        on_target_fraction: fraction(on_target, total),
+        loci_genotyped: genotyped,
+        call_rate: fraction(genotyped, observations.len()),
+        mean_depth: fraction(on_target, observations.len()),
+        observed_heterozygosity: fraction(heterozygous, genotyped),
+    }
+}
+
+/// Summarizes a locus from its observations in every listed sample
+pub fn locus_qc(locus: &str, observations: &[&Observation]) -> LocusQc {
+    let depths: Vec<f64> = observations.iter().map(|o| o.depth as f64).collect();
+    let total: usize = observations.iter().map(|o| o.depth).fold(0, |sum, depth| sum + depth);
+    let top_two: usize = observations.iter().map(|o| o.top_two_reads).fold(0, |sum, reads| sum + reads);
+
+    let mut alleles: Vec<&String> = observations.iter().flat_map(|o| o.alleles.iter()).collect();
+    alleles.sort();
+    alleles.dedup();
+
+    let balances: Vec<f64> = observations.iter().filter_map(|o| o.allele_balance()).collect();
+    let allele_balance = if balances.len() > 0 {
+        Some((quantile(&balances, 0.25), quantile(&balances, 0.5), quantile(&balances, 0.75)))
+    } else {
+        None
+    };
+
+    LocusQc {
+        locus: locus.to_string(),
+        mean_depth: fraction(total, observations.len()),
+        median_depth: if depths.len() > 0 { quantile(&depths, 0.5) } else { 0.0 },
+        call_rate: fraction(observations.iter().filter(|o| o.is_called()).count(), observations.len()),
+        alleles: alleles.len(),
+        heterozygotes: observations.iter().filter(|o| o.is_heterozygous()).count(),
+        allele_balance: allele_balance,
+        top_two_fraction: fraction(top_two, total),
+    }
+}
+
+fn fraction(numerator: usize, denominator: usize) -> f64 {
+    if denominator > 0 { numerator as f64 / denominator as f64 } else { 0.0 }
+}
+
+/// Quantile of values by linear interpolation between closest ranks
+pub fn quantile(values: &[f64], q: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let rank = q * (sorted.len() - 1) as f64;
+    let below = rank.floor() as usize;
+    let above = rank.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+pub const SAMPLE_COLUMNS: &'static str =
+    "sample\ttotal_reads\ton_target_fraction\tloci_genotyped\tcall_rate\tmean_depth\tobserved_heterozygosity";
+
+pub const LOCUS_COLUMNS: &'static str =
+    "locus\tmean_depth\tmedian_depth\tcall_rate\talleles\theterozygotes\tallele_balance_q1\tallele_balance_median\tallele_balance_q3\ttop_two_fraction";
+
+pub fn write_sample_qc<W: io::Write>(table: &mut W, qc: &SampleQc) -> io::Result<()> {
+    writeln!(
+        table,
+        "{}\t{}\t{:.4}\t{}\t{:.4}\t{:.1}\t{:.4}",
+        qc.sample,
+        qc.total_reads,
+        qc.on_target_fraction,
+        qc.loci_genotyped,
+        qc.call_rate,
+        qc.mean_depth,
+        qc.observed_heterozygosity,
+    )
+}
+
+pub fn write_locus_qc<W: io::Write>(table: &mut W, qc: &LocusQc) -> io::Result<()> {
+    let balance = match qc.allele_balance {
+        Some((q1, median, q3)) => format!("{:.4}\t{:.4}\t{:.4}", q1, median, q3),
+        None => "NA\tNA\tNA".to_string(),
+    };
+    writeln!(
+        table,
+        "{}\t{:.1}\t{:.1}\t{:.4}\t{}\t{}\t{}\t{:.4}",
+        qc.locus,
+        qc.mean_depth,
+        qc.median_depth,
+        qc.call_rate,
+        qc.alleles,
+        qc.heterozygotes,
+        balance,
+        qc.top_two_fraction,
+    )
+}
+
+impl ToJson for SampleQc {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        object.insert("sample".to_string(), self.sample.to_json());
+        object.insert("total_reads".to_string(), self.total_reads.to_json());
+        object.insert("on_target_fraction".to_string(), self.on_target_fraction.to_json());
+        object.insert("loci_genotyped".to_string(), self.loci_genotyped.to_json());
+        object.insert("call_rate".to_string(), self.call_rate.to_json());
+        object.insert("mean_depth".to_string(), self.mean_depth.to_json());
+        object.insert("observed_heterozygosity".to_string(), self.observed_heterozygosity.to_json());
+        Json::Object(object)
+    }
+}
+
+impl ToJson for LocusQc {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        object.insert("locus".to_string(), self.locus.to_json());
+        object.insert("mean_depth".to_string(), self.mean_depth.to_json());
+        object.insert("median_depth".to_string(), self.median_depth.to_json());
+        object.insert("call_rate".to_string(), self.call_rate.to_json());
+        object.insert("alleles".to_string(), self.alleles.to_json());
+        object.insert("heterozygotes".to_string(), self.heterozygotes.to_json());
+        let balance = match self.allele_balance {
+            Some((q1, median, q3)) => {
+                let mut quartiles = BTreeMap::new();
+                quartiles.insert("q1".to_string(), q1.to_json());
+                quartiles.insert("median".to_string(), median.to_json());
+                quartiles.insert("q3".to_string(), q3.to_json());
+                Json::Object(quartiles)
+            },
+            None => Json::Null,
+        };
+        object.insert("allele_balance".to_string(), balance);
+        object.insert("top_two_fraction".to_string(), self.top_two_fraction.to_json());
+        Json::Object(object)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn observation(depth: usize, alleles: &[&str], allele_reads: &[u32]) -> Observation {
+    Observation {
+        depth: depth,
+        top_two_reads: allele_reads.iter().fold(0, |sum, &reads| sum + reads as usize),
+        alleles: alleles.iter().map(|allele| allele.to_string()).collect(),
+        allele_reads: allele_reads.to_vec(),
+    }
+}
+
+#[test]
+fn test_allele_balance() {
+    assert_eq!(observation(100, &["GATT", "GACA"], &[60, 40]).allele_balance(), Some(0.4));
+    assert_eq!(observation(100, &["GATT", "GATT"], &[100, 100]).allele_balance(), None);
+}
+
+#[test]
+fn test_sample_qc() {
+    let called_het = observation(100, &["GATT", "GACA"], &[50, 50]);
+    let called_hom = observation(60, &["GATT", "GATT"], &[60, 60]);
+    let missing = observation(10, &[], &[]);
+
+    let qc = sample_qc("s1", &[&called_het, &called_hom, &missing], 30);
+    assert_eq!(qc.total_reads, 200);
+    assert_eq!(qc.on_target_fraction, 0.85);
+    assert_eq!(qc.loci_genotyped, 2);
+    assert_eq!(qc.observed_heterozygosity, 0.5);
+}
+
+#[test]
+fn test_locus_qc() {
+    let observations = vec![
+        observation(100, &["GATT", "GACA"], &[75, 25]),
+        observation(80, &["GATT", "GACA"], &[40, 40]),
+        observation(40, &["GATT", "GATT"], &[36, 36]),
+        observation(0, &[], &[]),
+    ];
+    let observations: Vec<&Observation> = observations.iter().collect();
+
+    let qc = locus_qc("locus1", &observations);
+    assert_eq!(qc.mean_depth, 55.0);
+    assert_eq!(qc.median_depth, 60.0);
+    assert_eq!(qc.call_rate, 0.75);
+    assert_eq!(qc.alleles, 2);
+    assert_eq!(qc.heterozygotes, 2);
+    assert_eq!(qc.allele_balance, Some((0.3125, 0.375, 0.4375)));
+}
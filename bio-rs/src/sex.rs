@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use loci::LocusTable;
+
+/// Share of reads expected from a Y marker in a male, one Y copy against two autosomal copies, used
+/// when no sample of a run shows the marker
+const DEFAULT_MALE_Y_SHARE: f64 = 0.5;
+
+/// Times the background rate a sample must show a marker at to be taken as male when calibrating
+const MALE_EVIDENCE: f64 = 5.0;
+
+/// Sex-linked and control loci of a panel, from the role column of a locus table
+/// Roles are "y" for Y-specific markers, "x_gametolog" and "y_gametolog" for the two copies of a
+/// gametolog, paired by a shared value in the pair column, and "autosomal_control"
+#[derive(Clone, Debug, PartialEq)]
+pub struct SexMarkers {
+    /// Autosomal loci reads are normalised against, every autosomal locus when none is marked
+    pub controls: Vec<String>,
+    pub y_loci: Vec<String>,
+    /// (X, Y) locus pairs
+    pub gametologs: Vec<(String, String)>,
+}
+
+impl SexMarkers {
+    /// Reads the roles of a locus table, None when it marks no sex-linked loci
+    pub fn from_table(table: &LocusTable) -> Result<Option<SexMarkers>, String> {
+        let mut markers = SexMarkers { controls: vec!(), y_loci: vec!(), gametologs: vec!() };
+        let mut pairs: BTreeMap<&str, (Option<String>, Option<String>)> = BTreeMap::new();
+        for locus in &table.loci {
+            match table.get(locus, "role") {
+                Some("y") => markers.y_loci.push(locus.clone()),
+                Some("autosomal_control") => markers.controls.push(locus.clone()),
+                Some(role) if role == "x_gametolog" || role == "y_gametolog" => {
+                    let pair = try!(table.get(locus, "pair").ok_or(format!("Gametolog locus {} has no pair", locus)));
+                    let entry = pairs.entry(pair).or_insert((None, None));
+                    let copy = if role == "x_gametolog" { &mut entry.0 } else { &mut entry.1 };
+                    if copy.is_some() {
+                        return Err(format!("Gametolog pair {} has more than one {} locus", pair, role));
+                    }
+                    *copy = Some(locus.clone());
+                },
+                _ => {},
+            }
+        }
+        for (pair, copies) in pairs {
+            match copies {
+                (Some(x), Some(y)) => markers.gametologs.push((x, y)),
+                _ => return Err(format!("Gametolog pair {} needs one x_gametolog and one y_gametolog locus", pair)),
+            }
+        }
+
+        if markers.y_loci.len() == 0 && markers.gametologs.len() == 0 {
+            return Ok(None);
+        }
+        if markers.controls.len() == 0 {
+            markers.controls = table.loci.iter()
+                .filter(|locus| !markers.is_sex_linked(locus))
+                .cloned()
+                .collect();
+        }
+        Ok(Some(markers))
+    }
+
+    pub fn is_sex_linked(&self, locus: &str) -> bool {
+        self.y_loci.iter().any(|l| l == locus) || self.gametologs.iter().any(|&(ref x, ref y)| x == locus || y == locus)
+    }
+
+    /// Mean reads per control locus of a sample
+    fn control_depth<F: Fn(&str) -> usize>(&self, reads: &F) -> f64 {
+        if self.controls.len() == 0 {
+            return 0.0;
+        }
+        self.controls.iter().fold(0, |sum, locus| sum + reads(locus)) as f64 / self.controls.len() as f64
+    }
+
+    /// Learns what males look like at each marker from a run's samples
+    /// reads gives a sample's reads at a locus; samples showing a marker at more than
+    /// MALE_EVIDENCE times the background rate are taken as males, and the marker's male level is
+    /// their median, or one Y copy against two autosomal copies when no sample shows it
+    pub fn calibrate<F: Fn(&str, &str) -> usize>(&self, samples: &[String], reads: F, background_rate: f64) -> SexModel {
+        let male_level = |levels: Vec<f64>| -> f64 {
+            let mut levels: Vec<f64> = levels.into_iter().filter(|&level| level > MALE_EVIDENCE * background_rate).collect();
+            if levels.len() == 0 {
+                return DEFAULT_MALE_Y_SHARE;
+            }
+            levels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let mid = levels.len() / 2;
+            if levels.len() % 2 == 0 { (levels[mid - 1] + levels[mid]) / 2.0 } else { levels[mid] }
+        };
+
+        let y_ratios = self.y_loci.iter().map(|y| {
+            male_level(samples.iter().filter_map(|sample| {
+                let depth = self.control_depth(&|locus: &str| reads(sample, locus));
+                if depth > 0.0 { Some(reads(sample, y) as f64 / depth) } else { None }
+            }).collect())
+        }).collect();
+        let gametolog_y_fractions = self.gametologs.iter().map(|&(ref x, ref y)| {
+            male_level(samples.iter().filter_map(|sample| {
+                let (x_reads, y_reads) = (reads(sample, x), reads(sample, y));
+                if x_reads + y_reads > 0 { Some(y_reads as f64 / (x_reads + y_reads) as f64) } else { None }
+            }).collect())
+        }).collect();
+
+        SexModel {
+            background_rate: background_rate,
+            y_ratios: y_ratios,
+            gametolog_y_fractions: gametolog_y_fractions,
+        }
+    }
+
+    /// Weighs a sample's read counts at each locus for it being male over female
+    /// Males show each marker at the level the model learned, females only at the background rate
+    pub fn call<F: Fn(&str) -> usize>(&self, sample: &str, reads: F, model: &SexModel) -> SexCall {
+        let background = model.background_rate;
+        let control_depth = self.control_depth(&reads);
+        let mut lod = 0.0;
+
+        // Y reads are Poisson around the depth each sex predicts
+        let mut y_reads = 0;
+        for (y, &ratio) in self.y_loci.iter().zip(model.y_ratios.iter()) {
+            let observed = reads(y);
+            y_reads += observed;
+            if control_depth > 0.0 {
+                let (male, female) = (control_depth * ratio, control_depth * background);
+                lod += observed as f64 * (male / female).log10() - (male - female) / 10.0f64.ln();
+            }
+        }
+        let y_ratio = if control_depth > 0.0 && self.y_loci.len() > 0 {
+            Some(y_reads as f64 / (control_depth * self.y_loci.len() as f64))
+        } else {
+            None
+        };
+
+        // Y reads of gametologs are binomial out of both copies' reads
+        let (mut x_gametolog_reads, mut y_gametolog_reads) = (0, 0);
+        for (&(ref x, ref y), &fraction) in self.gametologs.iter().zip(model.gametolog_y_fractions.iter()) {
+            // Males showing only the Y copy mustn't make a single X read impossible
+            let fraction = fraction.min(1.0 - background);
+            let (x_reads, y_reads) = (reads(x), reads(y));
+            x_gametolog_reads += x_reads;
+            y_gametolog_reads += y_reads;
+            lod += y_reads as f64 * (fraction / background).log10() +
+                x_reads as f64 * ((1.0 - fraction) / (1.0 - background)).log10();
+        }
+        let gametolog_reads = x_gametolog_reads + y_gametolog_reads;
+
+        SexCall {
+            sample: sample.to_string(),
+            control_depth: control_depth,
+            y_reads: y_reads,
+            y_ratio: y_ratio,
+            x_gametolog_reads: x_gametolog_reads,
+            y_gametolog_reads: y_gametolog_reads,
+            gametolog_y_fraction: if gametolog_reads > 0 { Some(y_gametolog_reads as f64 / gametolog_reads as f64) } else { None },
+            lod: lod,
+        }
+    }
+}
+
+/// What each sex looks like at every marker of a panel
+#[derive(Clone, Debug, PartialEq)]
+pub struct SexModel {
+    /// Share of reads females show at Y markers, from contamination and index hopping
+    pub background_rate: f64,
+    /// Male reads per Y locus over reads per control locus, in the order of the markers' y_loci
+    pub y_ratios: Vec<f64>,
+    /// Male share of each gametolog's reads from the Y copy, in the order of the markers' gametologs
+    pub gametolog_y_fractions: Vec<f64>,
+}
+
+/// A sample's sex-linked read counts and how strongly they point to either sex
+#[derive(Clone, Debug, PartialEq)]
+pub struct SexCall {
+    pub sample: String,
+    /// Mean reads per autosomal control locus
+    pub control_depth: f64,
+    pub y_reads: usize,
+    /// Reads per Y locus over reads per control locus, None without control reads
+    pub y_ratio: Option<f64>,
+    pub x_gametolog_reads: usize,
+    pub y_gametolog_reads: usize,
+    /// Share of gametolog reads from the Y copy, None without gametolog reads
+    pub gametolog_y_fraction: Option<f64>,
+    /// Log10 likelihood ratio of male over female
+    pub lod: f64,
+}
+
+impl SexCall {
+    /// male or female when the LOD reaches min_lod either way, otherwise uncertain
+    pub fn sex(&self, min_lod: f64) -> &'static str {
+        if self.lod >= min_lod {
+            "male"
+        } else if self.lod <= -min_lod {
+            "female"
+        } else {
+            "uncertain"
+        }
+    }
+}
+
+/// Column labels of a sex call table
+pub const COLUMNS: &'static str =
+    "sample\tcontrol_depth\ty_reads\ty_ratio\tx_gametolog_reads\ty_gametolog_reads\tgametolog_y_fraction\tlod\tsex";
+
+pub fn write_call<W: io::Write>(table: &mut W, call: &SexCall, min_lod: f64) -> io::Result<()> {
+    let ratio = |ratio: Option<f64>| ratio.map(|ratio| format!("{:.4}", ratio)).unwrap_or("NA".to_string());
+    writeln!(
+        table,
+        "{}\t{:.1}\t{}\t{}\t{}\t{}\t{}\t{:.2}\t{}",
+        call.sample,
+        call.control_depth,
+        call.y_reads,
+        ratio(call.y_ratio),
+        call.x_gametolog_reads,
+        call.y_gametolog_reads,
+        ratio(call.gametolog_y_fraction),
+        call.lod,
+        call.sex(min_lod),
+    )
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_sex_markers() {
+    let mut table = io::BufReader::new(&b"locus\trole\tpair\nA1\nA2\tautosomal_control\nSRY\ty\nZFX\tx_gametolog\tZF\nZFY\ty_gametolog\tZF\n"[..]);
+    let markers = SexMarkers::from_table(&LocusTable::read(&mut table).unwrap()).unwrap().unwrap();
+    assert_eq!(markers.controls, vec!["A2".to_string()]);
+    assert_eq!(markers.y_loci, vec!["SRY".to_string()]);
+    assert_eq!(markers.gametologs, vec![("ZFX".to_string(), "ZFY".to_string())]);
+    assert!(markers.is_sex_linked("ZFX"));
+    assert!(!markers.is_sex_linked("A1"));
+
+    let mut unpaired = io::BufReader::new(&b"locus\trole\tpair\nZFX\tx_gametolog\tZF\n"[..]);
+    assert!(SexMarkers::from_table(&LocusTable::read(&mut unpaired).unwrap()).is_err());
+
+    let mut autosomal = io::BufReader::new(&b"locus\trole\nA1\tautosomal_control\n"[..]);
+    assert_eq!(SexMarkers::from_table(&LocusTable::read(&mut autosomal).unwrap()), Ok(None));
+}
+
+#[test]
+fn test_sex_call() {
+    use std::collections::HashMap;
+
+    let markers = SexMarkers {
+        controls: vec!["A1".to_string(), "A2".to_string()],
+        y_loci: vec!["SRY".to_string()],
+        gametologs: vec![("ZFX".to_string(), "ZFY".to_string())],
+    };
+    // SRY amplifies poorly, at a tenth of the control depth in males
+    let mut counts: HashMap<(&str, &str), usize> = HashMap::new();
+    for &(sample, sry, zfx, zfy) in &[("M1", 20, 55, 45), ("M2", 22, 50, 50), ("F1", 0, 100, 0), ("F2", 1, 100, 0)] {
+        for &(locus, reads) in &[("A1", 100), ("A2", 300), ("SRY", sry), ("ZFX", zfx), ("ZFY", zfy)] {
+            counts.insert((sample, locus), reads);
+        }
+    }
+    let reads = |sample: &str, locus: &str| counts.get(&(sample, locus)).cloned().unwrap_or(0);
+    let samples: Vec<String> = vec!["M1".to_string(), "M2".to_string(), "F1".to_string(), "F2".to_string()];
+
+    let model = markers.calibrate(&samples, &reads, 0.01);
+    assert!((model.y_ratios[0] - 0.105).abs() < 1e-9);
+    assert_eq!(model.gametolog_y_fractions, vec![0.475]);
+
+    let male = markers.call("M1", |locus| reads("M1", locus), &model);
+    assert_eq!(male.control_depth, 200.0);
+    assert_eq!(male.y_ratio, Some(0.1));
+    assert_eq!(male.gametolog_y_fraction, Some(0.45));
+    assert_eq!(male.sex(3.0), "male");
+
+    // The poorly amplifying Y marker alone is enough once calibrated
+    let y_only = SexMarkers { controls: markers.controls.clone(), y_loci: markers.y_loci.clone(), gametologs: vec!() };
+    let y_model = y_only.calibrate(&samples, &reads, 0.01);
+    assert_eq!(y_only.call("M1", |locus| reads("M1", locus), &y_model).sex(3.0), "male");
+
+    let female = markers.call("F2", |locus| reads("F2", locus), &model);
+    assert_eq!(female.y_reads, 1);
+    assert_eq!(female.sex(3.0), "female");
+
+    // Without reads there's nothing to go on
+    assert_eq!(markers.call("N", |_| 0, &model).sex(3.0), "uncertain");
+}
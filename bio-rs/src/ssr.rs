@@ -0,0 +1,309 @@
+use std::io;
+
+use genotype::{self, GenotypeCall};
+
+/// Longest repeat motif looked for
+pub const MAX_PERIOD: usize = 6;
+
+/// Fewest tandem copies of a motif that count as a microsatellite
+pub const MIN_UNITS: usize = 3;
+
+/// A run of tandem copies of a motif within a sequence
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Repeat {
+    /// 0-based start of the first copy
+    pub start: usize,
+    pub units: usize,
+}
+
+/// Finds the longest run of perfect tandem copies of motif, the first one if there are several
+pub fn longest_run(sequence: &str, motif: &str) -> Option<Repeat> {
+    let period = motif.len();
+    if period == 0 {
+        return None;
+    }
+
+    let mut best: Option<Repeat> = None;
+    for start in 0..sequence.len() {
+        let mut units = 0;
+        while sequence[start + units * period..].starts_with(motif) {
+            units += 1;
+        }
+        if units > best.map(|repeat| repeat.units).unwrap_or(0) {
+            best = Some(Repeat { start: start, units: units });
+        }
+    }
+    best
+}
+
+/// Finds the motif with the longest tandem run in sequence, preferring shorter motifs
+/// Motifs that are themselves repeats of a shorter one, like ACAC, aren't considered
+pub fn find_motif(sequence: &str, max_period: usize) -> Option<String> {
+    let mut best: Option<(String, usize)> = None;
+    for period in 1..(max_period + 1) {
+        if sequence.len() < period {
+            break;
+        }
+        for start in 0..(sequence.len() - period + 1) {
+            let motif = &sequence[start..start + period];
+            if (1..period).any(|p| period % p == 0 && motif == repeat(&motif[..p], period / p)) {
+                continue;
+            }
+
+            let mut units = 0;
+            while sequence[start + units * period..].starts_with(motif) {
+                units += 1;
+            }
+            if units >= MIN_UNITS && units * period > best.as_ref().map(|&(_, length)| length).unwrap_or(0) {
+                best = Some((motif.to_string(), units * period));
+            }
+        }
+    }
+    best.map(|(motif, _)| motif)
+}
+
+fn repeat(motif: &str, times: usize) -> String {
+    let mut repeated = String::new();
+    for _ in 0..times {
+        repeated.push_str(motif);
+    }
+    repeated
+}
+
+/// A microsatellite allele: its length in repeat units and the sequence either side of the repeat
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SsrAllele {
+    pub units: usize,
+    pub left_flank: String,
+    pub right_flank: String,
+}
+
+impl SsrAllele {
+    /// Reads the allele a read shows, None if it has no copy of the motif
+    pub fn of_read(sequence: &str, motif: &str) -> Option<SsrAllele> {
+        longest_run(sequence, motif).map(|repeat| SsrAllele {
+            units: repeat.units,
+            left_flank: sequence[..repeat.start].to_string(),
+            right_flank: sequence[repeat.start + repeat.units * motif.len()..].to_string(),
+        })
+    }
+
+    /// Names the allele <units>, followed by any substitutions in its flanks against the reference
+    /// allele's, e.g. 12.L3T_R1A for a T three bases left of the repeat and an A just right of it
+    /// Without a reference, or with flanks of another length, the flanks are written out instead, as
+    /// <units>.<left flank>_<right flank>
+    pub fn name(&self, reference: Option<&SsrAllele>) -> String {
+        let variants = reference.and_then(|reference| {
+            match (flank_variants('L', &reference.left_flank, &self.left_flank), flank_variants('R', &reference.right_flank, &self.right_flank)) {
+                (Some(mut left), Some(right)) => {
+                    left.extend(right.into_iter());
+                    Some(left)
+                },
+                _ => None,
+            }
+        });
+        match variants {
+            Some(ref variants) if variants.len() == 0 => self.units.to_string(),
+            Some(variants) => format!("{}.{}", self.units, variants.connect("_")),
+            None => format!("{}.{}_{}", self.units, self.left_flank, self.right_flank),
+        }
+    }
+
+    /// Whether other has the same flanks and is units repeat units longer
+    fn is_offset(&self, other: &SsrAllele, units: isize) -> bool {
+        other.units as isize - self.units as isize == units &&
+            other.left_flank == self.left_flank &&
+            other.right_flank == self.right_flank
+    }
+}
+
+/// Substitutions in a flank against the reference's, as the side, distance from the repeat and base
+/// None if the flanks differ in length
+fn flank_variants(side: char, reference: &str, flank: &str) -> Option<Vec<String>> {
+    if reference.len() != flank.len() {
+        return None;
+    }
+
+    let mut variants = vec!();
+    for (i, (r, b)) in reference.chars().zip(flank.chars()).enumerate() {
+        if r != b {
+            let distance = if side == 'L' { flank.len() - i } else { i + 1 };
+            variants.push(format!("{}{}{}", side, distance, b));
+        }
+    }
+    Some(variants)
+}
+
+/// Likelihood model for calling microsatellite genotypes that allows for PCR stutter
+/// A read from an allele of n units shows n - 1 units with probability stutter_minus, n + 1 with
+/// probability stutter_plus and some other allele with probability error_rate
+#[derive(Clone, Copy, Debug)]
+pub struct StutterModel {
+    pub ploidy: usize,
+    pub error_rate: f64,
+    pub stutter_minus: f64,
+    pub stutter_plus: f64,
+    /// Only this many of the most abundant alleles are considered
+    pub max_candidates: usize,
+}
+
+impl StutterModel {
+    pub fn new(ploidy: usize, error_rate: f64, stutter_minus: f64, stutter_plus: f64) -> StutterModel {
+        StutterModel {
+            ploidy: ploidy,
+            error_rate: error_rate,
+            stutter_minus: stutter_minus,
+            stutter_plus: stutter_plus,
+            max_candidates: 6,
+        }
+    }
+
+    /// Natural log likelihood of the allele counts under a genotype
+    /// Alleles beyond the candidates are lumped together as errors
+    pub fn log_likelihood(&self, alleles: &[(SsrAllele, u32)], genotype: &[usize]) -> f64 {
+        let num_classes = if alleles.len() > self.max_candidates { self.max_candidates + 1 } else { alleles.len() };
+        let other_rate = if num_classes > 1 { self.error_rate / ((num_classes - 1) as f64) } else { 0.0 };
+        let exact_rate = 1.0 - self.error_rate - self.stutter_minus - self.stutter_plus;
+
+        alleles.iter().enumerate().filter(|&(_, &(_, count))| count > 0).map(|(class, &(ref allele, count))| {
+            // Chance of a read showing this allele, averaged over the genotype's allele copies
+            let p = genotype.iter().map(|&copy| {
+                let parent = &alleles[copy].0;
+                if class == copy {
+                    exact_rate
+                } else if class >= self.max_candidates {
+                    other_rate
+                } else if parent.is_offset(allele, -1) {
+                    self.stutter_minus
+                } else if parent.is_offset(allele, 1) {
+                    self.stutter_plus
+                } else {
+                    other_rate
+                }
+            }).fold(0.0, |sum, p| sum + p) / (genotype.len() as f64);
+
+            (count as f64) * p.ln()
+        }).fold(0.0, |sum, ll| sum + ll)
+    }
+
+    /// Calls the maximum likelihood genotype
+    /// alleles must be sorted most reads first
+    /// Returns None if there are no reads
+    pub fn call(&self, alleles: &[(SsrAllele, u32)]) -> Option<GenotypeCall> {
+        if self.ploidy == 0 || alleles.iter().all(|&(_, count)| count == 0) {
+            return None;
+        }
+
+        let num_candidates = if alleles.len() < self.max_candidates { alleles.len() } else { self.max_candidates };
+        let genotypes = genotype::multisets(num_candidates, self.ploidy);
+        let log_likelihoods: Vec<f64> = genotypes.iter().map(|g| self.log_likelihood(alleles, g)).collect();
+        Some(genotype::best_call(&genotypes, &log_likelihoods))
+    }
+}
+
+/// Column labels of a microsatellite genotype table
+pub const COLUMNS: &'static str = "locus\tsample\tdepth\tmotif\tunits\tgenotype\tgq\tallele_reads";
+
+/// A sample's microsatellite genotype at a locus
+#[derive(Clone, Debug, PartialEq)]
+pub struct SsrCall {
+    pub locus: String,
+    pub sample: String,
+    /// Reads showing the motif
+    pub depth: usize,
+    pub motif: String,
+    /// One allele per copy
+    pub alleles: Vec<SsrAllele>,
+    pub quality: f64,
+    /// Reads of each allele copy
+    pub allele_reads: Vec<u32>,
+}
+
+pub fn write_header<W: io::Write>(calls: &mut W) -> io::Result<()> {
+    writeln!(calls, "{}", COLUMNS)
+}
+
+/// Writes a call, naming its alleles against the locus's reference allele when there is one
+pub fn write_call<W: io::Write>(calls: &mut W, call: &SsrCall, reference: Option<&SsrAllele>) -> io::Result<()> {
+    let units: Vec<String> = call.alleles.iter().map(|allele| allele.units.to_string()).collect();
+    let names: Vec<String> = call.alleles.iter().map(|allele| allele.name(reference)).collect();
+    let reads: Vec<String> = call.allele_reads.iter().map(|reads| reads.to_string()).collect();
+    writeln!(
+        calls,
+        "{}\t{}\t{}\t{}\t{}\t{}\t{:.0}\t{}",
+        call.locus,
+        call.sample,
+        call.depth,
+        call.motif,
+        units.connect("/"),
+        names.connect("/"),
+        call.quality,
+        reads.connect(","),
+    )
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn allele(units: usize, left_flank: &str) -> SsrAllele {
+    SsrAllele { units: units, left_flank: left_flank.to_string(), right_flank: "GGC".to_string() }
+}
+
+#[test]
+fn test_find_motif() {
+    assert_eq!(find_motif("GATTACACACACACAGGC", MAX_PERIOD), Some("AC".to_string()));
+    assert_eq!(find_motif("TTGATCGATCGATCGATCAA", MAX_PERIOD), Some("GATC".to_string()));
+    assert_eq!(find_motif("AAAAAAAA", MAX_PERIOD), Some("A".to_string()));
+    assert_eq!(find_motif("GATTACA", MAX_PERIOD), None);
+}
+
+#[test]
+fn test_ssr_allele() {
+    let allele = SsrAllele::of_read("GATTACACACACAGGC", "AC").unwrap();
+    assert_eq!(allele.units, 4);
+    assert_eq!(allele.left_flank, "GATT");
+    assert_eq!(allele.right_flank, "AGGC");
+
+    let stutter = SsrAllele::of_read("GATTACACACAGGC", "AC").unwrap();
+    assert!(allele.is_offset(&stutter, -1));
+    assert!(SsrAllele::of_read("GATTGGC", "AC").is_none());
+}
+
+#[test]
+fn test_ssr_allele_names() {
+    let reference = SsrAllele::of_read("GATTACACACACAGGC", "AC").unwrap();
+    let allele = SsrAllele::of_read("GCTTACACACACACAGGT", "AC").unwrap();
+    assert_eq!(reference.name(Some(&reference)), "4");
+    assert_eq!(allele.name(Some(&reference)), "5.L3C_R4T");
+    assert_eq!(allele.name(None), "5.GCTT_AGGT");
+
+    // Flanks that don't line up with the reference's are written out
+    let shifted = SsrAllele::of_read("GATTACACACAGG", "AC").unwrap();
+    assert_eq!(shifted.name(Some(&reference)), "3.GATT_AGG");
+}
+
+#[test]
+fn test_calls_homozygote_over_stutter() {
+    let model = StutterModel::new(2, 0.01, 0.1, 0.02);
+
+    let call = model.call(&[(allele(12, "GAT"), 100), (allele(11, "GAT"), 15), (allele(13, "GAT"), 3)]).unwrap();
+    assert_eq!(call.alleles, vec![0, 0]);
+    assert!(call.quality > 20.0);
+}
+
+#[test]
+fn test_calls_adjacent_heterozygote() {
+    let model = StutterModel::new(2, 0.01, 0.1, 0.02);
+
+    let call = model.call(&[(allele(12, "GAT"), 100), (allele(11, "GAT"), 90), (allele(10, "GAT"), 10)]).unwrap();
+    assert_eq!(call.alleles, vec![0, 1]);
+}
+
+#[test]
+fn test_calls_same_size_alleles_by_flank() {
+    let model = StutterModel::new(2, 0.01, 0.1, 0.02);
+
+    let call = model.call(&[(allele(12, "GAT"), 60), (allele(12, "GAC"), 50)]).unwrap();
+    assert_eq!(call.alleles, vec![0, 1]);
+}
@@ -0,0 +1,223 @@
+use std::io;
+
+/// Per-sample FORMAT fields of a VCF record: GT, AD, DP and GQ
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleData {
+    /// One allele index per copy (0 for the reference), None where the allele is missing
+    pub genotype: Vec<Option<usize>>,
+    /// Read depth for the reference and each alternate allele
+    pub allele_depths: Option<Vec<u32>>,
+    pub depth: u32,
+    pub quality: Option<u32>,
+}
+
+impl SampleData {
+    /// Sample data for a sample without a call
+    pub fn missing(ploidy: usize, depth: u32) -> SampleData {
+        SampleData {
+            genotype: vec![None; ploidy],
+            allele_depths: None,
+            depth: depth,
+            quality: None,
+        }
+    }
+
+    fn as_string(&self) -> String {
+        let genotype: Vec<String> = self.genotype.iter().map(|allele| {
+            allele.map(|a| a.to_string()).unwrap_or(".".to_string())
+        }).collect();
+        let allele_depths = match self.allele_depths {
+            Some(ref depths) => {
+                let depths: Vec<String> = depths.iter().map(|d| d.to_string()).collect();
+                depths.connect(",")
+            },
+            None => ".".to_string(),
+        };
+        let quality = self.quality.map(|q| q.to_string()).unwrap_or(".".to_string());
+
+        format!("{}:{}:{}:{}", genotype.connect("/"), allele_depths, self.depth, quality)
+    }
+}
+
+/// A VCF data line
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub chrom: String,
+    /// 1-based position
+    pub pos: usize,
+    pub ref_allele: String,
+    pub alt_alleles: Vec<String>,
+    /// One entry per sample, in header order
+    pub samples: Vec<SampleData>,
+}
+
+/// Writes a VCF 4.3 header
+/// contigs are (name, length) pairs, meta lines are written as given after a leading "##"
+pub fn write_header<W: io::Write>(
+    vcf: &mut W,
+    contigs: &[(String, usize)],
+    samples: &[String],
+    meta: &[String],
+) -> io::Result<()>
+{
+    try!(writeln!(vcf, "##fileformat=VCFv4.3"));
+    for line in meta {
+        try!(writeln!(vcf, "##{}", line));
+    }
+    for &(ref name, length) in contigs {
+        try!(writeln!(vcf, "##contig=<ID={},length={}>", name, length));
+    }
+    try!(writeln!(vcf, "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">"));
+    try!(writeln!(vcf, "##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Read depth for each allele\">"));
+    try!(writeln!(vcf, "##FORMAT=<ID=DP,Number=1,Type=Integer,Description=\"Read depth\">"));
+    try!(writeln!(vcf, "##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype quality\">"));
+    try!(write!(vcf, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT"));
+    for sample in samples {
+        try!(write!(vcf, "\t{}", sample));
+    }
+    try!(writeln!(vcf, ""));
+    Ok(())
+}
+
+pub fn write_record<W: io::Write>(vcf: &mut W, record: &Record) -> io::Result<()> {
+    try!(write!(
+        vcf,
+        "{}\t{}\t.\t{}\t{}\t.\t.\t.\tGT:AD:DP:GQ",
+        record.chrom,
+        record.pos,
+        record.ref_allele,
+        record.alt_alleles.connect(","),
+    ));
+    for sample in &record.samples {
+        try!(write!(vcf, "\t{}", sample.as_string()));
+    }
+    try!(writeln!(vcf, ""));
+    Ok(())
+}
+
+/// Reads the sample names and records of a VCF
+/// Only the GT, AD, DP and GQ FORMAT fields are kept
+pub fn read_vcf<R: io::Read>(vcf: &mut io::BufReader<R>) -> io::Result<(Vec<String>, Vec<Record>)> {
+    use std::io::BufRead;
+
+    let mut samples = vec!();
+    let mut records = vec!();
+    for (n, line) in vcf.lines().enumerate() {
+        let line = try!(line);
+        if line.starts_with("##") || line.len() == 0 {
+            continue;
+        } else if line.starts_with("#CHROM") {
+            samples = line.split('\t').skip(9).map(|sample| sample.to_string()).collect();
+            continue;
+        }
+
+        match parse_record(&line, samples.len()) {
+            Some(record) => records.push(record),
+            None => {
+                let message = format!("Malformed VCF line {}: {}", n + 1, line);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            },
+        }
+    }
+    Ok((samples, records))
+}
+
+fn parse_record(line: &str, num_samples: usize) -> Option<Record> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 9 + num_samples {
+        return None;
+    }
+
+    let format: Vec<&str> = fields[8].split(':').collect();
+    let mut samples = vec!();
+    for sample in &fields[9..] {
+        let values: Vec<&str> = sample.split(':').collect();
+        let value = |key: &str| -> Option<&str> {
+            format.iter().position(|&k| k == key).and_then(|i| values.get(i).cloned()).and_then(|v| {
+                if v == "." { None } else { Some(v) }
+            })
+        };
+
+        let genotype = match value("GT") {
+            Some(gt) => gt.split(|c| c == '/' || c == '|').map(|allele| allele.parse().ok()).collect(),
+            None => vec![None],
+        };
+        let allele_depths = match value("AD") {
+            Some(ad) => {
+                let depths: Result<Vec<u32>, _> = ad.split(',').map(|d| d.parse()).collect();
+                match depths { Ok(depths) => Some(depths), Err(_) => return None }
+            },
+            None => None,
+        };
+
+        samples.push(SampleData {
+            genotype: genotype,
+            allele_depths: allele_depths,
+            depth: value("DP").and_then(|dp| dp.parse().ok()).unwrap_or(0),
+            quality: value("GQ").and_then(|gq| gq.parse().ok()),
+        });
+    }
+
+    Some(Record {
+        chrom: fields[0].to_string(),
+        pos: match fields[1].parse() { Ok(pos) => pos, Err(_) => return None },
+        ref_allele: fields[3].to_string(),
+        alt_alleles: if fields[4] == "." { vec!() } else { fields[4].split(',').map(|alt| alt.to_string()).collect() },
+        samples: samples,
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_write_vcf() {
+    use std::str::from_utf8;
+
+    let mut vcf: Vec<u8> = vec!();
+
+    let record = Record {
+        chrom: "locus1".to_string(),
+        pos: 4,
+        ref_allele: "AT".to_string(),
+        alt_alleles: vec!["A".to_string(), "ATT".to_string()],
+        samples: vec![
+            SampleData { genotype: vec![Some(0), Some(2)], allele_depths: Some(vec![20, 0, 18]), depth: 40, quality: Some(99) },
+            SampleData::missing(2, 3),
+        ],
+    };
+
+    write_header(&mut vcf, &[("locus1".to_string(), 120)], &["s1".to_string(), "s2".to_string()], &["source=test".to_string()]).unwrap();
+    write_record(&mut vcf, &record).unwrap();
+
+    let vcf = from_utf8(&vcf).unwrap();
+    let lines: Vec<&str> = vcf.lines().collect();
+    assert_eq!(lines[0], "##fileformat=VCFv4.3");
+    assert_eq!(lines[1], "##source=test");
+    assert_eq!(lines[2], "##contig=<ID=locus1,length=120>");
+    assert_eq!(lines[7], "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\ts1\ts2");
+    assert_eq!(lines[8], "locus1\t4\t.\tAT\tA,ATT\t.\t.\t.\tGT:AD:DP:GQ\t0/2:20,0,18:40:99\t./.:.:3:.");
+}
+
+#[test]
+fn test_read_vcf() {
+    let mut vcf: Vec<u8> = vec!();
+
+    let record = Record {
+        chrom: "locus1".to_string(),
+        pos: 4,
+        ref_allele: "AT".to_string(),
+        alt_alleles: vec!["A".to_string(), "ATT".to_string()],
+        samples: vec![
+            SampleData { genotype: vec![Some(0), Some(2)], allele_depths: Some(vec![20, 0, 18]), depth: 40, quality: Some(99) },
+            SampleData::missing(2, 3),
+        ],
+    };
+
+    write_header(&mut vcf, &[("locus1".to_string(), 120)], &["s1".to_string(), "s2".to_string()], &[]).unwrap();
+    write_record(&mut vcf, &record).unwrap();
+
+    let (samples, records) = read_vcf(&mut io::BufReader::new(&vcf[..])).unwrap();
+    assert_eq!(samples, vec!["s1".to_string(), "s2".to_string()]);
+    assert_eq!(records, vec![record]);
+}
@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use toml;
+
+/// Thresholds used when calling alleles, either for the whole run or for a single locus
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallParams {
+    /// Samples with fewer reads than this at a locus aren't called
+    pub min_depth: usize,
+    /// Haplotypes must be above this fraction of the most abundant one to be called
+    pub min_allele_ratio: f64,
+    /// Most alleles counted for a sample at a locus
+    pub max_alleles: usize,
+    /// Allele copies in a genotype call
+    pub ploidy: usize,
+    /// Chance that a read doesn't show the allele it came from
+    pub error_rate: f64,
+    /// Fold haplotypes carrying sequencing errors into their parents before calling
+    pub denoise: bool,
+    /// How quickly the abundance skew needed to fold a haplotype falls with its edit distance
+    pub denoise_alpha: f64,
+    /// Leave haplotypes that are crossovers of two more abundant ones out of calls
+    pub chimera_check: bool,
+    /// Times more reads each parent of a chimera needs than the chimera
+    pub chimera_skew: f64,
+    /// Most edits between a chimera and the crossover of its parents
+    pub chimera_max_diffs: usize,
+    /// Heterozygotes whose allele balance is further than this from the expected fraction are flagged
+    pub allele_balance_window: f64,
+    /// Chance that a read from a microsatellite allele shows one repeat unit fewer
+    pub stutter_minus: f64,
+    /// Chance that a read from a microsatellite allele shows one repeat unit more
+    pub stutter_plus: f64,
+    /// Call a consensus from a quality weighted pileup of reads instead of counting exact haplotypes
+    pub positional: bool,
+    /// Fraction of a pileup column's weight the second base needs for a heterozygous call
+    pub het_fraction: f64,
+}
+
+impl CallParams {
+    pub fn new() -> CallParams {
+        CallParams {
+            min_depth: 42,
+            min_allele_ratio: 0.1,
+            max_alleles: 4,
+            ploidy: 2,
+            error_rate: 0.01,
+            denoise: true,
+            denoise_alpha: 2.0,
+            chimera_check: true,
+            chimera_skew: 2.0,
+            chimera_max_diffs: 0,
+            allele_balance_window: 0.3,
+            stutter_minus: 0.1,
+            stutter_plus: 0.02,
+            positional: false,
+            het_fraction: 0.2,
+        }
+    }
+
+    /// Sets the parameter named key
+    /// Returns Ok(false) if there is no such parameter
+    pub fn set(&mut self, key: &str, value: &toml::Value) -> Result<bool, String> {
+        match key {
+            "min_depth" => self.min_depth = try!(to_usize(key, value)),
+            "min_allele_ratio" => self.min_allele_ratio = try!(to_f64(key, value)),
+            "max_alleles" => self.max_alleles = try!(to_usize(key, value)),
+            "ploidy" => self.ploidy = try!(to_usize(key, value)),
+            "error_rate" => self.error_rate = try!(to_f64(key, value)),
+            "denoise" => self.denoise = try!(to_bool(key, value)),
+            "denoise_alpha" => self.denoise_alpha = try!(to_f64(key, value)),
+            "chimera_check" => self.chimera_check = try!(to_bool(key, value)),
+            "chimera_skew" => self.chimera_skew = try!(to_f64(key, value)),
+            "chimera_max_diffs" => self.chimera_max_diffs = try!(to_usize(key, value)),
+            "allele_balance_window" => self.allele_balance_window = try!(to_f64(key, value)),
+            "stutter_minus" => self.stutter_minus = try!(to_f64(key, value)),
+            "stutter_plus" => self.stutter_plus = try!(to_f64(key, value)),
+            "positional" => self.positional = try!(to_bool(key, value)),
+            "het_fraction" => self.het_fraction = try!(to_f64(key, value)),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Sets every parameter in table, failing on unknown keys
+    fn set_all(&mut self, table: &toml::Table) -> Result<(), String> {
+        for (key, value) in table {
+            if !try!(self.set(key, value)) {
+                return Err(format!("Unknown parameter: {}", key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the parameters work together
+    pub fn validate(&self) -> Result<(), String> {
+        // A pileup consensus has at most two alleles
+        if self.positional && self.ploidy != 2 {
+            return Err(format!("positional calling needs ploidy 2, not {}", self.ploidy));
+        }
+        // What's left is the chance a microsatellite read shows its own allele
+        let rates = [self.error_rate, self.stutter_minus, self.stutter_plus];
+        if rates.iter().any(|&rate| !(rate >= 0.0)) || rates.iter().fold(0.0, |sum, &rate| sum + rate) >= 1.0 {
+            return Err(format!(
+                "error_rate, stutter_minus and stutter_plus must not be negative and must add up to less than 1, not {} + {} + {}",
+                self.error_rate,
+                self.stutter_minus,
+                self.stutter_plus,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Describes the parameters as space separated key=value pairs
+    pub fn describe(&self) -> String {
+        format!(
+            "min_depth={} min_allele_ratio={} max_alleles={} ploidy={} error_rate={} denoise={} denoise_alpha={} \
+             chimera_check={} chimera_skew={} chimera_max_diffs={} allele_balance_window={} \
+             stutter_minus={} stutter_plus={} positional={} het_fraction={}",
+            self.min_depth,
+            self.min_allele_ratio,
+            self.max_alleles,
+            self.ploidy,
+            self.error_rate,
+            self.denoise,
+            self.denoise_alpha,
+            self.chimera_check,
+            self.chimera_skew,
+            self.chimera_max_diffs,
+            self.allele_balance_window,
+            self.stutter_minus,
+            self.stutter_plus,
+            self.positional,
+            self.het_fraction,
+        )
+    }
+}
+
+/// Settings for a call_consensus run
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Demultiplexed reads
+    pub fastq: String,
+    /// File listing one sample name per line
+    pub samples: String,
+    /// File listing one locus name per line
+    pub loci: String,
+    /// Directory all output is written to
+    pub output_dir: String,
+    /// Fasta with one reference sequence per locus, enables VCF output
+    pub reference: Option<String>,
+    /// Table of SNP positions within each locus, enables microhaplotypes.tsv
+    pub microhaplotype_positions: Option<String>,
+    /// FASTQ to write reads that can't be assigned to a listed sample and locus to
+    pub unassigned_fastq: Option<String>,
+    /// Stop without calling if more than this fraction of reads can't be assigned
+    pub max_unassigned_fraction: f64,
+    /// LOD of male over female, either way, a sex call needs
+    pub sex_min_lod: f64,
+    /// Share of reads females show at Y markers
+    pub sex_background_rate: f64,
+    /// Parameters for loci without overrides
+    pub params: CallParams,
+    /// Maps locus names to the parameters they override
+    locus_overrides: BTreeMap<String, toml::Table>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            fastq: "sorted.fastq".to_string(),
+            samples: "samples".to_string(),
+            loci: "loci".to_string(),
+            output_dir: ".".to_string(),
+            reference: None,
+            microhaplotype_positions: None,
+            unassigned_fastq: None,
+            max_unassigned_fraction: 1.0,
+            sex_min_lod: 3.0,
+            sex_background_rate: 0.01,
+            params: CallParams::new(),
+            locus_overrides: BTreeMap::new(),
+        }
+    }
+
+    /// Reads a TOML config file
+    /// Top level keys set files and parameters, [locus.<name>] tables override parameters for one locus
+    pub fn from_file(path: &Path) -> Result<Config, String> {
+        let mut text = String::new();
+        try!(
+            File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+                .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))
+        );
+        Config::parse(&text, &path.display().to_string())
+    }
+
+    /// Reads the text of a TOML config, naming it source in errors
+    pub fn parse(text: &str, source: &str) -> Result<Config, String> {
+        let mut parser = toml::Parser::new(text);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let errors: Vec<String> = parser.errors.iter().map(|e| e.desc.clone()).collect();
+                return Err(format!("Failed to parse config {}: {}", source, errors.connect(", ")));
+            },
+        };
+
+        let mut config = Config::new();
+        for (key, value) in &table {
+            match &key[..] {
+                "fastq" => config.fastq = try!(to_string(key, value)),
+                "samples" => config.samples = try!(to_string(key, value)),
+                "loci" => config.loci = try!(to_string(key, value)),
+                "output_dir" => config.output_dir = try!(to_string(key, value)),
+                "reference" => config.reference = Some(try!(to_string(key, value))),
+                "unassigned_fastq" => config.unassigned_fastq = Some(try!(to_string(key, value))),
+                "max_unassigned_fraction" => config.max_unassigned_fraction = try!(to_f64(key, value)),
+                "sex_min_lod" => config.sex_min_lod = try!(to_f64(key, value)),
+                "sex_background_rate" => config.sex_background_rate = try!(to_f64(key, value)),
+                "microhaplotype_positions" => config.microhaplotype_positions = Some(try!(to_string(key, value))),
+                "locus" => {
+                    let loci = try!(value.as_table().ok_or("locus must be a table of loci".to_string()));
+                    for (locus, overrides) in loci {
+                        let overrides = try!(overrides.as_table().ok_or(format!("locus.{} must be a table", locus)));
+
+                        // Make sure the overrides are valid now rather than when the locus is called
+                        try!(CallParams::new().set_all(overrides).map_err(|e| format!("locus.{}: {}", locus, e)));
+
+                        config.locus_overrides.insert(locus.clone(), overrides.clone());
+                    }
+                },
+                _ => {
+                    if !try!(config.params.set(key, value)) {
+                        return Err(format!("Unknown config key: {}", key));
+                    }
+                },
+            }
+        }
+
+        try!(config.validate());
+        Ok(config)
+    }
+
+    /// Checks the settings of the run and the parameters of every locus with overrides
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.sex_min_lod > 0.0) {
+            return Err(format!("sex_min_lod must be above 0, not {}", self.sex_min_lod));
+        }
+        if !(self.sex_background_rate > 0.0 && self.sex_background_rate < 1.0) {
+            return Err(format!("sex_background_rate must be between 0 and 1, not {}", self.sex_background_rate));
+        }
+        try!(self.params.validate());
+        for locus in self.locus_overrides.keys() {
+            try!(self.params_for(locus).validate().map_err(|e| format!("locus.{}: {}", locus, e)));
+        }
+        Ok(())
+    }
+
+    /// Gets the parameters used for a locus
+    pub fn params_for(&self, locus: &str) -> CallParams {
+        let mut params = self.params.clone();
+        if let Some(overrides) = self.locus_overrides.get(locus) {
+            params.set_all(overrides).ok().expect("Locus overrides were validated when loaded");
+        }
+        params
+    }
+
+    /// Comment lines recording the parameters used, written at the top of every output table
+    pub fn header(&self) -> String {
+        let mut header = format!("# call_consensus {}\n", self.params.describe());
+        for locus in self.locus_overrides.keys() {
+            header.push_str(&format!("# locus {} {}\n", locus, self.params_for(locus).describe()));
+        }
+        header
+    }
+}
+
+fn to_usize(key: &str, value: &toml::Value) -> Result<usize, String> {
+    match value.as_integer() {
+        Some(i) if i >= 0 => Ok(i as usize),
+        _ => Err(format!("{} must be a non-negative integer", key)),
+    }
+}
+
+fn to_f64(key: &str, value: &toml::Value) -> Result<f64, String> {
+    match *value {
+        toml::Value::Float(f) => Ok(f),
+        toml::Value::Integer(i) => Ok(i as f64),
+        _ => Err(format!("{} must be a number", key)),
+    }
+}
+
+fn to_bool(key: &str, value: &toml::Value) -> Result<bool, String> {
+    value.as_bool().ok_or(format!("{} must be true or false", key))
+}
+
+fn to_string(key: &str, value: &toml::Value) -> Result<String, String> {
+    value.as_str().map(|s| s.to_string()).ok_or(format!("{} must be a string", key))
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_params_round_trip() {
+    let mut params = CallParams::new();
+    params.min_depth = 20;
+    params.error_rate = 0.05;
+    params.denoise = false;
+    params.positional = true;
+
+    // describe's key=value pairs are also valid TOML
+    let text: Vec<String> = params.describe().split(' ').map(|pair| pair.replace("=", " = ")).collect();
+    let config = Config::parse(&text.connect("\n"), "test").unwrap();
+    assert_eq!(config.params, params);
+}
+
+#[test]
+fn test_locus_overrides() {
+    let text = "min_depth = 20\nploidy = 4\n[locus.L2]\nmin_depth = 5\nerror_rate = 0.1\n";
+    let config = Config::parse(text, "test").unwrap();
+
+    assert_eq!(config.params_for("L1").min_depth, 20);
+    let params = config.params_for("L2");
+    assert_eq!((params.min_depth, params.error_rate), (5, 0.1));
+    // Parameters a locus doesn't override come from the top level, not the defaults
+    assert_eq!(params.ploidy, 4);
+    assert!(config.header().contains("# locus L2 min_depth=5 "));
+}
+
+#[test]
+fn test_config_errors() {
+    let error = |text: &str| Config::parse(text, "test").err().unwrap();
+
+    assert_eq!(error("min_depth = -1"), "min_depth must be a non-negative integer");
+    assert_eq!(error("min_depth = 2.5"), "min_depth must be a non-negative integer");
+    assert_eq!(error("error_rate = \"high\""), "error_rate must be a number");
+    assert_eq!(error("denoise = 1"), "denoise must be true or false");
+    assert_eq!(error("depth = 1"), "Unknown config key: depth");
+    assert_eq!(error("[locus.L1]\nploidy = true"), "locus.L1: ploidy must be a non-negative integer");
+    assert_eq!(error("[locus.L1]\nplody = 2"), "locus.L1: Unknown parameter: plody");
+    assert_eq!(error("positional = true\n[locus.L1]\nploidy = 1"), "locus.L1: positional calling needs ploidy 2, not 1");
+    assert!(error("stutter_minus = 0.5\nstutter_plus = 0.5").starts_with("error_rate, stutter_minus and stutter_plus"));
+    assert!(error("min_depth = ").starts_with("Failed to parse config test"));
+}
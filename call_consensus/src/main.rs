@@ -4,11 +4,15 @@
 #![feature(convert)]
 
 extern crate bio;
+extern crate getopts;
+extern crate rustc_serialize;
+extern crate toml;
 
 use std::cmp;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::collections::hash_map::Entry;
-use std::fs::{create_dir, File, OpenOptions};
+use std::fs::{create_dir, File};
 use std::io::{
     BufRead,
     BufReader,
@@ -16,21 +20,149 @@ use std::io::{
     Write,
 };
 use std::path::Path;
+use std::process;
 
+use getopts::Options;
+use rustc_serialize::json::{Json, ToJson};
+
+use bio::assign::Assigner;
+use bio::balance::{self, AlleleBalance, LocusBalance};
+use bio::align;
 use bio::bases;
+use bio::calls;
+use bio::chimera;
+use bio::cli::{fail, parse_opt};
+use bio::consensus::{self, Status};
+use bio::denoise;
+use bio::fasta;
 use bio::fastq;
+use bio::genotype::GenotypeModel;
+use bio::loci::LocusTable;
+use bio::pileup::{self, Pileup};
+use bio::qc;
+use bio::sex::{self, SexMarkers};
+use bio::ssr::{self, StutterModel};
+use bio::vcf;
+
+use config::Config;
+use microhaplotypes::Microhaplotyper;
+use variants::SampleCall;
+
+mod config;
+mod microhaplotypes;
+mod microsatellites;
+mod variants;
 
 fn main() {
-    let mut fastq_file = BufReader::new(File::open(&Path::new("sorted.fastq")).unwrap());
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("c", "config", "TOML config file, overridden by any options given here", "FILE");
+    opts.optopt("", "fastq", "demultiplexed reads (default sorted.fastq)", "FILE");
+    opts.optopt("", "samples", "file listing one sample per line (default samples)", "FILE");
+    opts.optopt("", "loci", "file listing one locus per line, or a locus table with a header line (default loci)", "FILE");
+    opts.optopt("", "reference", "fasta of locus reference sequences, variants are written to variants.vcf", "FILE");
+    opts.optopt("", "microhaplotype-positions", "table of SNP positions per locus, microhaplotypes are written to microhaplotypes.tsv", "FILE");
+    opts.optopt("o", "output-dir", "directory to write output to (default .)", "DIR");
+    opts.optopt("", "unassigned-fastq", "write reads without a listed sample and locus to FILE", "FILE");
+    opts.optopt("", "max-unassigned-fraction", "stop if more than this fraction of reads is unassigned (default 1)", "F");
+    opts.optopt("", "sex-min-lod", "LOD of male over female, either way, needed to call a sample's sex (default 3)", "F");
+    opts.optopt("", "sex-background-rate", "share of reads females show at Y markers, between 0 and 1 (default 0.01)", "F");
+    opts.optopt("", "min-depth", "fewest reads a sample needs at a locus to be called (default 42)", "N");
+    opts.optopt("", "min-allele-ratio", "fraction of the most abundant haplotype an allele must exceed (default 0.1)", "F");
+    opts.optopt("", "max-alleles", "most alleles counted in yay_nay_matrix.tsv (default 4)", "N");
+    opts.optopt("", "ploidy", "allele copies in a genotype call (default 2)", "N");
+    opts.optopt("", "error-rate", "chance a read doesn't show the allele it came from (default 0.01)", "F");
+    opts.optflag("", "no-denoise", "count haplotypes as read, without folding sequencing errors into their parents");
+    opts.optopt("", "denoise-alpha", "skew needed to fold a haplotype d edits away is 1/2^(alpha*d+1) (default 2)", "F");
+    opts.optflag("", "no-chimera-check", "keep haplotypes that look like PCR chimeras in calls");
+    opts.optopt("", "chimera-skew", "times more reads each parent of a chimera needs (default 2)", "F");
+    opts.optopt("", "chimera-max-diffs", "most edits between a chimera and its parents' crossover (default 0)", "N");
+    opts.optopt("", "allele-balance-window", "flag heterozygotes whose allele balance is further than this from expected (default 0.3)", "F");
+    opts.optopt("", "stutter-minus", "chance a microsatellite read shows one repeat unit fewer (default 0.1)", "F");
+    opts.optopt("", "stutter-plus", "chance a microsatellite read shows one repeat unit more (default 0.02)", "F");
+    opts.optflag("", "positional", "call a consensus from a quality weighted pileup, for long or error-prone amplicons");
+    opts.optopt("", "het-fraction", "share of a pileup position the second base needs to be heterozygous (default 0.2)", "F");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(1);
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: call_consensus [options]"));
+        return;
+    }
+
+    // Options given on the command line take precedence over the config file
+    let mut config = match matches.opt_str("config") {
+        Some(path) => match Config::from_file(&Path::new(&path)) {
+            Ok(config) => config,
+            Err(error) => {
+                println!("{}", error);
+                process::exit(1);
+            },
+        },
+        None => Config::new(),
+    };
+    config.fastq = matches.opt_str("fastq").unwrap_or(config.fastq);
+    config.samples = matches.opt_str("samples").unwrap_or(config.samples);
+    config.loci = matches.opt_str("loci").unwrap_or(config.loci);
+    config.output_dir = matches.opt_str("output-dir").unwrap_or(config.output_dir);
+    config.reference = matches.opt_str("reference").or(config.reference);
+    config.microhaplotype_positions = matches.opt_str("microhaplotype-positions").or(config.microhaplotype_positions);
+    config.unassigned_fastq = matches.opt_str("unassigned-fastq").or(config.unassigned_fastq);
+    config.max_unassigned_fraction = parse_opt(&matches, "max-unassigned-fraction", config.max_unassigned_fraction);
+    config.sex_min_lod = parse_opt(&matches, "sex-min-lod", config.sex_min_lod);
+    config.sex_background_rate = parse_opt(&matches, "sex-background-rate", config.sex_background_rate);
+    config.params.min_depth = parse_opt(&matches, "min-depth", config.params.min_depth);
+    config.params.min_allele_ratio = parse_opt(&matches, "min-allele-ratio", config.params.min_allele_ratio);
+    config.params.max_alleles = parse_opt(&matches, "max-alleles", config.params.max_alleles);
+    config.params.ploidy = parse_opt(&matches, "ploidy", config.params.ploidy);
+    config.params.error_rate = parse_opt(&matches, "error-rate", config.params.error_rate);
+    config.params.denoise = config.params.denoise && !matches.opt_present("no-denoise");
+    config.params.denoise_alpha = parse_opt(&matches, "denoise-alpha", config.params.denoise_alpha);
+    config.params.chimera_check = config.params.chimera_check && !matches.opt_present("no-chimera-check");
+    config.params.chimera_skew = parse_opt(&matches, "chimera-skew", config.params.chimera_skew);
+    config.params.chimera_max_diffs = parse_opt(&matches, "chimera-max-diffs", config.params.chimera_max_diffs);
+    config.params.allele_balance_window = parse_opt(&matches, "allele-balance-window", config.params.allele_balance_window);
+    config.params.stutter_minus = parse_opt(&matches, "stutter-minus", config.params.stutter_minus);
+    config.params.stutter_plus = parse_opt(&matches, "stutter-plus", config.params.stutter_plus);
+    config.params.positional = config.params.positional || matches.opt_present("positional");
+    config.params.het_fraction = parse_opt(&matches, "het-fraction", config.params.het_fraction);
+    if let Err(error) = config.validate() {
+        println!("{}", error);
+        process::exit(1);
+    }
+
+    let mut fastq_file = BufReader::new(File::open(&Path::new(&config.fastq)).unwrap());
 
-    let mut samples_file = BufReader::new(File::open(&Path::new("samples")).unwrap());
-    let mut loci_file = BufReader::new(File::open(&Path::new("loci")).unwrap());
+    let samples_file = BufReader::new(File::open(&Path::new(&config.samples)).unwrap());
+    let mut loci_file = BufReader::new(File::open(&Path::new(&config.loci)).unwrap());
 
     // Store samples and loci
     println!("Reading loci and samples...");
-    let loci: Vec<String> = loci_file.lines().map(|x| x.ok().unwrap().as_str().trim_right().to_string()).collect();
+    let locus_table = match LocusTable::read(&mut loci_file) {
+        Ok(locus_table) => locus_table,
+        Err(error) => {
+            println!("{}: {}", config.loci, error);
+            process::exit(1);
+        },
+    };
+    let loci: Vec<String> = locus_table.loci.clone();
+    let sex_markers = match SexMarkers::from_table(&locus_table) {
+        Ok(sex_markers) => sex_markers,
+        Err(error) => {
+            println!("{}: {}", config.loci, error);
+            process::exit(1);
+        },
+    };
+    let is_sex_linked = |locus: &str| sex_markers.as_ref().map(|markers| markers.is_sex_linked(locus)).unwrap_or(false);
     let samples: Vec<String> = samples_file.lines().map(|x| x.ok().unwrap().as_str().trim_right().to_string()).collect();
-    
+
     // Read fastq file
     println!("Reading fastq...");
     let seqs = fastq::read_fastq(&mut fastq_file);
@@ -50,47 +182,171 @@ fn main() {
         seq_matrix.insert(loci.clone(), sample_map);
     }
 
-    // Sort all of the sequences into a matrix
+    let output_dir = Path::new(&config.output_dir);
+    if !output_dir.is_dir() {
+        create_dir(output_dir).unwrap();
+    }
+    let header = config.header();
+
+    // Sort all of the sequences into a matrix, setting aside reads without a listed sample and locus
     println!("Filling matrix...");
+    let mut assigner = Assigner::new(&samples, &loci);
+    let mut unassigned_seqs = vec!();
     for seq in seqs {
-        let sample = seq.header.as_str().split(' ').nth(1).unwrap().split(':').nth(3).unwrap().to_string();
-        let loci = seq.header.as_str().split(' ').nth(3).unwrap().trim_right().to_string();
+        match assigner.assign(&seq) {
+            Ok((sample, locus)) => seq_matrix.get_mut(&locus).unwrap().get_mut(&sample).unwrap().push(seq),
+            Err(_) => unassigned_seqs.push(seq),
+        }
+    }
 
-        seq_matrix.get_mut(&loci).unwrap().get_mut(&sample).unwrap().push(seq);
+    let mut unassigned_file = BufWriter::new(File::create(output_dir.join("unassigned.tsv")).unwrap());
+    unassigned_file.write_all(header.as_bytes());
+    assigner.write_report(&mut unassigned_file).unwrap();
+    if let Some(ref path) = config.unassigned_fastq {
+        let mut unassigned_fastq = BufWriter::new(File::create(&Path::new(path)).unwrap());
+        fastq::write_fastq(&mut unassigned_fastq, unassigned_seqs.iter()).unwrap();
     }
 
+    println!("{} of {} reads unassigned", assigner.num_unassigned(), assigner.total);
+    if assigner.unassigned_fraction() > config.max_unassigned_fraction {
+        println!(
+            "ERROR: {:.3} of reads unassigned, more than the {} allowed, see unassigned.tsv",
+            assigner.unassigned_fraction(),
+            config.max_unassigned_fraction,
+        );
+        process::exit(1);
+    }
+
+    // Read locus reference sequences
+    let references: HashMap<String, bases::Bases> = match config.reference {
+        Some(ref path) => {
+            println!("Reading references...");
+            let mut reference_file = BufReader::new(File::open(&Path::new(path)).unwrap());
+            fasta::read_fasta(&mut reference_file).unwrap_or_else(|error| fail(&format!("{}: {}", path, error))).into_iter().collect()
+        },
+        None => HashMap::new(),
+    };
+    for locus in &loci {
+        if config.reference.is_some() && !references.contains_key(locus) {
+            println!("WARNING: no reference sequence for locus {}, leaving it out of the VCF", locus);
+        }
+    }
+    let mut vcf_records: HashMap<String, Vec<vcf::Record>> = HashMap::new();
+
+    // Read SNP positions for microhaplotypes
+    let snp_positions: HashMap<String, Vec<usize>> = match config.microhaplotype_positions {
+        Some(ref path) => {
+            println!("Reading microhaplotype positions...");
+            match microhaplotypes::read_positions(&Path::new(path)) {
+                Ok(positions) => positions,
+                Err(error) => {
+                    println!("{}", error);
+                    process::exit(1);
+                },
+            }
+        },
+        None => HashMap::new(),
+    };
+
+    // Microsatellite loci are called by repeat length rather than by haplotype
+    let ssr_motifs = microsatellites::find_motifs(&locus_table, &seq_matrix);
+    // Alleles are named by their flanking variants against the reference's allele, where there is one
+    let ssr_references: HashMap<String, ssr::SsrAllele> = ssr_motifs.iter().filter_map(|(locus, motif)| {
+        references.get(locus)
+            .and_then(|reference| ssr::SsrAllele::of_read(&reference.as_string(), motif))
+            .map(|allele| (locus.clone(), allele))
+    }).collect();
+
     // Call consensus
     println!("Calling consensus...");
 
-    let mut consensus_file = BufWriter::new(File::create(&Path::new("consensus.tsv")).unwrap()); 
-    let mut consensus_matrix = BufWriter::new(File::create(&Path::new("yay_nay_matrix.tsv")).unwrap()); 
-    let mut count_matrix = BufWriter::new(File::create(&Path::new("counts_matrix.tsv")).unwrap()); 
-    
+    let mut consensus_file = BufWriter::new(File::create(output_dir.join("consensus.tsv")).unwrap());
+    let mut consensus_matrix = BufWriter::new(File::create(output_dir.join("yay_nay_matrix.tsv")).unwrap());
+    let mut count_matrix = BufWriter::new(File::create(output_dir.join("counts_matrix.tsv")).unwrap());
+    let mut genotypes_file = BufWriter::new(File::create(output_dir.join("genotypes.tsv")).unwrap());
+    let mut denoising_file = BufWriter::new(File::create(output_dir.join("denoising.tsv")).unwrap());
+    let mut chimeras_file = BufWriter::new(File::create(output_dir.join("chimeras.tsv")).unwrap());
+    let mut balance_file = BufWriter::new(File::create(output_dir.join("allele_balance.tsv")).unwrap());
+
+    // Record the parameters used at the top of each table
+    consensus_file.write_all(header.as_bytes());
+    consensus_matrix.write_all(header.as_bytes());
+    count_matrix.write_all(header.as_bytes());
+    genotypes_file.write_all(header.as_bytes());
+    denoising_file.write_all(header.as_bytes());
+    chimeras_file.write_all(header.as_bytes());
+    balance_file.write_all(header.as_bytes());
+
+    let mut ssr_file = if ssr_motifs.len() > 0 {
+        let mut file = BufWriter::new(File::create(output_dir.join("ssr_genotypes.tsv")).unwrap());
+        file.write_all(header.as_bytes());
+        ssr::write_header(&mut file).unwrap();
+        Some(file)
+    } else {
+        None
+    };
+
+    let positional = loci.iter().any(|locus| config.params_for(locus).positional);
+    let mut positional_files = if positional {
+        let mut consensus = BufWriter::new(File::create(output_dir.join("positional_consensus.tsv")).unwrap());
+        consensus.write_all(header.as_bytes());
+        pileup::write_header(&mut consensus).unwrap();
+        let mut depth = BufWriter::new(File::create(output_dir.join("positional_depth.tsv")).unwrap());
+        depth.write_all(header.as_bytes());
+        depth.write_all(b"locus\tsample\tposition\tdepth\n");
+        Some((consensus, depth))
+    } else {
+        None
+    };
+
+    let mut microhaplotypes_file = config.microhaplotype_positions.as_ref().map(|_| {
+        let mut file = BufWriter::new(File::create(output_dir.join("microhaplotypes.tsv")).unwrap());
+        file.write_all(header.as_bytes());
+        file.write_all(microhaplotypes::COLUMNS.as_bytes());
+        file
+    });
 
     // Write column labels to matrices
     let samples_row = "\t".to_string() + &samples.connect("\t") + "\n";
     consensus_matrix.write_all(samples_row.as_bytes());
     count_matrix.write_all(samples_row.as_bytes());
+    consensus::write_header(&mut consensus_file).unwrap();
+    calls::write_header(&mut genotypes_file).unwrap();
+    denoising_file.write_all(b"locus\tsample\tsequence\treads\thaplotype\tedit_distance\n");
+    chimeras_file.write_all(b"locus\tsample\tsequence\treads\tleft_parent\tright_parent\tbreakpoint\tdiffs\tparent_diffs\n");
+    writeln!(balance_file, "{}", balance::LOCUS_COLUMNS).unwrap();
+
+    // What was seen for each (locus, sample), summarized once every locus is called
+    let mut observations: HashMap<(String, String), qc::Observation> = HashMap::new();
+
+    // Loci are called in the order they were listed so runs on the same input give identical output
+    for loci in &loci {
+        let sample_map = &seq_matrix[loci];
+        let params = config.params_for(loci);
+        let model = GenotypeModel::new(params.ploidy, params.error_rate);
+        let stutter_model = StutterModel::new(params.ploidy, params.error_rate, params.stutter_minus, params.stutter_plus);
+        // Sex-linked loci are counted for sex calls but kept out of the autosomal genotypes
+        let sex_linked = is_sex_linked(loci);
 
-    for (loci, sample_map) in &seq_matrix {
         // Create the loci's fasta folder
-        create_dir(loci);
+        let fasta_dir = output_dir.join(loci);
+        create_dir(&fasta_dir);
 
         // Begin rows of matrices
         consensus_matrix.write_all(loci.as_bytes());
         count_matrix.write_all(loci.as_bytes());
 
+        // Calls for each sample, kept for the VCF
+        let mut locus_calls: Vec<SampleCall> = vec!();
+        // Balance of each heterozygous call, to spot loci that under-amplify an allele
+        let mut locus_balances: Vec<AlleleBalance> = vec!();
+
         for sample in &samples {
             let seqs = &sample_map[sample];
 
             // Output count matrix entry
             count_matrix.write_all(format!("\t{}", seqs.len()).as_bytes());
 
-            if seqs.len() < 42 {
-                consensus_matrix.write_all(b"\t0");
-                continue;
-            } 
-
             let mut seqs_map: HashMap<bases::Bases, u32> = HashMap::new();
             for seq in seqs {
                 match seqs_map.entry(seq.bases.clone()) {
@@ -98,40 +354,348 @@ fn main() {
                     Entry::Vacant(entry) => { entry.insert(1); },
                 }
             }
+
+            // A sample without reads has nothing to call even when min_depth is 0
+            if seqs.len() < params.min_depth || seqs.len() == 0 {
+                let mut raw_counts: Vec<(bases::Bases, u32)> = seqs_map.into_iter().collect();
+                sort_haplotypes(&mut raw_counts);
+                write_consensus(&mut consensus_file, loci, sample, seqs.len(), &raw_counts, |_| Status::BelowDepth);
+                observations.insert((loci.clone(), sample.clone()), qc::Observation {
+                    depth: seqs.len(),
+                    top_two_reads: top_two_reads(&raw_counts),
+                    alleles: vec!(),
+                    allele_reads: vec!(),
+                });
+
+                consensus_matrix.write_all(b"\t0");
+                locus_calls.push(SampleCall { depth: seqs.len(), haplotypes: vec!(), genotype: None });
+                continue;
+            }
+
+            // Stutter products differ from their parent by whole repeat units, so microsatellites are
+            // called from the raw haplotypes
+            if let (Some(motif), Some(file)) = (ssr_motifs.get(loci), ssr_file.as_mut()) {
+                let mut raw_counts: Vec<(bases::Bases, u32)> = seqs_map.into_iter().collect();
+                sort_haplotypes(&mut raw_counts);
+
+                let call = microsatellites::call_sample(loci, sample, motif, &raw_counts, &stutter_model);
+                if let (Some(call), false) = (call.as_ref(), sex_linked) {
+                    ssr::write_call(file, call, ssr_references.get(loci)).unwrap();
+                }
+                write_consensus(&mut consensus_file, loci, sample, seqs.len(), &raw_counts, |haplotype| {
+                    let allele = ssr::SsrAllele::of_read(&haplotype.as_string(), motif);
+                    match (call.as_ref(), allele) {
+                        (Some(call), Some(ref allele)) if call.alleles.contains(allele) => Status::Called,
+                        _ => Status::Filtered,
+                    }
+                });
+
+                let mut called: Vec<String> = call.iter().flat_map(|call| call.alleles.iter().map(|allele| allele.name(ssr_references.get(loci)))).collect();
+                observations.insert((loci.clone(), sample.clone()), qc::Observation {
+                    depth: seqs.len(),
+                    top_two_reads: top_two_reads(&raw_counts),
+                    alleles: called.clone(),
+                    allele_reads: call.iter().flat_map(|call| call.allele_reads.iter().cloned()).collect(),
+                });
+
+                called.dedup();
+                consensus_matrix.write_all(format!("\t{}", cmp::min(params.max_alleles, called.len())).as_bytes());
+                locus_calls.push(SampleCall { depth: seqs.len(), haplotypes: vec!(), genotype: None });
+                continue;
+            }
+
+            // Long amplicons have few identical reads, so reads are stacked on the locus reference,
+            // or the most common read without one, and called position by position
+            if let (true, Some(&mut (ref mut consensus_out, ref mut depth_out))) = (params.positional, positional_files.as_mut()) {
+                let mut raw_counts: Vec<(bases::Bases, u32)> = seqs_map.into_iter().collect();
+                sort_haplotypes(&mut raw_counts);
+                let (reference, reference_name) = match references.get(loci) {
+                    Some(reference) => (reference.clone(), "locus"),
+                    None => (raw_counts[0].0.clone(), "top_read"),
+                };
+
+                // Identical reads align the same way, so each haplotype is aligned once
+                let mut alignments: HashMap<&bases::Bases, Vec<align::AlignOp>> = HashMap::new();
+                let mut pileup = Pileup::new(reference.len());
+                for seq in seqs {
+                    let ops = alignments.entry(&seq.bases).or_insert_with(|| {
+                        align::global_align(&reference.bases, &seq.bases.bases, &align::Scoring::new())
+                    });
+                    pileup.add(ops, seq);
+                }
+                let consensus = pileup.consensus(params.het_fraction);
+                if !sex_linked {
+                    pileup::write_consensus(consensus_out, loci, sample, seqs.len(), reference_name, &consensus).unwrap();
+                    pileup::write_depth(depth_out, loci, sample, &consensus.depth).unwrap();
+                }
+
+                let alleles = if consensus.is_heterozygous() {
+                    vec![consensus.alleles.0.clone(), consensus.alleles.1.clone()]
+                } else {
+                    vec![consensus.alleles.0.clone(); params.ploidy]
+                };
+                let (first, second) = consensus.allele_weights;
+                observations.insert((loci.clone(), sample.clone()), qc::Observation {
+                    depth: seqs.len(),
+                    top_two_reads: top_two_reads(&raw_counts),
+                    alleles: alleles,
+                    allele_reads: vec![first.round() as u32, second.round() as u32],
+                });
+
+                consensus_matrix.write_all(format!("\t{}", if consensus.is_heterozygous() { 2 } else { 1 }).as_bytes());
+                locus_calls.push(SampleCall { depth: seqs.len(), haplotypes: vec!(), genotype: None });
+                continue;
+            }
+
+            // Fold reads carrying sequencing errors into the haplotypes they came from
+            if params.denoise {
+                let mut raw_counts: Vec<(bases::Bases, u32)> = seqs_map.into_iter().collect();
+                sort_haplotypes(&mut raw_counts);
+
+                seqs_map = HashMap::new();
+                for (i, assignment) in denoise::denoise(&raw_counts, params.denoise_alpha).into_iter().enumerate() {
+                    let (ref sequence, count) = raw_counts[i];
+                    let haplotype = &raw_counts[assignment.parent].0;
+                    *seqs_map.entry(haplotype.clone()).or_insert(0) += count;
+
+                    denoising_file.write_all(format!(
+                        "{}\t{}\t{}\t{}\t{}\t{}\n",
+                        loci,
+                        sample,
+                        sequence.as_string(),
+                        count,
+                        haplotype.as_string(),
+                        assignment.distance,
+                    ).as_bytes());
+                }
+            }
+
+            // Call the genotype from every haplotype, most abundant first
+            let mut all_counts: Vec<(bases::Bases, u32)> = seqs_map.iter().map(|(bases, &count)| (bases.clone(), count)).collect();
+            sort_haplotypes(&mut all_counts);
+
+            let haplotype_counts = all_counts.clone();
+
+            // Leave PCR chimeras out of the calls
+            if params.chimera_check {
+                let chimeras = chimera::find_chimeras(&all_counts, params.chimera_skew, params.chimera_max_diffs);
+                for (&(ref sequence, count), chimera) in all_counts.iter().zip(chimeras.iter()) {
+                    if let Some(chimera) = *chimera {
+                        chimeras_file.write_all(format!(
+                            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                            loci,
+                            sample,
+                            sequence.as_string(),
+                            count,
+                            all_counts[chimera.left_parent].0.as_string(),
+                            all_counts[chimera.right_parent].0.as_string(),
+                            chimera.breakpoint,
+                            chimera.diffs,
+                            chimera.parent_diffs,
+                        ).as_bytes());
+                        seqs_map.remove(sequence);
+                    }
+                }
+                all_counts = all_counts.into_iter().zip(chimeras.into_iter())
+                    .filter(|&(_, ref chimera)| chimera.is_none())
+                    .map(|(haplotype, _)| haplotype)
+                    .collect();
+            }
+            let counts: Vec<u32> = all_counts.iter().map(|&(_, count)| count).collect();
+
+            let mut observation = qc::Observation {
+                depth: seqs.len(),
+                top_two_reads: top_two_reads(&haplotype_counts),
+                alleles: vec!(),
+                allele_reads: vec!(),
+            };
+
+            let mut genotype: Option<(Vec<bases::Bases>, f64)> = None;
+            if let Some(call) = model.call(&counts) {
+                genotype = Some((call.alleles.iter().map(|&a| all_counts[a].0.clone()).collect(), call.quality));
+                observation.alleles = call.alleles.iter().map(|&a| all_counts[a].0.as_string()).collect();
+                observation.allele_reads = call.alleles.iter().map(|&a| counts[a]).collect();
+
+                let allele_balance = AlleleBalance::of_call(&counts, &call);
+                if let Some(allele_balance) = allele_balance {
+                    locus_balances.push(allele_balance);
+                }
+
+                if !sex_linked {
+                    calls::write_call(&mut genotypes_file, &calls::Call {
+                        locus: loci.clone(),
+                        sample: sample.clone(),
+                        ploidy: params.ploidy,
+                        depth: seqs.len(),
+                        genotype: call.as_string(),
+                        quality: call.quality,
+                        alleles: call.alleles.iter().map(|&a| all_counts[a].0.clone()).collect(),
+                        haplotype_counts: counts.clone(),
+                        balance: allele_balance.map(|b| (b.fraction, b.p_value)),
+                        skewed: allele_balance.map(|b| b.is_skewed(params.allele_balance_window)).unwrap_or(false),
+                    }).unwrap();
+                }
+            }
+
+            write_consensus(&mut consensus_file, loci, sample, seqs.len(), &haplotype_counts, |haplotype| {
+                match genotype {
+                    Some((ref alleles, _)) if alleles.contains(haplotype) => Status::Called,
+                    _ => Status::Filtered,
+                }
+            });
+            observations.insert((loci.clone(), sample.clone()), observation);
+
             if let Some(most_abundant) = seqs_map.iter().map(|(_, count)| count).max().cloned() {
                 let seqs_map: HashMap<bases::Bases, u32> =
                     seqs_map.into_iter()
-                        .filter(|&(_, count)| (count as f64)/(most_abundant as f64) > 0.1)
+                        .filter(|&(_, count)| (count as f64)/(most_abundant as f64) > params.min_allele_ratio)
                         .collect();
 
                 let mut seq_counts: Vec<(bases::Bases, u32)> = seqs_map.into_iter().collect();
-                seq_counts.sort_by(|&(_, a), &(_, b)| a.cmp(&b));
-                seq_counts.reverse();
+                sort_haplotypes(&mut seq_counts);
 
-                consensus_matrix.write_all(format!("\t{}", cmp::min(4, seq_counts.len())).as_bytes());
+                consensus_matrix.write_all(format!("\t{}", cmp::min(params.max_alleles, seq_counts.len())).as_bytes());
 
                 // Write fasta
-                let mut fasta_file = BufWriter::new(File::create(format!("{}/{}.fasta", loci, sample)).unwrap()); 
+                let mut fasta_file = BufWriter::new(File::create(fasta_dir.join(format!("{}.fasta", sample))).unwrap());
 
                 for &(ref bases, count) in &seq_counts {
                     fasta_file.write_all(format!(">{}_{} {}\n{}\n", loci, sample, count, bases.as_string()).as_bytes());
                 }
 
-                // Write consensus file stuff
-                consensus_file.write_all(format!("{}\t{}", loci, sample).as_bytes());
-                for &(_, count) in &seq_counts {
-                    consensus_file.write_all(format!("\t{}", count).as_bytes());
-                }
-                consensus_file.write_all(b"\n");
-                for &(ref bases, _) in &seq_counts {
-                    consensus_file.write_all(format!("\n{}", bases.as_string()).as_bytes());
-                }
-                consensus_file.write_all(b"\n\n");
+                locus_calls.push(SampleCall { depth: seqs.len(), haplotypes: seq_counts, genotype: genotype });
             } else {
                 consensus_matrix.write_all(b"\t0");
+                locus_calls.push(SampleCall { depth: seqs.len(), haplotypes: vec!(), genotype: None });
             }
         }
         consensus_matrix.write_all(b"\n");
         count_matrix.write_all(b"\n");
+        if sex_linked {
+            continue;
+        }
+        LocusBalance::new(loci, &locus_balances, params.allele_balance_window).write(&mut balance_file).unwrap();
+
+        if ssr_motifs.contains_key(loci) || params.positional {
+            continue;
+        }
+
+        if let (Some(file), Some(positions)) = (microhaplotypes_file.as_mut(), snp_positions.get(loci)) {
+            let mut typer = Microhaplotyper::new(references.get(loci), positions);
+            for (sample, call) in samples.iter().zip(locus_calls.iter()) {
+                microhaplotypes::write_sample(file, loci, sample, call, &mut typer).unwrap();
+            }
+        }
+
+        if let Some(reference) = references.get(loci) {
+            vcf_records.insert(loci.clone(), variants::locus_records(loci, reference, &locus_calls, params.ploidy));
+        }
+    }
+
+    // Summarize samples and loci so failing ones can be dropped before export, leaving out sex-linked
+    // loci that females don't amplify and males carry one copy of
+    println!("Summarizing QC...");
+    let autosomal: Vec<&String> = loci.iter().filter(|locus| !is_sex_linked(locus)).collect();
+    let sample_qcs: Vec<qc::SampleQc> = samples.iter().map(|sample| {
+        let seen: Vec<&qc::Observation> = autosomal.iter().map(|&locus| &observations[&(locus.clone(), sample.clone())]).collect();
+        qc::sample_qc(sample, &seen, assigner.off_target.get(sample).cloned().unwrap_or(0))
+    }).collect();
+    let locus_qcs: Vec<qc::LocusQc> = autosomal.iter().map(|&locus| {
+        let seen: Vec<&qc::Observation> = samples.iter().map(|sample| &observations[&(locus.clone(), sample.clone())]).collect();
+        qc::locus_qc(locus, &seen)
+    }).collect();
+
+    let mut qc_samples_file = BufWriter::new(File::create(output_dir.join("qc_samples.tsv")).unwrap());
+    qc_samples_file.write_all(header.as_bytes());
+    writeln!(qc_samples_file, "{}", qc::SAMPLE_COLUMNS).unwrap();
+    for sample_qc in &sample_qcs {
+        qc::write_sample_qc(&mut qc_samples_file, sample_qc).unwrap();
+    }
+
+    let mut qc_loci_file = BufWriter::new(File::create(output_dir.join("qc_loci.tsv")).unwrap());
+    qc_loci_file.write_all(header.as_bytes());
+    writeln!(qc_loci_file, "{}", qc::LOCUS_COLUMNS).unwrap();
+    for locus_qc in &locus_qcs {
+        qc::write_locus_qc(&mut qc_loci_file, locus_qc).unwrap();
     }
+
+    let mut qc_json = BTreeMap::new();
+    let parameters: Vec<String> = header.lines().map(|line| line.trim_left_matches("# ").to_string()).collect();
+    qc_json.insert("parameters".to_string(), parameters.to_json());
+    qc_json.insert("samples".to_string(), sample_qcs.to_json());
+    qc_json.insert("loci".to_string(), locus_qcs.to_json());
+    let mut qc_json_file = BufWriter::new(File::create(output_dir.join("qc.json")).unwrap());
+    writeln!(qc_json_file, "{}", Json::Object(qc_json).pretty()).unwrap();
+
+    // Call each sample's sex from its reads at sex-linked loci against autosomal controls
+    if let Some(ref markers) = sex_markers {
+        println!("Calling sex...");
+        let mut sex_file = BufWriter::new(File::create(output_dir.join("sex.tsv")).unwrap());
+        sex_file.write_all(header.as_bytes());
+        writeln!(sex_file, "# sex_min_lod={} sex_background_rate={}", config.sex_min_lod, config.sex_background_rate).unwrap();
+        writeln!(sex_file, "{}", sex::COLUMNS).unwrap();
+
+        // How much each marker shows in males is learned from the run, as amplicons differ
+        let reads = |sample: &str, locus: &str| observations[&(locus.to_string(), sample.to_string())].depth;
+        let model = markers.calibrate(&samples, &reads, config.sex_background_rate);
+        for sample in &samples {
+            let call = markers.call(sample, |locus| reads(sample, locus), &model);
+            sex::write_call(&mut sex_file, &call, config.sex_min_lod).unwrap();
+        }
+    }
+
+    // Write the VCF, loci in the order they were listed
+    if config.reference.is_some() {
+        let mut vcf_file = BufWriter::new(File::create(output_dir.join("variants.vcf")).unwrap());
+
+        let contigs: Vec<(String, usize)> = loci.iter()
+            .filter(|locus| !is_sex_linked(locus))
+            .filter_map(|locus| references.get(locus).map(|reference| (locus.clone(), reference.len())))
+            .collect();
+        let mut meta = vec!["source=call_consensus".to_string()];
+        meta.extend(header.lines().map(|line| format!("call_consensus_parameters={}", line.trim_left_matches("# "))));
+
+        vcf::write_header(&mut vcf_file, &contigs, &samples, &meta).unwrap();
+        for locus in &loci {
+            for record in vcf_records.get(locus).into_iter().flat_map(|records| records.iter()) {
+                vcf::write_record(&mut vcf_file, record).unwrap();
+            }
+        }
+    }
+}
+
+/// Writes a row of the consensus table for each of a sample's haplotypes at a locus
+/// haplotypes must be sorted most reads first, status says what became of each
+fn write_consensus<W, F>(consensus_file: &mut W, locus: &str, sample: &str, depth: usize, haplotypes: &[(bases::Bases, u32)], status: F)
+    where
+        W: Write,
+        F: Fn(&bases::Bases) -> Status,
+{
+    for (rank, &(ref sequence, count)) in haplotypes.iter().enumerate() {
+        let haplotype = consensus::Haplotype {
+            sample: sample.to_string(),
+            locus: locus.to_string(),
+            rank: rank + 1,
+            sequence: sequence.clone(),
+            reads: count,
+            fraction: count as f64 / depth as f64,
+            status: status(sequence),
+        };
+        consensus::write_haplotype(consensus_file, &haplotype).unwrap();
+    }
+}
+
+/// Reads of the two most abundant haplotypes, haplotypes must be sorted most reads first
+fn top_two_reads(haplotypes: &[(bases::Bases, u32)]) -> usize {
+    haplotypes.iter().take(2).fold(0, |sum, &(_, count)| sum + count as usize)
+}
+
+/// Sorts haplotypes most reads first, breaking ties by sequence
+fn sort_haplotypes(haplotypes: &mut Vec<(bases::Bases, u32)>) {
+    haplotypes.sort_by(|&(ref a, a_count), &(ref b, b_count)| {
+        match b_count.cmp(&a_count) {
+            Ordering::Equal => a.cmp(b),
+            order => order,
+        }
+    });
 }
@@ -0,0 +1,160 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use bio::align;
+use bio::bases::Bases;
+
+use variants::SampleCall;
+
+/// Column labels of microhaplotypes.tsv
+pub const COLUMNS: &'static str = "indiv.ID\tlocus\tgene_copy\thaplo\tdepth\tallele.balance\tgq\n";
+
+/// Reads a table of SNP positions, one "<locus><TAB><position>" line per SNP
+/// Positions are 1-based in the file and returned 0-based and sorted
+pub fn read_positions(path: &Path) -> Result<HashMap<String, Vec<usize>>, String> {
+    let file = try!(File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e)));
+
+    let mut positions: HashMap<String, Vec<usize>> = HashMap::new();
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = try!(line.map_err(|e| format!("Failed to read {}: {}", path.display(), e)));
+        let line = line.trim();
+        if line.len() == 0 || line.starts_with("#") {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let position = match (fields.len(), fields.get(1).and_then(|p| p.trim().parse::<usize>().ok())) {
+            (2, Some(position)) if position > 0 => position,
+            _ => return Err(format!("{} line {}: expected <locus><TAB><position>, got {}", path.display(), n + 1, line)),
+        };
+        positions.entry(fields[0].to_string()).or_insert(vec!()).push(position - 1);
+    }
+
+    for locus_positions in positions.values_mut() {
+        locus_positions.sort();
+        locus_positions.dedup();
+    }
+    Ok(positions)
+}
+
+/// Reads the bases of haplotypes at the SNP positions of one locus
+/// Positions are relative to the reference when there is one, otherwise to the haplotype itself
+pub struct Microhaplotyper<'a> {
+    reference: Option<&'a Bases>,
+    positions: &'a [usize],
+    /// Haplotypes already read, since the same ones turn up in many samples
+    cache: HashMap<Bases, String>,
+}
+
+impl<'a> Microhaplotyper<'a> {
+    pub fn new(reference: Option<&'a Bases>, positions: &'a [usize]) -> Microhaplotyper<'a> {
+        Microhaplotyper {
+            reference: reference,
+            positions: positions,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Gets the microhaplotype string of a haplotype, with '-' where a position is deleted or missing
+    pub fn microhaplotype(&mut self, haplotype: &Bases) -> String {
+        if let Some(microhaplotype) = self.cache.get(haplotype) {
+            return microhaplotype.clone();
+        }
+
+        let bases = match self.reference {
+            Some(reference) => align::bases_at(reference, haplotype, self.positions),
+            None => self.positions.iter().map(|&pos| haplotype.bases.get(pos).cloned()).collect(),
+        };
+        let microhaplotype: String = bases.iter().map(|base| base.map(|b| b.to_char()).unwrap_or('-')).collect();
+
+        self.cache.insert(haplotype.clone(), microhaplotype.clone());
+        microhaplotype
+    }
+}
+
+/// Writes a sample's phased genotype at a locus as one row per allele copy
+/// Reads are pooled by microhaplotype, so depth counts every reported haplotype that shares it
+pub fn write_sample<W: io::Write>(
+    out: &mut W,
+    locus: &str,
+    sample: &str,
+    call: &SampleCall,
+    typer: &mut Microhaplotyper,
+) -> io::Result<()>
+{
+    let (alleles, quality) = match call.genotype {
+        Some((ref alleles, quality)) => (alleles, quality),
+        None => return Ok(()),
+    };
+
+    let mut depths: BTreeMap<String, u32> = BTreeMap::new();
+    for &(ref haplotype, count) in &call.haplotypes {
+        *depths.entry(typer.microhaplotype(haplotype)).or_insert(0) += count;
+    }
+    let top_depth = depths.values().max().cloned().unwrap_or(0);
+
+    for (copy, allele) in alleles.iter().enumerate() {
+        let microhaplotype = typer.microhaplotype(allele);
+        let depth = depths.get(&microhaplotype).cloned().unwrap_or(0);
+        let balance = if top_depth > 0 { depth as f64 / top_depth as f64 } else { 0.0 };
+        try!(write!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{:.3}\t{:.0}\n",
+            sample,
+            locus,
+            copy + 1,
+            microhaplotype,
+            depth,
+            balance,
+            quality,
+        ));
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[test]
+fn test_microhaplotype() {
+    let reference = Bases::from_str("GATTACAGATTACA");
+    let positions = [2, 9, 13];
+    let mut typer = Microhaplotyper::new(Some(&reference), &positions);
+
+    assert_eq!(typer.microhaplotype(&reference), "TTA");
+    // A SNP at 2, the AT at 8-9 deleted and a SNP at 13
+    assert_eq!(typer.microhaplotype(&Bases::from_str("GACTACAGTACG")), "C-G");
+
+    // Without a reference positions are read from the haplotype itself, '-' past its end
+    let mut typer = Microhaplotyper::new(None, &positions);
+    assert_eq!(typer.microhaplotype(&Bases::from_str("GACTACAGTACGTT")), "CAT");
+    assert_eq!(typer.microhaplotype(&Bases::from_str("GACTACAGTACG")), "CA-");
+}
+
+#[test]
+fn test_write_sample() {
+    let reference = Bases::from_str("GATTACA");
+    let positions = [1, 3];
+    let mut typer = Microhaplotyper::new(Some(&reference), &positions);
+
+    // GATTACT carries the reference microhaplotype, so its reads are pooled with GATTACA's
+    let call = SampleCall {
+        depth: 100,
+        haplotypes: vec![
+            (Bases::from_str("GATTACA"), 50),
+            (Bases::from_str("GCTCACA"), 40),
+            (Bases::from_str("GATTACT"), 10),
+        ],
+        genotype: Some((vec![Bases::from_str("GATTACA"), Bases::from_str("GCTCACA")], 42.0)),
+    };
+    let mut out: Vec<u8> = vec!();
+    write_sample(&mut out, "L1", "S1", &call, &mut typer).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "S1\tL1\t1\tAT\t60\t1.000\t42\nS1\tL1\t2\tCC\t40\t0.667\t42\n");
+
+    let uncalled = SampleCall { depth: 3, haplotypes: vec!(), genotype: None };
+    let mut out: Vec<u8> = vec!();
+    write_sample(&mut out, "L1", "S2", &uncalled, &mut typer).unwrap();
+    assert!(out.is_empty());
+}
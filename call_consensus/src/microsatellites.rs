@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use bio::bases::Bases;
+use bio::fastq::Sequence;
+use bio::loci::LocusTable;
+use bio::ssr::{self, SsrAllele, SsrCall, StutterModel};
+
+/// Finds the repeat motif of each locus marked "ssr" in the type column of the locus table
+/// A motif column gives the motif, otherwise it's found in the locus's most common read
+pub fn find_motifs(table: &LocusTable, seq_matrix: &HashMap<String, HashMap<String, Vec<Sequence>>>) -> HashMap<String, String> {
+    let mut motifs = HashMap::new();
+    for locus in &table.loci {
+        if table.get(locus, "type") != Some("ssr") {
+            continue;
+        }
+        if let Some(motif) = table.get(locus, "motif") {
+            motifs.insert(locus.clone(), motif.to_string());
+            continue;
+        }
+
+        let mut counts: HashMap<&Bases, usize> = HashMap::new();
+        for seqs in seq_matrix[locus].values() {
+            for seq in seqs {
+                *counts.entry(&seq.bases).or_insert(0) += 1;
+            }
+        }
+        let mut counts: Vec<(&Bases, usize)> = counts.into_iter().collect();
+        counts.sort_by(|&(a, a_count), &(b, b_count)| (b_count, a).cmp(&(a_count, b)));
+
+        match counts.first().and_then(|&(seq, _)| ssr::find_motif(&seq.as_string(), ssr::MAX_PERIOD)) {
+            Some(motif) => { motifs.insert(locus.clone(), motif); },
+            None => println!("WARNING: no repeat motif found for microsatellite locus {}, calling it by haplotype", locus),
+        }
+    }
+    motifs
+}
+
+/// Calls a sample's microsatellite genotype from its haplotype counts
+/// Haplotypes without a copy of the motif are left out
+pub fn call_sample(locus: &str, sample: &str, motif: &str, haplotypes: &[(Bases, u32)], model: &StutterModel) -> Option<SsrCall> {
+    let mut counts: HashMap<SsrAllele, u32> = HashMap::new();
+    for &(ref haplotype, count) in haplotypes {
+        if let Some(allele) = SsrAllele::of_read(&haplotype.as_string(), motif) {
+            *counts.entry(allele).or_insert(0) += count;
+        }
+    }
+
+    // Most reads first, ties broken by allele so calls don't depend on hashing order
+    let mut counts: Vec<(SsrAllele, u32)> = counts.into_iter().collect();
+    counts.sort_by(|&(ref a, a_count), &(ref b, b_count)| (b_count, a).cmp(&(a_count, b)));
+
+    model.call(&counts).map(|call| SsrCall {
+        locus: locus.to_string(),
+        sample: sample.to_string(),
+        depth: counts.iter().fold(0, |sum, &(_, count)| sum + count as usize),
+        motif: motif.to_string(),
+        alleles: call.alleles.iter().map(|&a| counts[a].0.clone()).collect(),
+        quality: call.quality,
+        allele_reads: call.alleles.iter().map(|&a| counts[a].1).collect(),
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn haplotype(units: usize, count: u32) -> (Bases, u32) {
+    let mut sequence = "GATT".to_string();
+    for _ in 0..units {
+        sequence.push_str("AC");
+    }
+    sequence.push_str("GGC");
+    (Bases::from_str(&sequence), count)
+}
+
+#[test]
+fn test_call_sample() {
+    let model = StutterModel::new(2, 0.01, 0.1, 0.02);
+
+    // Stutter at 11 units is absorbed into the 12 unit homozygote, the read without the motif is left out
+    let haplotypes = vec![haplotype(12, 100), haplotype(11, 12), haplotype(13, 2), (Bases::from_str("GATTGGC"), 5)];
+    let call = call_sample("L1", "S1", "AC", &haplotypes, &model).unwrap();
+    assert_eq!((&call.locus[..], &call.sample[..], &call.motif[..]), ("L1", "S1", "AC"));
+    assert_eq!(call.depth, 114);
+    let units: Vec<usize> = call.alleles.iter().map(|allele| allele.units).collect();
+    assert_eq!(units, vec![12, 12]);
+    assert_eq!(call.allele_reads, vec![100, 100]);
+
+    let haplotypes = vec![haplotype(12, 80), haplotype(9, 70), haplotype(11, 8), haplotype(8, 7)];
+    let call = call_sample("L1", "S2", "AC", &haplotypes, &model).unwrap();
+    let units: Vec<usize> = call.alleles.iter().map(|allele| allele.units).collect();
+    assert_eq!(units, vec![12, 9]);
+    assert_eq!(call.allele_reads, vec![80, 70]);
+
+    assert!(call_sample("L1", "S3", "AC", &[(Bases::from_str("GATTGGC"), 50)], &model).is_none());
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bio::align::{self, Variant};
+use bio::bases::Bases;
+use bio::vcf::{Record, SampleData};
+
+/// What was found for one sample at a locus
+pub struct SampleCall {
+    /// Reads assigned to the sample at the locus
+    pub depth: usize,
+    /// Reported haplotypes and their read counts, most abundant first
+    pub haplotypes: Vec<(Bases, u32)>,
+    /// Called alleles, one per copy, with the genotype quality
+    pub genotype: Option<(Vec<Bases>, f64)>,
+}
+
+/// Builds the VCF records of a locus by aligning every haplotype to the locus reference
+/// Sites are the variants carried by called alleles, samples are in the same order as their calls
+pub fn locus_records(locus: &str, reference: &Bases, calls: &[SampleCall], ploidy: usize) -> Vec<Record> {
+    // Align each distinct haplotype once
+    let mut haplotype_variants: HashMap<Bases, Vec<Variant>> = HashMap::new();
+    for call in calls {
+        let called = call.genotype.iter().flat_map(|&(ref alleles, _)| alleles.iter());
+        for haplotype in call.haplotypes.iter().map(|&(ref h, _)| h).chain(called) {
+            if !haplotype_variants.contains_key(haplotype) {
+                haplotype_variants.insert(haplotype.clone(), align::variants(reference, haplotype));
+            }
+        }
+    }
+
+    // Map each site to the alternate alleles called there
+    // Variants without an anchor base, like a deletion of the whole reference, can't be written to
+    // a VCF, though they still leave overlapped sites uncalled in the haplotypes that carry them
+    let mut sites: BTreeMap<(usize, Bases), Vec<Bases>> = BTreeMap::new();
+    for call in calls {
+        if let Some((ref alleles, _)) = call.genotype {
+            for allele in alleles {
+                for variant in haplotype_variants[allele].iter().filter(|v| v.ref_allele.len() > 0 && v.alt_allele.len() > 0) {
+                    let alts = sites.entry((variant.pos, variant.ref_allele.clone())).or_insert(vec!());
+                    if !alts.contains(&variant.alt_allele) {
+                        alts.push(variant.alt_allele.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    sites.into_iter().map(|((pos, ref_allele), mut alts)| {
+        alts.sort_by(|a, b| a.as_string().cmp(&b.as_string()));
+
+        // Index of the allele a haplotype carries at this site
+        // None if it carries an alternate allele that wasn't called in any sample, or another
+        // variant overlapping the site, so its reference bases aren't there either
+        let end = pos + ref_allele.len();
+        let allele_index = |haplotype: &Bases| -> Option<usize> {
+            let variants = &haplotype_variants[haplotype];
+            match variants.iter().find(|v| v.pos == pos && v.ref_allele == ref_allele) {
+                Some(variant) => alts.iter().position(|alt| *alt == variant.alt_allele).map(|i| i + 1),
+                None if variants.iter().any(|v| v.pos < end && pos < v.pos + v.ref_allele.len()) => None,
+                None => Some(0),
+            }
+        };
+
+        let samples = calls.iter().map(|call| {
+            match call.genotype {
+                Some((ref alleles, quality)) => {
+                    let mut allele_depths = vec![0; alts.len() + 1];
+                    for &(ref haplotype, count) in &call.haplotypes {
+                        if let Some(index) = allele_index(haplotype) {
+                            allele_depths[index] += count;
+                        }
+                    }
+
+                    // Genotypes are unphased, so list the alleles in ascending order
+                    let mut genotype: Vec<Option<usize>> = alleles.iter().map(|allele| allele_index(allele)).collect();
+                    genotype.sort();
+
+                    SampleData {
+                        genotype: genotype,
+                        allele_depths: Some(allele_depths),
+                        depth: call.depth as u32,
+                        quality: Some(quality.round() as u32),
+                    }
+                },
+                None => SampleData::missing(ploidy, call.depth as u32),
+            }
+        }).collect();
+
+        Record {
+            chrom: locus.to_string(),
+            pos: pos + 1,
+            ref_allele: ref_allele.as_string(),
+            alt_alleles: alts.iter().map(|alt| alt.as_string()).collect(),
+            samples: samples,
+        }
+    }).collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unit tests
+
+#[cfg(test)]
+fn sample_call(haplotypes: &[(&str, u32)], alleles: &[&str]) -> SampleCall {
+    SampleCall {
+        depth: haplotypes.iter().fold(0, |sum, &(_, count)| sum + count as usize),
+        haplotypes: haplotypes.iter().map(|&(haplotype, count)| (Bases::from_str(haplotype), count)).collect(),
+        genotype: Some((alleles.iter().map(|allele| Bases::from_str(allele)).collect(), 99.0)),
+    }
+}
+
+#[test]
+fn test_het_snp_record() {
+    let reference = Bases::from_str("GATTACA");
+    let calls = vec![
+        sample_call(&[("GATTACA", 60), ("GATCACA", 40)], &["GATCACA", "GATTACA"]),
+        sample_call(&[("GATTACA", 90)], &["GATTACA", "GATTACA"]),
+        SampleCall { depth: 3, haplotypes: vec!(), genotype: None },
+    ];
+
+    let records = locus_records("L1", &reference, &calls, 2);
+    assert_eq!(records.len(), 1);
+    assert_eq!((records[0].pos, &records[0].ref_allele[..]), (4, "T"));
+    assert_eq!(records[0].alt_alleles, vec!["C".to_string()]);
+    assert_eq!(records[0].samples[0].genotype, vec![Some(0), Some(1)]);
+    assert_eq!(records[0].samples[0].allele_depths, Some(vec![60, 40]));
+    assert_eq!(records[0].samples[1].genotype, vec![Some(0), Some(0)]);
+    assert_eq!(records[0].samples[2].genotype, vec![None, None]);
+}
+
+#[test]
+fn test_left_normalized_indel_record() {
+    // Deleting either T of the TT run is reported once, anchored on the A before it
+    let reference = Bases::from_str("CCGATTACACC");
+    let calls = vec![sample_call(&[("CCGATACACC", 50)], &["CCGATACACC", "CCGATACACC"])];
+
+    let records = locus_records("L1", &reference, &calls, 2);
+    assert_eq!(records.len(), 1);
+    assert_eq!((records[0].pos, &records[0].ref_allele[..]), (4, "AT"));
+    assert_eq!(records[0].alt_alleles, vec!["A".to_string()]);
+    assert_eq!(records[0].samples[0].genotype, vec![Some(1), Some(1)]);
+}
+
+#[test]
+fn test_overlapping_variants() {
+    // S2's deletion spans the SNP S1 carries, so S2 has neither allele at the SNP
+    let reference = Bases::from_str("GGCCGATTACACCGG");
+    let calls = vec![
+        sample_call(&[("GGCCGATGACACCGG", 50)], &["GGCCGATGACACCGG", "GGCCGATGACACCGG"]),
+        sample_call(&[("GGCCGACACCGG", 30), ("GGCCGATTACACCGG", 30)], &["GGCCGACACCGG", "GGCCGATTACACCGG"]),
+    ];
+
+    let records = locus_records("L1", &reference, &calls, 2);
+    assert_eq!(records.len(), 2);
+    let snp = records.iter().find(|record| record.ref_allele == "T").unwrap();
+    assert_eq!(snp.samples[0].genotype, vec![Some(1), Some(1)]);
+    assert_eq!(snp.samples[1].genotype, vec![None, Some(0)]);
+    assert_eq!(snp.samples[1].allele_depths, Some(vec![30, 0]));
+}
+
+#[test]
+fn test_skips_deletion_of_whole_reference() {
+    let reference = Bases::from_str("GATTACA");
+    let calls = vec![sample_call(&[("GATCACA", 30), ("", 30)], &["GATCACA", ""])];
+
+    let records = locus_records("L1", &reference, &calls, 2);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].alt_alleles, vec!["C".to_string()]);
+    assert_eq!(records[0].samples[0].genotype, vec![None, Some(1)]);
+}
@@ -0,0 +1,128 @@
+extern crate bio;
+extern crate getopts;
+
+use std::fs::File;
+use std::io::{
+    BufReader,
+    BufWriter,
+    Write,
+};
+use std::path::Path;
+use std::process;
+
+use getopts::Options;
+
+use bio::calls;
+use bio::cli::{fail, parse_opt};
+use bio::concordance::{Concordance, GenotypeTable};
+use bio::metadata::SampleMetadata;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "genotypes", "call_consensus genotype calls (default genotypes.tsv)", "FILE");
+    opts.optopt("", "metadata", "tab separated sample metadata declaring replicates, with a header line and a sample column", "FILE");
+    opts.optopt("", "replicate-column", "metadata column naming the individual each sample is from (default individual)", "NAME");
+    opts.optopt("", "min-concordance", "concordance replicates must reach, and above which other pairs are reported as duplicates (default 0.95)", "F");
+    opts.optopt("", "min-shared-loci", "fewest loci called in both samples for a pair to be judged (default 10)", "N");
+    opts.optopt("o", "output-prefix", "prefix of the files written (default concordance)", "PREFIX");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(1);
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: concordance [options]"));
+        return;
+    }
+
+    let min_concordance: f64 = parse_opt(&matches, "min-concordance", 0.95);
+    let min_shared_loci: usize = parse_opt(&matches, "min-shared-loci", 10);
+
+    let calls_path = matches.opt_str("genotypes").unwrap_or("genotypes.tsv".to_string());
+    let mut calls_file = BufReader::new(File::open(&Path::new(&calls_path)).unwrap_or_else(|error| fail(&format!("{}: {}", calls_path, error))));
+    let calls = calls::read_calls(&mut calls_file).unwrap_or_else(|error| fail(&format!("{}: {}", calls_path, error)));
+    let table = GenotypeTable::new(&calls);
+
+    // Samples from the same individual are declared replicates
+    let individuals: Option<Vec<Option<String>>> = matches.opt_str("metadata").map(|path| {
+        let column = matches.opt_str("replicate-column").unwrap_or("individual".to_string());
+        let mut metadata_file = BufReader::new(File::open(&Path::new(&path)).unwrap_or_else(|error| fail(&format!("{}: {}", path, error))));
+        let metadata = SampleMetadata::read(&mut metadata_file).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+        if !metadata.has_column(&column) {
+            fail::<()>(&format!("{} has no {} column", path, column));
+        }
+        table.samples.iter().map(|sample| metadata.get(sample, &column).map(|individual| individual.to_string())).collect()
+    });
+    let declared = |a: usize, b: usize| -> bool {
+        match individuals {
+            Some(ref individuals) => individuals[a].is_some() && individuals[a] == individuals[b],
+            None => false,
+        }
+    };
+
+    let n = table.samples.len();
+    let mut pairs: Vec<Vec<Concordance>> = vec!();
+    for a in 0..n {
+        pairs.push((0..n).map(|b| table.compare(&table.samples[a], &table.samples[b])).collect());
+    }
+
+    let prefix = matches.opt_str("output-prefix").unwrap_or("concordance".to_string());
+
+    // Discordance of every pair, NA where no locus was called in both
+    let mut matrix_file = BufWriter::new(File::create(&Path::new(&format!("{}_matrix.tsv", prefix))).unwrap());
+    writeln!(matrix_file, "\t{}", table.samples.connect("\t")).unwrap();
+    for a in 0..n {
+        let row: Vec<String> = pairs[a].iter().map(|concordance| {
+            concordance.discordance().map(|discordance| format!("{:.4}", discordance)).unwrap_or("NA".to_string())
+        }).collect();
+        writeln!(matrix_file, "{}\t{}", table.samples[a], row.connect("\t")).unwrap();
+    }
+
+    // Declared replicates and the pairs that look like duplicates
+    let mut report_file = BufWriter::new(File::create(&Path::new(&format!("{}_pairs.tsv", prefix))).unwrap());
+    writeln!(report_file, "sample_1\tsample_2\tshared_loci\tconcordant\tconcordance\tdeclared_replicates\tstatus").unwrap();
+    let (mut discordant_replicates, mut duplicates) = (0, 0);
+    for a in 0..n {
+        for b in (a + 1)..n {
+            let concordance = pairs[a][b];
+            let matching = concordance.shared_loci >= min_shared_loci &&
+                concordance.fraction().map(|fraction| fraction >= min_concordance).unwrap_or(false);
+
+            let status = if declared(a, b) {
+                if concordance.shared_loci < min_shared_loci {
+                    "too_few_shared_loci"
+                } else if matching {
+                    "concordant_replicates"
+                } else {
+                    discordant_replicates += 1;
+                    "discordant_replicates"
+                }
+            } else if matching {
+                duplicates += 1;
+                "undeclared_duplicate"
+            } else {
+                continue;
+            };
+
+            writeln!(
+                report_file,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                table.samples[a],
+                table.samples[b],
+                concordance.shared_loci,
+                concordance.concordant,
+                concordance.fraction().map(|fraction| format!("{:.4}", fraction)).unwrap_or("NA".to_string()),
+                if declared(a, b) { "yes" } else { "no" },
+                status,
+            ).unwrap();
+        }
+    }
+
+    println!("Compared {} samples: {} discordant replicate pairs, {} undeclared duplicates", n, discordant_replicates, duplicates);
+}
@@ -0,0 +1,119 @@
+extern crate bio;
+extern crate getopts;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{
+    BufReader,
+    BufWriter,
+    Write,
+};
+use std::path::Path;
+use std::process;
+
+use getopts::Options;
+
+use bio::cli::{fail, parse_opt};
+use bio::consensus;
+use bio::contamination::{self, HaplotypeMatrix, Well};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "consensus", "call_consensus haplotype table (default consensus.tsv)", "FILE");
+    opts.optopt("", "oligos", "yo_deoligo oligo table, with each sample's well (e.g. B07) and optionally plate in extra columns", "FILE");
+    opts.optopt("", "max-sources", "most likely sources listed per sample (default 3)", "N");
+    opts.optopt("o", "output-prefix", "prefix of the files written (default contamination)", "PREFIX");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(1);
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: contamination [options]"));
+        return;
+    }
+
+    let consensus_path = matches.opt_str("consensus").unwrap_or("consensus.tsv".to_string());
+    let mut consensus_file = BufReader::new(File::open(&Path::new(&consensus_path)).unwrap_or_else(|error| {
+        fail(&format!("{}: {}", consensus_path, error))
+    }));
+    let haplotypes = consensus::read_consensus(&mut consensus_file).unwrap_or_else(|error| {
+        fail(&format!("{}: {}", consensus_path, error))
+    });
+    let matrix = HaplotypeMatrix::new(&haplotypes);
+
+    let max_sources: usize = parse_opt(&matches, "max-sources", 3);
+
+    // Wells of the samples in the oligo table, samples without a well have no neighbours
+    let wells: Option<HashMap<String, Well>> = matches.opt_str("oligos").map(|path| {
+        let mut oligos_file = BufReader::new(File::open(&Path::new(&path)).unwrap_or_else(|error| fail(&format!("{}: {}", path, error))));
+        let wells = contamination::read_wells(&mut oligos_file).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+        if wells.len() == 0 {
+            println!("WARNING: {} gives no wells, so neighbours can't be found", path);
+        }
+        wells
+    });
+    let neighbours = |sample: &str| -> Option<Vec<&str>> {
+        wells.as_ref().and_then(|wells| wells.get(sample).map(|well| {
+            matrix.samples.iter()
+                .filter(|other| wells.get(*other).map(|w| w.is_neighbour(well)).unwrap_or(false))
+                .map(|other| &other[..])
+                .collect()
+        }))
+    };
+
+    let prefix = matches.opt_str("output-prefix").unwrap_or("contamination".to_string());
+    let mut summary_file = BufWriter::new(File::create(&Path::new(&format!("{}.tsv", prefix))).unwrap());
+    let mut sources_file = BufWriter::new(File::create(&Path::new(&format!("{}_sources.tsv", prefix))).unwrap());
+    writeln!(summary_file, "sample\treads\tloci\tcontamination\tneighbour_contamination\tlikely_sources").unwrap();
+    writeln!(sources_file, "sample\tsource\tfraction\tinformative_loci\tneighbour").unwrap();
+
+    for sample in &matrix.samples {
+        let others: Vec<&str> = matrix.samples.iter().map(|other| &other[..]).collect();
+        let sample_neighbours = neighbours(sample);
+        let neighbour_contamination = match sample_neighbours {
+            Some(ref sample_neighbours) => format!("{:.4}", matrix.foreign_fraction(sample, sample_neighbours)),
+            None => "NA".to_string(),
+        };
+
+        let sources = matrix.likely_sources(sample);
+        let listed: Vec<String> = sources.iter().take(max_sources)
+            .map(|source| format!("{}={:.4}", source.source, source.fraction))
+            .collect();
+        writeln!(
+            summary_file,
+            "{}\t{}\t{}\t{:.4}\t{}\t{}",
+            sample,
+            matrix.depth(sample),
+            matrix.num_called(sample),
+            matrix.foreign_fraction(sample, &others),
+            neighbour_contamination,
+            if listed.len() > 0 { listed.connect(",") } else { ".".to_string() },
+        ).unwrap();
+
+        for source in &sources {
+            let neighbour = match sample_neighbours {
+                Some(ref sample_neighbours) if sample_neighbours.contains(&&source.source[..]) => "yes",
+                Some(_) => "no",
+                None => "NA",
+            };
+            writeln!(
+                sources_file,
+                "{}\t{}\t{:.4}\t{}\t{}",
+                sample,
+                source.source,
+                source.fraction,
+                source.informative_loci,
+                neighbour,
+            ).unwrap();
+        }
+    }
+
+    println!("Estimated contamination of {} samples", matrix.samples.len());
+}
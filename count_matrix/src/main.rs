@@ -0,0 +1,141 @@
+extern crate bio;
+extern crate flate2;
+extern crate getopts;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{
+    self,
+    BufRead,
+    BufReader,
+    BufWriter,
+    Read,
+    Write,
+};
+use std::path::Path;
+use std::process;
+
+use flate2::read::MultiGzDecoder;
+use getopts::Options;
+
+use bio::assign::Assigner;
+use bio::fastq;
+use bio::loci::LocusTable;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "fastq", "demultiplexed reads, optionally gzipped (default sorted.fastq)", "FILE");
+    opts.optopt("", "samples", "file listing one sample per line (default samples)", "FILE");
+    opts.optopt("", "loci", "file listing one locus per line, or a locus table with a header line (default loci)", "FILE");
+    opts.optopt("o", "output", "matrix to write (default sample_locus_matrix.tsv)", "FILE");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(1);
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: count-matrix [options]"));
+        return;
+    }
+
+    let fastq_path = matches.opt_str("fastq").unwrap_or("sorted.fastq".to_string());
+    let samples = read_names(&Path::new(&matches.opt_str("samples").unwrap_or("samples".to_string())));
+    let loci = read_loci(&Path::new(&matches.opt_str("loci").unwrap_or("loci".to_string())));
+    let output_path = matches.opt_str("output").unwrap_or("sample_locus_matrix.tsv".to_string());
+
+    // Rows are loci and columns are samples
+    let sample_columns: HashMap<String, usize> = samples.iter().cloned().enumerate().map(|(i, s)| (s, i)).collect();
+    let locus_rows: HashMap<String, usize> = loci.iter().cloned().enumerate().map(|(i, l)| (l, i)).collect();
+    let mut counts = vec![vec![0usize; samples.len()]; loci.len()];
+
+    // Count reads one at a time rather than reading the whole file in
+    let mut fastq_file = match open_fastq(&Path::new(&fastq_path)) {
+        Ok(fastq_file) => fastq_file,
+        Err(error) => {
+            println!("Failed to open {}: {}", fastq_path, error);
+            process::exit(1);
+        },
+    };
+    let mut assigner = Assigner::new(&samples, &loci);
+    let mut records = fastq::records(&mut fastq_file);
+    for seq in records.by_ref() {
+        if let Ok((sample, locus)) = assigner.assign(&seq) {
+            counts[locus_rows[&locus]][sample_columns[&sample]] += 1;
+        }
+    }
+    // A damaged or truncated file would otherwise give counts of just the reads before the damage
+    if let Some(error) = records.error() {
+        println!("Failed to read {}: {}", fastq_path, error);
+        process::exit(1);
+    }
+
+    let mut matrix_file = BufWriter::new(File::create(&Path::new(&output_path)).unwrap());
+    matrix_file.write_all(format!("\t{}\n", samples.connect("\t")).as_bytes()).unwrap();
+    for (locus, row) in loci.iter().zip(counts.iter()) {
+        let row: Vec<String> = row.iter().map(|count| count.to_string()).collect();
+        matrix_file.write_all(format!("{}\t{}\n", locus, row.connect("\t")).as_bytes()).unwrap();
+    }
+
+    // Report reads that didn't make it into the matrix
+    if assigner.num_unassigned() > 0 {
+        let stdout = io::stdout();
+        assigner.write_report(&mut stdout.lock()).unwrap();
+    }
+}
+
+/// Reads a file listing one name per line, skipping blank lines
+fn read_names(path: &Path) -> Vec<String> {
+    let file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(error) => {
+            println!("Failed to open {}: {}", path.display(), error);
+            process::exit(1);
+        },
+    };
+
+    let names: Vec<String> = file.lines()
+        .map(|line| line.ok().expect(&format!("Failed to read {}", path.display())).trim().to_string())
+        .filter(|name| name.len() > 0)
+        .collect();
+    if names.len() == 0 {
+        println!("No names found in {}", path.display());
+        process::exit(1);
+    }
+    names
+}
+
+/// Reads the loci of a locus list or table
+fn read_loci(path: &Path) -> Vec<String> {
+    let table = File::open(path).and_then(|file| LocusTable::read(&mut BufReader::new(file)));
+    match table {
+        Ok(ref table) if table.loci.len() > 0 => table.loci.clone(),
+        Ok(_) => {
+            println!("No names found in {}", path.display());
+            process::exit(1);
+        },
+        Err(error) => {
+            println!("Failed to read {}: {}", path.display(), error);
+            process::exit(1);
+        },
+    }
+}
+
+/// Opens a fastq file, decompressing it if it is gzipped
+fn open_fastq(path: &Path) -> io::Result<BufReader<Box<Read>>> {
+    let mut file = BufReader::new(try!(File::open(path)));
+
+    // Gzip files start with the magic bytes 1f 8b
+    let gzipped = try!(file.fill_buf()).starts_with(&[0x1f, 0x8b]);
+    let reader: Box<Read> = if gzipped {
+        Box::new(try!(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    };
+    Ok(BufReader::new(reader))
+}
@@ -0,0 +1,209 @@
+extern crate bio;
+extern crate getopts;
+
+use std::fs::File;
+use std::io::{
+    BufRead,
+    BufReader,
+    BufWriter,
+};
+use std::path::Path;
+use std::process;
+
+use getopts::Options;
+
+use bio::calls;
+use bio::cli::fail;
+use bio::export::{self, AlleleCodes, GenotypeMatrix, Marker};
+use bio::metadata::SampleMetadata;
+use bio::vcf;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "level", "export whole haplotypes or the SNPs in them: haplotype or snp (default haplotype)", "LEVEL");
+    opts.optopt("", "genotypes", "call_consensus genotype calls, used at the haplotype level (default genotypes.tsv)", "FILE");
+    opts.optopt("", "vcf", "call_consensus variants, used at the SNP level (default variants.vcf)", "FILE");
+    opts.optopt("", "samples", "file listing one sample per line, sets the order of samples", "FILE");
+    opts.optopt("", "metadata", "tab separated sample metadata with a header line and a sample column", "FILE");
+    opts.optopt("", "population-column", "metadata column grouping samples into populations (default population)", "NAME");
+    opts.optopt("", "allele-codes", "table of allele codes, read if it exists and updated (default allele_codes.tsv)", "FILE");
+    opts.optopt("o", "output-prefix", "prefix of the files written (default genotypes)", "PREFIX");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(1);
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: genotype-export [options]"));
+        return;
+    }
+
+    let codes_path = matches.opt_str("allele-codes").unwrap_or("allele_codes.tsv".to_string());
+    let mut codes = if Path::new(&codes_path).exists() {
+        let mut codes_file = BufReader::new(File::open(&Path::new(&codes_path)).unwrap());
+        AlleleCodes::read(&mut codes_file).unwrap_or_else(|error| fail(&format!("{}: {}", codes_path, error)))
+    } else {
+        AlleleCodes::new()
+    };
+
+    let level = matches.opt_str("level").unwrap_or("haplotype".to_string());
+    let mut matrix = match &level[..] {
+        "haplotype" => {
+            let path = matches.opt_str("genotypes").unwrap_or("genotypes.tsv".to_string());
+            haplotype_matrix(&Path::new(&path), &mut codes)
+        },
+        "snp" => {
+            let path = matches.opt_str("vcf").unwrap_or("variants.vcf".to_string());
+            snp_matrix(&Path::new(&path), &mut codes)
+        },
+        _ => fail(&format!("Unknown level {}, expected haplotype or snp", level)),
+    };
+
+    if let Some(path) = matches.opt_str("samples") {
+        let samples: Vec<String> = BufReader::new(File::open(&Path::new(&path)).unwrap()).lines()
+            .map(|line| line.unwrap().trim().to_string())
+            .filter(|sample| sample.len() > 0)
+            .collect();
+        reorder_samples(&mut matrix, &samples);
+    }
+
+    // Samples missing from the metadata or without a population are grouped together
+    let populations: Option<Vec<String>> = matches.opt_str("metadata").map(|path| {
+        let column = matches.opt_str("population-column").unwrap_or("population".to_string());
+        let mut metadata_file = BufReader::new(File::open(&Path::new(&path)).unwrap());
+        let metadata = SampleMetadata::read(&mut metadata_file).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+        if !metadata.has_column(&column) {
+            fail::<()>(&format!("{} has no {} column", path, column));
+        }
+        matrix.samples.iter().map(|sample| metadata.get(sample, &column).unwrap_or("unknown").to_string()).collect()
+    });
+    let populations = populations.as_ref().map(|populations| &populations[..]);
+
+    let prefix = matches.opt_str("output-prefix").unwrap_or("genotypes".to_string());
+    let create = |extension: &str| BufWriter::new(File::create(&Path::new(&format!("{}.{}", prefix, extension))).unwrap());
+
+    let title = format!("genotype-export {} level", level);
+    export::write_genepop(&mut create("gen"), &matrix, populations, &title).unwrap();
+    export::write_structure(&mut create("str"), &matrix, populations).unwrap();
+    let skipped = matrix.markers.len() - export::plink_markers(&matrix).len();
+    if skipped > 0 {
+        println!("WARNING: {} markers aren't biallelic diploid or haploid and were left out of the PLINK files", skipped);
+    }
+    export::write_ped(&mut create("ped"), &matrix, populations).unwrap();
+    export::write_map(&mut create("map"), &matrix).unwrap();
+    export::write_adegenet(&mut create("csv"), &matrix, populations).unwrap();
+
+    let mut codes_file = BufWriter::new(File::create(&Path::new(&codes_path)).unwrap());
+    codes.write(&mut codes_file).unwrap();
+
+    println!("Exported {} samples at {} markers", matrix.samples.len(), matrix.markers.len());
+}
+
+/// Builds the matrix of called haplotypes, one marker per locus
+fn haplotype_matrix(path: &Path, codes: &mut AlleleCodes) -> GenotypeMatrix {
+    let mut calls_file = BufReader::new(File::open(path).unwrap_or_else(|error| fail(&format!("{}: {}", path.display(), error))));
+    let calls = calls::read_calls(&mut calls_file).unwrap_or_else(|error| fail(&format!("{}: {}", path.display(), error)));
+
+    // Loci and samples in the order they first appear
+    let mut markers: Vec<Marker> = vec!();
+    let mut samples: Vec<String> = vec!();
+    for call in &calls {
+        if !markers.iter().any(|marker| marker.name == call.locus) {
+            markers.push(Marker { name: call.locus.clone(), position: None, ploidy: call.ploidy });
+        }
+        if !samples.contains(&call.sample) {
+            samples.push(call.sample.clone());
+        }
+    }
+
+    for marker in &markers {
+        let alleles: Vec<String> = calls.iter()
+            .filter(|call| call.locus == marker.name)
+            .flat_map(|call| call.alleles.iter().map(|allele| allele.as_string()))
+            .collect();
+        codes.add(&marker.name, &alleles);
+    }
+
+    let mut genotypes: Vec<Vec<Vec<Option<usize>>>> = samples.iter().map(|_| {
+        markers.iter().map(|marker| vec![None; marker.ploidy]).collect()
+    }).collect();
+    for call in &calls {
+        let s = samples.iter().position(|sample| *sample == call.sample).unwrap();
+        let m = markers.iter().position(|marker| marker.name == call.locus).unwrap();
+        let mut genotype: Vec<Option<usize>> = call.alleles.iter().map(|allele| codes.code(&call.locus, &allele.as_string())).collect();
+        genotype.sort();
+        genotypes[s][m] = genotype;
+    }
+
+    GenotypeMatrix {
+        markers: markers,
+        samples: samples,
+        genotypes: genotypes,
+    }
+}
+
+/// Builds the matrix of called SNPs and indels, one marker per VCF record named <locus>_<position>
+fn snp_matrix(path: &Path, codes: &mut AlleleCodes) -> GenotypeMatrix {
+    let mut vcf_file = BufReader::new(File::open(path).unwrap_or_else(|error| fail(&format!("{}: {}", path.display(), error))));
+    let (samples, records) = vcf::read_vcf(&mut vcf_file).unwrap_or_else(|error| fail(&format!("{}: {}", path.display(), error)));
+
+    let mut markers = vec!();
+    let mut genotypes: Vec<Vec<Vec<Option<usize>>>> = samples.iter().map(|_| vec!()).collect();
+    for record in &records {
+        let name = format!("{}_{}", record.chrom, record.pos);
+        let mut alleles = vec![record.ref_allele.clone()];
+        alleles.extend(record.alt_alleles.iter().cloned());
+        codes.add(&name, &alleles);
+
+        let ploidy = record.samples.iter().map(|sample| sample.genotype.len()).max().unwrap_or(2);
+        for (s, sample) in record.samples.iter().enumerate() {
+            let mut genotype: Vec<Option<usize>> = sample.genotype.iter()
+                .map(|index| index.and_then(|i| alleles.get(i)).and_then(|allele| codes.code(&name, allele)))
+                .collect();
+            genotype.sort();
+            while genotype.len() < ploidy {
+                genotype.push(None);
+            }
+            genotypes[s].push(genotype);
+        }
+
+        markers.push(Marker { name: name, position: Some((record.chrom.clone(), record.pos)), ploidy: ploidy });
+    }
+
+    GenotypeMatrix {
+        markers: markers,
+        samples: samples,
+        genotypes: genotypes,
+    }
+}
+
+/// Puts samples in the listed order, adding listed samples without calls as missing
+/// Samples that aren't listed keep their place after the listed ones
+fn reorder_samples(matrix: &mut GenotypeMatrix, order: &[String]) {
+    let missing: Vec<Vec<Option<usize>>> = matrix.markers.iter().map(|marker| vec![None; marker.ploidy]).collect();
+
+    let mut samples = vec!();
+    let mut genotypes = vec!();
+    for sample in order {
+        samples.push(sample.clone());
+        match matrix.samples.iter().position(|s| s == sample) {
+            Some(s) => genotypes.push(matrix.genotypes[s].clone()),
+            None => genotypes.push(missing.clone()),
+        }
+    }
+    for (s, sample) in matrix.samples.iter().enumerate() {
+        if !order.contains(sample) {
+            samples.push(sample.clone());
+            genotypes.push(matrix.genotypes[s].clone());
+        }
+    }
+
+    matrix.samples = samples;
+    matrix.genotypes = genotypes;
+}
@@ -1,34 +1,88 @@
 extern crate bio;
+extern crate getopts;
 
-use std::collections::HashMap;
-use std::collections::hash_map::Entry;
-use std::old_io::{
-    BufferedReader,
-    BufferedWriter,
-    File,
+use std::fs::File;
+use std::io::{
+    BufReader,
+    BufWriter,
 };
+use std::path::Path;
 
-use bio::bases;
+use getopts::Options;
+
+use bio::cli::{parse_opt, parse_opt_maybe};
 use bio::fastq;
+use bio::join::{self, Spacer};
 
 fn main() {
-    let mut forward_fastq = BufferedReader::new(File::open(&Path::new("forward.fastq")));
-    let mut reverse_fastq = BufferedReader::new(File::open(&Path::new("reverse.fastq")));
-    
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "spacer-length", "N bases placed between the reads (default 10)", "N");
+    opts.optopt("", "spacer-quality", "Phred quality of the spacer bases (default 2)", "Q");
+    opts.optopt("", "truncate-forward", "keep at most N bases of the forward read", "N");
+    opts.optopt("", "truncate-reverse", "keep at most N bases of the reverse read", "N");
+    opts.optopt("", "trim-quality", "trim 3' bases below Phred quality Q from both reads", "Q");
+    opts.optopt("", "min-length", "discard pairs where either trimmed read is shorter than N (default 1)", "N");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: njoiner [options]"));
+        return;
+    }
+
+    let spacer = Spacer::new(parse_opt(&matches, "spacer-length", 10), parse_opt(&matches, "spacer-quality", 2));
+    let truncate_forward: Option<usize> = parse_opt_maybe(&matches, "truncate-forward");
+    let truncate_reverse: Option<usize> = parse_opt_maybe(&matches, "truncate-reverse");
+    let trim_quality: Option<u8> = parse_opt_maybe(&matches, "trim-quality");
+    let min_length: usize = parse_opt(&matches, "min-length", 1);
+
+    let mut forward_fastq = BufReader::new(File::open(&Path::new("forward.fastq")).unwrap());
+    let mut reverse_fastq = BufReader::new(File::open(&Path::new("reverse.fastq")).unwrap());
+
     let forward_seqs = fastq::read_fastq(&mut forward_fastq);
     let reverse_seqs = fastq::read_fastq(&mut reverse_fastq);
 
-    let joined_seqs_iter = forward_seqs.into_iter().zip(reverse_seqs.into_iter()).map(|(forward_seq, mut reverse_seq)| {
-        reverse_seq.bases.reverse_complement();
-        reverse_seq.qual = reverse_seq.qual.chars().rev().collect();
-        fastq::Sequence {
-            header: forward_seq.header,
-            bases: forward_seq.bases + &bases::Bases::from_str("NNNNNNNNNN") + &reverse_seq.bases,
-            qual: forward_seq.qual + "NNNNNNNNNN" + &reverse_seq.qual,
-        }
+    let num_pairs = forward_seqs.len();
+
+    // Trim both reads before joining, dropping pairs that end up too short
+    let trimmed_pairs: Vec<(fastq::Sequence, fastq::Sequence)> =
+        forward_seqs.into_iter().zip(reverse_seqs.into_iter()).filter_map(|(mut forward_seq, mut reverse_seq)| {
+            trim(&mut forward_seq, truncate_forward, trim_quality);
+            trim(&mut reverse_seq, truncate_reverse, trim_quality);
+
+            if forward_seq.bases.len() < min_length || reverse_seq.bases.len() < min_length {
+                None
+            } else {
+                Some((forward_seq, reverse_seq))
+            }
+        }).collect();
+
+    println!("Discarded {} of {} pairs shorter than {} bases after trimming", num_pairs - trimmed_pairs.len(), num_pairs, min_length);
+
+    let joined_seqs_iter = trimmed_pairs.iter().map(|&(ref forward_seq, ref reverse_seq)| {
+        join::concatenate(forward_seq, reverse_seq, &spacer)
     });
 
-    let mut sorted_fastq = BufferedWriter::new(File::create(&Path::new("joined.fastq"))); 
+    let mut sorted_fastq = BufWriter::new(File::create(&Path::new("joined.fastq")).unwrap());
+
+    fastq::write_fastq_owned(&mut sorted_fastq, joined_seqs_iter).ok()
+        .expect("Failed to write joined fastq file");
+}
 
-    fastq::write_fastq_owned(&mut sorted_fastq, joined_seqs_iter);
+/// Truncates a read to a fixed length, then trims low quality bases off its 3' end
+fn trim(seq: &mut fastq::Sequence, truncate_length: Option<usize>, trim_quality: Option<u8>) {
+    if let Some(length) = truncate_length {
+        seq.truncate(length);
+    }
+    if let Some(quality) = trim_quality {
+        seq.quality_trim(quality);
+    }
 }
@@ -0,0 +1,189 @@
+extern crate bio;
+extern crate getopts;
+
+use std::fs::File;
+use std::io::{
+    BufRead,
+    BufReader,
+    BufWriter,
+    Write,
+};
+use std::path::Path;
+use std::process;
+
+use getopts::Options;
+
+use bio::calls;
+use bio::cli::{fail, parse_opt};
+use bio::concordance::GenotypeTable;
+use bio::parentage::{self, Assignment, Parentage};
+
+/// Thresholds an assignment has to meet
+struct Criteria {
+    min_loci: usize,
+    max_exclusions: usize,
+    min_delta: f64,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "genotypes", "call_consensus genotype calls (default genotypes.tsv)", "FILE");
+    opts.optopt("", "offspring", "offspring samples, one per line", "FILE");
+    opts.optopt("", "parents", "candidate parent samples, one per line", "FILE");
+    opts.optopt("", "error-rate", "chance an offspring genotype is wrong, for scoring LOD, between 0 and 1 (default 0.01)", "F");
+    opts.optopt("", "max-exclusions", "most Mendelian exclusions an assigned parent or pair may have (default 1)", "N");
+    opts.optopt("", "min-loci", "fewest loci typed in both the offspring and a candidate for it to be considered (default 10)", "N");
+    opts.optopt("", "min-delta", "LOD the best candidate must lead the next by to be assigned (default 2.0)", "F");
+    opts.optopt("", "max-candidates", "candidates and pairs listed per offspring (default 3)", "N");
+    opts.optopt("o", "output-prefix", "prefix of the files written (default parentage)", "PREFIX");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(1);
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: parentage --offspring FILE --parents FILE [options]"));
+        return;
+    }
+
+    let error_rate: f64 = parse_opt(&matches, "error-rate", 0.01);
+    // Without any chance of error a single exclusion scores -inf, and LODs can't be ranked
+    if !(error_rate > 0.0 && error_rate < 1.0) {
+        fail::<()>(&format!("--error-rate must be between 0 and 1, not {}", error_rate));
+    }
+    let max_candidates: usize = parse_opt(&matches, "max-candidates", 3);
+    let criteria = Criteria {
+        min_loci: parse_opt(&matches, "min-loci", 10),
+        max_exclusions: parse_opt(&matches, "max-exclusions", 1),
+        min_delta: parse_opt(&matches, "min-delta", 2.0),
+    };
+
+    let calls_path = matches.opt_str("genotypes").unwrap_or("genotypes.tsv".to_string());
+    let mut calls_file = BufReader::new(File::open(&Path::new(&calls_path)).unwrap_or_else(|error| fail(&format!("{}: {}", calls_path, error))));
+    let calls = calls::read_calls(&mut calls_file).unwrap_or_else(|error| fail(&format!("{}: {}", calls_path, error)));
+    let table = GenotypeTable::new(&calls);
+    let scorer = Parentage::new(&table, error_rate);
+
+    let offspring = read_samples(&matches.opt_str("offspring").unwrap_or_else(|| fail("--offspring is required")));
+    let parents = read_samples(&matches.opt_str("parents").unwrap_or_else(|| fail("--parents is required")));
+    for sample in offspring.iter().chain(parents.iter()) {
+        if !table.samples.contains(sample) {
+            println!("WARNING: no genotypes for sample {}", sample);
+        }
+    }
+    let parents: Vec<String> = parents.into_iter().filter(|parent| table.samples.contains(parent)).collect();
+
+    let prefix = matches.opt_str("output-prefix").unwrap_or("parentage".to_string());
+    let mut summary_file = BufWriter::new(File::create(&Path::new(&format!("{}.tsv", prefix))).unwrap());
+    let mut candidates_file = BufWriter::new(File::create(&Path::new(&format!("{}_candidates.tsv", prefix))).unwrap());
+    writeln!(
+        summary_file,
+        "offspring\tparent\tparent_loci\tparent_exclusions\tparent_lod\tparent_delta\tparent_status\t\
+         pair\tpair_loci\tpair_exclusions\tpair_lod\tpair_delta\tpair_status"
+    ).unwrap();
+    writeln!(candidates_file, "offspring\trank\tparents\tloci\texclusions\tlod").unwrap();
+
+    let (mut assigned_parents, mut assigned_pairs) = (0, 0);
+    for child in &offspring {
+        let candidates: Vec<&str> = parents.iter().filter(|parent| *parent != child).map(|parent| &parent[..]).collect();
+
+        let mut singles: Vec<Assignment> = candidates.iter().map(|&parent| scorer.assess(child, &[parent])).collect();
+
+        // A pair can't have fewer exclusions than either parent alone, so pairs are only made of
+        // parents that pass on their own
+        let compatible: Vec<&str> = candidates.iter().zip(singles.iter())
+            .filter(|&(_, single)| single.exclusions <= criteria.max_exclusions)
+            .map(|(&parent, _)| parent)
+            .collect();
+        let mut pairs: Vec<Assignment> = vec!();
+        for (i, &a) in compatible.iter().enumerate() {
+            for &b in &compatible[(i + 1)..] {
+                pairs.push(scorer.assess(child, &[a, b]));
+            }
+        }
+        // Candidates typed at too few loci would compete on a LOD near 0
+        singles.retain(|assignment| assignment.loci >= criteria.min_loci);
+        pairs.retain(|assignment| assignment.loci >= criteria.min_loci);
+        parentage::rank(&mut singles);
+        parentage::rank(&mut pairs);
+
+        let (parent_summary, parent_status) = summarize(&singles, &criteria);
+        let (pair_summary, pair_status) = summarize(&pairs, &criteria);
+        if parent_status == "assigned" {
+            assigned_parents += 1;
+        }
+        if pair_status == "assigned" {
+            assigned_pairs += 1;
+        }
+        writeln!(summary_file, "{}\t{}\t{}", child, parent_summary, pair_summary).unwrap();
+
+        for ranked in &[&singles, &pairs] {
+            for (rank, assignment) in ranked.iter().take(max_candidates).enumerate() {
+                writeln!(
+                    candidates_file,
+                    "{}\t{}\t{}\t{}\t{}\t{:.3}",
+                    child,
+                    rank + 1,
+                    assignment.parents.connect(","),
+                    assignment.loci,
+                    assignment.exclusions,
+                    assignment.lod,
+                ).unwrap();
+            }
+        }
+    }
+
+    println!(
+        "Assigned a parent to {} and a parent pair to {} of {} offspring",
+        assigned_parents,
+        assigned_pairs,
+        offspring.len(),
+    );
+}
+
+/// Summary columns of the best of ranked assignments, and its status
+fn summarize(ranked: &[Assignment], criteria: &Criteria) -> (String, &'static str) {
+    let best = match ranked.first() {
+        Some(best) => best,
+        None => return (".\t0\t0\tNA\tNA\tno_candidates".to_string(), "no_candidates"),
+    };
+    let delta = parentage::delta(ranked).unwrap();
+
+    let status = if best.exclusions > criteria.max_exclusions {
+        "excluded"
+    } else if best.lod <= 0.0 || delta < criteria.min_delta {
+        "ambiguous"
+    } else {
+        "assigned"
+    };
+    let summary = format!(
+        "{}\t{}\t{}\t{:.3}\t{:.3}\t{}",
+        best.parents.connect(","),
+        best.loci,
+        best.exclusions,
+        best.lod,
+        delta,
+        status,
+    );
+    (summary, status)
+}
+
+/// Reads a list of samples, one per line, skipping blank lines and # comments
+fn read_samples(path: &str) -> Vec<String> {
+    let file = BufReader::new(File::open(&Path::new(path)).unwrap_or_else(|error| fail(&format!("{}: {}", path, error))));
+    let mut samples = vec!();
+    for line in file.lines() {
+        let line = line.unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+        let sample = line.trim();
+        if sample.len() > 0 && !sample.starts_with("#") {
+            samples.push(sample.to_string());
+        }
+    }
+    samples
+}
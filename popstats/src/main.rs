@@ -0,0 +1,195 @@
+extern crate bio;
+extern crate getopts;
+
+use std::fs::File;
+use std::io::{
+    BufReader,
+    BufWriter,
+    Write,
+};
+use std::path::Path;
+use std::process;
+
+use getopts::Options;
+
+use bio::calls;
+use bio::cli::{fail, parse_opt};
+use bio::concordance::GenotypeTable;
+use bio::metadata::SampleMetadata;
+use bio::popstats::{FstComponents, LocusStats};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "genotypes", "call_consensus genotype calls (default genotypes.tsv)", "FILE");
+    opts.optopt("", "metadata", "tab separated sample metadata assigning populations, with a header line and a sample column", "FILE");
+    opts.optopt("", "population-column", "metadata column naming each sample's population (default population)", "NAME");
+    opts.optopt("", "hwe-alpha", "p-value below which a locus is out of Hardy-Weinberg equilibrium (default 0.05)", "F");
+    opts.optopt("", "permutations", "permutations for testing loci with more than two alleles (default 1000)", "N");
+    opts.optopt("o", "output-prefix", "prefix of the files written (default popstats)", "PREFIX");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(1);
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: popstats [options]"));
+        return;
+    }
+
+    let hwe_alpha: f64 = parse_opt(&matches, "hwe-alpha", 0.05);
+    let permutations: usize = parse_opt(&matches, "permutations", 1000);
+
+    let calls_path = matches.opt_str("genotypes").unwrap_or("genotypes.tsv".to_string());
+    let mut calls_file = BufReader::new(File::open(&Path::new(&calls_path)).unwrap_or_else(|error| fail(&format!("{}: {}", calls_path, error))));
+    let calls = calls::read_calls(&mut calls_file).unwrap_or_else(|error| fail(&format!("{}: {}", calls_path, error)));
+    let table = GenotypeTable::new(&calls);
+
+    // Samples of each population in the order the populations first appear, all samples being
+    // one population without metadata
+    let mut populations: Vec<(String, Vec<String>)> = vec!();
+    match matches.opt_str("metadata") {
+        Some(path) => {
+            let column = matches.opt_str("population-column").unwrap_or("population".to_string());
+            let mut metadata_file = BufReader::new(File::open(&Path::new(&path)).unwrap_or_else(|error| fail(&format!("{}: {}", path, error))));
+            let metadata = SampleMetadata::read(&mut metadata_file).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+            if !metadata.has_column(&column) {
+                fail::<()>(&format!("{} has no {} column", path, column));
+            }
+
+            let mut unassigned = 0;
+            for sample in &table.samples {
+                let population = match metadata.get(sample, &column) {
+                    Some(population) => population,
+                    None => {
+                        unassigned += 1;
+                        continue;
+                    },
+                };
+                match populations.iter().position(|&(ref name, _)| name == population) {
+                    Some(i) => populations[i].1.push(sample.clone()),
+                    None => populations.push((population.to_string(), vec![sample.clone()])),
+                }
+            }
+            if unassigned > 0 {
+                println!("WARNING: {} samples have no population and were left out", unassigned);
+            }
+        },
+        None => populations.push(("all".to_string(), table.samples.clone())),
+    }
+
+    let prefix = matches.opt_str("output-prefix").unwrap_or("popstats".to_string());
+    let create = |name: &str| BufWriter::new(File::create(&Path::new(&format!("{}_{}.tsv", prefix, name))).unwrap());
+    let mut loci_file = create("loci");
+    let mut frequencies_file = create("frequencies");
+    let mut fst_loci_file = create("fst_loci");
+    let mut flags_file = create("flags");
+    writeln!(loci_file, "locus\tpopulation\tindividuals\talleles\tobserved_het\texpected_het\thwe_p_value").unwrap();
+    writeln!(frequencies_file, "locus\tpopulation\tallele\tcount\tfrequency").unwrap();
+    writeln!(fst_loci_file, "locus\tpopulation_1\tpopulation_2\tfst").unwrap();
+    writeln!(flags_file, "locus\tpopulations_tested\tpopulations_out_of_hwe\tmean_observed_het\tmean_expected_het\tflag").unwrap();
+
+    let n = populations.len();
+    let mut fst_totals: Vec<Vec<(FstComponents, usize)>> = vec![vec![(FstComponents::zero(), 0); n]; n];
+    let mut paralogs = 0;
+    for locus in table.loci() {
+        // Diploid genotypes of each population
+        let genotypes: Vec<Vec<&[String]>> = populations.iter().map(|&(_, ref samples)| {
+            samples.iter()
+                .filter_map(|sample| table.get(sample, &locus))
+                .filter(|alleles| alleles.len() == 2)
+                .collect()
+        }).collect();
+
+        let (mut tested, mut out, mut het_excess) = (0, 0, true);
+        let (mut observed_het, mut expected_het) = (0.0, 0.0);
+        for (i, &(ref population, _)) in populations.iter().enumerate() {
+            if genotypes[i].len() == 0 {
+                continue;
+            }
+            let stats = LocusStats::new(&genotypes[i], permutations);
+            writeln!(
+                loci_file,
+                "{}\t{}\t{}\t{}\t{:.4}\t{:.4}\t{}",
+                locus,
+                population,
+                stats.individuals,
+                stats.allele_counts.len(),
+                stats.observed_het,
+                stats.expected_het,
+                stats.hwe_p_value.map(|p| format!("{:.4e}", p)).unwrap_or("NA".to_string()),
+            ).unwrap();
+            for (allele, &count) in &stats.allele_counts {
+                writeln!(frequencies_file, "{}\t{}\t{}\t{}\t{:.4}", locus, population, allele, count, stats.frequency(allele)).unwrap();
+            }
+
+            if let Some(p) = stats.hwe_p_value {
+                tested += 1;
+                observed_het += stats.observed_het;
+                expected_het += stats.expected_het;
+                if p < hwe_alpha {
+                    out += 1;
+                    het_excess = het_excess && stats.observed_het > stats.expected_het;
+                }
+            }
+        }
+
+        // Out of equilibrium everywhere with too many heterozygotes is what a paralog looks like
+        let flag = if tested == 0 {
+            "untested"
+        } else if out < tested {
+            "pass"
+        } else if het_excess {
+            paralogs += 1;
+            "likely_paralog"
+        } else {
+            "out_of_hwe"
+        };
+        let mean = |sum: f64| if tested > 0 { format!("{:.4}", sum / tested as f64) } else { "NA".to_string() };
+        writeln!(flags_file, "{}\t{}\t{}\t{}\t{}\t{}", locus, tested, out, mean(observed_het), mean(expected_het), flag).unwrap();
+
+        for a in 0..n {
+            for b in (a + 1)..n {
+                let components = FstComponents::new(&[genotypes[a].clone(), genotypes[b].clone()]);
+                let fst = components.fst();
+                writeln!(
+                    fst_loci_file,
+                    "{}\t{}\t{}\t{}",
+                    locus,
+                    populations[a].0,
+                    populations[b].0,
+                    fst.map(|fst| format!("{:.4}", fst)).unwrap_or("NA".to_string()),
+                ).unwrap();
+                // Every locus adds to the multi-locus estimate, even one whose own estimate is undefined
+                fst_totals[a][b].0.add(&components);
+                if fst.is_some() {
+                    fst_totals[a][b].1 += 1;
+                }
+            }
+        }
+    }
+
+    // Multi-locus F_ST of every pair of populations
+    let mut fst_file = create("fst");
+    writeln!(fst_file, "population_1\tpopulation_2\tloci\tfst").unwrap();
+    for a in 0..n {
+        for b in (a + 1)..n {
+            let (ref components, loci) = fst_totals[a][b];
+            writeln!(
+                fst_file,
+                "{}\t{}\t{}\t{}",
+                populations[a].0,
+                populations[b].0,
+                loci,
+                components.fst().map(|fst| format!("{:.4}", fst)).unwrap_or("NA".to_string()),
+            ).unwrap();
+        }
+    }
+
+    println!("Summarized {} loci in {} populations, {} look like paralogs", table.loci().len(), n, paralogs);
+}
@@ -1,61 +1,114 @@
-#![feature(io)]
-#![feature(path)]
-#![feature(core)]
-
 extern crate bio;
-
-use std::old_io::{
-    BufferedReader,
-    BufferedWriter,
-    File,
+extern crate getopts;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{
+    BufReader,
+    BufWriter,
+    Write,
 };
+use std::path::Path;
 
-use bio::fastq;
+use getopts::Options;
 
-use find::Find;
-
-mod find;
+use bio::cli::parse_opt;
+use bio::fastq;
+use bio::join::{self, MergeFailure, MergeParams, Spacer};
 
 fn main() {
     use ProcessedSequence::*;
 
-    let oligo_size = 10;
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "oligo-size", "bases of the reverse read searched for in the forward read (default 10)", "N");
+    opts.optopt("", "spacer-length", "N bases placed between concatenated reads (default 10)", "N");
+    opts.optopt("", "spacer-quality", "Phred quality of the spacer bases (default 2)", "Q");
+    opts.optopt("", "max-mismatches", "mismatches allowed when searching for the reverse read (default 0)", "N");
+    opts.optopt("", "min-length", "reads shorter than N can't be merged (default 0)", "N");
+    opts.optopt("", "max-n-fraction", "fraction of N bases above which a failed pair is reported as high N (default 0.1)", "F");
+    opts.optflag("", "reject-ambiguous", "don't merge pairs whose reverse read is found equally well at several places");
+    opts.optflag("", "no-concatenate", "write pairs that fail to merge to the unjoined files instead");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: total_joiner [options]"));
+        return;
+    }
+
+    let mut merge_params = MergeParams::new(parse_opt(&matches, "oligo-size", 10));
+    merge_params.max_mismatches = parse_opt(&matches, "max-mismatches", merge_params.max_mismatches);
+    merge_params.min_length = parse_opt(&matches, "min-length", merge_params.min_length);
+    merge_params.max_n_fraction = parse_opt(&matches, "max-n-fraction", merge_params.max_n_fraction);
+    merge_params.reject_ambiguous = matches.opt_present("reject-ambiguous");
+    let spacer = Spacer::new(parse_opt(&matches, "spacer-length", 10), parse_opt(&matches, "spacer-quality", 2));
+    let concatenate = !matches.opt_present("no-concatenate");
+
+    let mut forward_fastq = BufReader::new(File::open(&Path::new("forward.fastq")).unwrap());
+    let mut reverse_fastq = BufReader::new(File::open(&Path::new("reverse.fastq")).unwrap());
 
-    let mut forward_fastq = BufferedReader::new(File::open(&Path::new("forward.fastq")));
-    let mut reverse_fastq = BufferedReader::new(File::open(&Path::new("reverse.fastq")));
-    
     let forward_seqs = fastq::read_fastq(&mut forward_fastq);
     let reverse_seqs = fastq::read_fastq(&mut reverse_fastq);
 
-    let maybe_joined_seqs =
-        forward_seqs.into_iter().zip(reverse_seqs.into_iter()).map(|(forward_seq, reverse_seq)| {
-            // Get forward oligo from forward sequence
-            let forward_oligo = forward_seq.bases.head(oligo_size);
-
-            // Get reverse oligo from the reverse sequence
-            let mut reverse_oligo = reverse_seq.bases.head(oligo_size);
-            reverse_oligo.reverse_complement();
-
-            match forward_seq.bases.bases.find(reverse_oligo.bases.as_slice()) { 
-                Some(index) => {
-                    let merged_len = index + reverse_oligo.len();
-                    Joined(fastq::Sequence {
-                        header: forward_seq.header,
-                        bases: forward_seq.bases.head(merged_len),
-                        qual: forward_seq.qual[0..merged_len].to_string(),
-                    })
-                },
-                None => Unjoined(forward_seq, reverse_seq),
-            }
-        });
-
-    let maybe_joined_seqs: Vec<ProcessedSequence> = maybe_joined_seqs.collect();
+    let mut num_merged = 0;
+    let mut failure_counts: HashMap<MergeFailure, usize> = HashMap::new();
+
+    let mut maybe_joined_seqs: Vec<ProcessedSequence> = vec!();
+    for (mut forward_seq, mut reverse_seq) in forward_seqs.into_iter().zip(reverse_seqs.into_iter()) {
+        match join::try_merge(&forward_seq, &reverse_seq, &merge_params) {
+            Ok(merged) => {
+                num_merged += 1;
+                maybe_joined_seqs.push(Joined(merged));
+            },
+            Err(failure) => {
+                *failure_counts.entry(failure).or_insert(0) += 1;
+
+                if concatenate {
+                    maybe_joined_seqs.push(Joined(join::concatenate(&forward_seq, &reverse_seq, &spacer)));
+                } else {
+                    // Record why the pair didn't merge on both mates
+                    forward_seq.add_tag(join::UNMERGED_TAG, failure.as_str());
+                    reverse_seq.add_tag(join::UNMERGED_TAG, failure.as_str());
+
+                    maybe_joined_seqs.push(Unjoined(forward_seq, reverse_seq));
+                }
+            },
+        }
+    }
+
+    // Write join report
+    {
+        let mut join_report = BufWriter::new(File::create(&Path::new("join_report.txt")).unwrap());
+
+        let num_unmerged = maybe_joined_seqs.len() - num_merged;
+        writeln!(join_report, "Pairs: {}", maybe_joined_seqs.len()).unwrap();
+        writeln!(join_report, "Merged: {}", num_merged).unwrap();
+        if concatenate {
+            writeln!(join_report, "Concatenated: {}", num_unmerged).unwrap();
+        } else {
+            writeln!(join_report, "Unjoined: {}", num_unmerged).unwrap();
+        }
+        writeln!(join_report, "").unwrap();
+        writeln!(join_report, "reason\tcount").unwrap();
+        writeln!(join_report, "------\t-----").unwrap();
+        for failure in MergeFailure::all() {
+            writeln!(join_report, "{}\t{}", failure.as_str(), failure_counts.get(&failure).cloned().unwrap_or(0)).unwrap();
+        }
+    }
 
     {
         let joined_seqs = maybe_joined_seqs.iter().filter_map(|s| s.clone().map_joined());
-        
+
         // Write joined fastq
-        let mut joined_fastq = BufferedWriter::new(File::create(&Path::new("joined.fastq"))); 
+        let mut joined_fastq = BufWriter::new(File::create(&Path::new("joined.fastq")).unwrap());
         fastq::write_fastq_owned(&mut joined_fastq, joined_seqs).ok()
             .expect("Failed to write joined fastq file");
     }
@@ -67,12 +120,12 @@ fn main() {
     let unjoined_reverse_seqs = unjoined_seqs.iter().map(|&(_, ref r)| r);
 
     // Write unjoined forward fastq
-    let mut unjoined_forward_fastq = BufferedWriter::new(File::create(&Path::new("unjoined_forward.fastq"))); 
+    let mut unjoined_forward_fastq = BufWriter::new(File::create(&Path::new("unjoined_forward.fastq")).unwrap());
     fastq::write_fastq(&mut unjoined_forward_fastq, unjoined_forward_seqs).ok()
         .expect("Failed to write unjoined forward fastq file");
 
     // Write unjoined reverse fastq
-    let mut unjoined_reverse_fastq = BufferedWriter::new(File::create(&Path::new("unjoined_reverse.fastq"))); 
+    let mut unjoined_reverse_fastq = BufWriter::new(File::create(&Path::new("unjoined_reverse.fastq")).unwrap());
     fastq::write_fastq(&mut unjoined_reverse_fastq, unjoined_reverse_seqs).ok()
         .expect("Failed to write unjoined reverse fastq file");
 }
@@ -96,7 +149,7 @@ impl ProcessedSequence {
 
     fn map_unjoined(self) -> Option<(fastq::Sequence, fastq::Sequence)> {
         use ProcessedSequence::*;
-        
+
         if let Unjoined(forward, reverse) = self {
             Some((forward, reverse))
         } else {
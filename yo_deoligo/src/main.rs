@@ -1,58 +1,91 @@
-#![feature(io)]
-#![feature(core)]
-#![feature(path)]
-#![feature(std_misc)]
-
 extern crate bio;
+extern crate getopts;
 
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
-use std::old_io::{
-    BufferedReader,
-    BufferedWriter,
-    File,
+use std::fs::{create_dir, File};
+use std::io::{
+    BufRead,
+    BufReader,
+    BufWriter,
+    Write,
 };
+use std::path::Path;
+
+use getopts::Options;
 
 use bio::bases;
 use bio::fastq;
 
 fn main() {
-    let mut fastq = BufferedReader::new(File::open(&Path::new("in.fastq")));
-    
-    let seqs = fastq::read_fastq(&mut fastq);
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "forward", "forward (R1) fastq of a paired run, matched against the forward barcodes", "FILE");
+    opts.optopt("", "reverse", "reverse (R2) fastq of a paired run, matched against the reverse barcodes", "FILE");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        },
+    };
+    if matches.opt_present("h") {
+        print!("{}", opts.usage("usage: yo_deoligo [--forward <R1.fastq> --reverse <R2.fastq>]"));
+        return;
+    }
+
+    let oligos = read_oligos(&Path::new("in.oligos"));
+
+    match (matches.opt_str("forward"), matches.opt_str("reverse")) {
+        (Some(forward), Some(reverse)) => deoligo_paired(&Path::new(&forward), &Path::new(&reverse), &oligos),
+        (None, None) => deoligo_single(&Path::new("in.fastq"), &oligos),
+        _ => println!("--forward and --reverse must be given together"),
+    }
+}
 
-    // Read the oligos file
+/// Reads the oligos file
+/// Maps oligo name to forward and reverse barcode sequences
+fn read_oligos(path: &Path) -> HashMap<String, (bases::Bases, bases::Bases)> {
+    let mut oligos = HashMap::new();
 
-    // Maps oligo name to forward and reverse barcode sequences
-    let mut oligos: HashMap<String, (String, String)> = HashMap::new();
+    let oligos_file = BufReader::new(File::open(path).unwrap());
 
-    let oligos_file = File::open(&Path::new("in.oligos"));
-    let mut oligos_file = BufferedReader::new(oligos_file);
+    for (line_number, line) in oligos_file.lines().enumerate() {
+        let line = line.unwrap();
+        let line_split: Vec<String> = line.split('\t').map(|s| s.trim_right().to_string()).collect();
 
-    let mut line_number: u32 = 0;
-    while let Ok(line) = oligos_file.read_line() {
-        let line_split: Vec<String> = line.as_slice().split('\t').map(|s| s.trim_right().to_string()).collect();
-        
-        // Verify that the line is properly formatted
-        if line_split.len() != 3 {
+        // Verify that the line is properly formatted, a well and plate may follow for contamination
+        if line_split.len() < 3 || line_split.len() > 5 {
             panic!(
-                "Expected 3 columns in oligos file, found {} columns at line {}",
+                "Expected 3 to 5 columns in oligos file, found {} columns at line {}",
                 line_split.len(),
                 line_number
             );
         }
 
         // Add the current oligo
-        oligos.insert(line_split[2].clone(), (line_split[0].clone(), line_split[1].clone()));
-
-        // Increment line number
-        line_number += 1;
+        oligos.insert(
+            line_split[2].clone(),
+            (bases::Bases::from_str(&line_split[0]), bases::Bases::from_str(&line_split[1])),
+        );
     }
 
+    oligos
+}
+
+/// Sorts single reads carrying the forward barcode at their head and the reverse barcode at their tail
+fn deoligo_single(fastq_path: &Path, oligos: &HashMap<String, (bases::Bases, bases::Bases)>) {
+    let mut fastq = BufReader::new(File::open(fastq_path).unwrap());
+
+    let seqs = fastq::read_fastq(&mut fastq);
+
     // Sort the sequences by oligo
 
-    // Maps oligo name to list of debarcoded seqs
-    let mut seqs_sorted: HashMap<String, Vec<fastq::Sequence>> = HashMap::new();
+    // Maps oligo name to number of debarcoded seqs
+    let mut oligo_counts: HashMap<String, usize> = HashMap::new();
     let mut succeeded_seqs = vec!();
     let mut failed_seqs = vec!();
 
@@ -60,25 +93,20 @@ fn main() {
         let mut sorted_seq = seq.clone();
         for (oligo_name, &(ref forward, ref reverse)) in oligos.iter() {
             // Attempt to debarcode the sequence
-            let debarcoded =
-                sorted_seq.debarcode(
-                    &bases::Bases::from_str(forward.as_slice()),
-                    &bases::Bases::from_str(reverse.as_slice()),
-                    0,
-                );
+            let debarcoded = sorted_seq.debarcode(forward, reverse, 0);
 
             // Check if debarcoding succeeded
             if debarcoded {
                 // Build the new sorted sequence
                 sorted_seq.header.push_str(" ");
-                sorted_seq.header.push_str(oligo_name.as_slice());
+                sorted_seq.header.push_str(oligo_name);
 
-                succeeded_seqs.push(sorted_seq.clone());
+                succeeded_seqs.push(sorted_seq);
 
-                // Update our sorted sequences map
-                match seqs_sorted.entry(oligo_name.clone()) {
-                    Entry::Occupied(mut entry) => { entry.get_mut().push(sorted_seq); },
-                    Entry::Vacant(entry) => { entry.insert(vec![sorted_seq]); },
+                // Update our oligo counts
+                match oligo_counts.entry(oligo_name.clone()) {
+                    Entry::Occupied(mut entry) => { *entry.get_mut() += 1; },
+                    Entry::Vacant(entry) => { entry.insert(1); },
                 }
 
                 // Done with this sequence, move on
@@ -91,35 +119,107 @@ fn main() {
     }
 
     // Output report
-    let mut output_report = BufferedWriter::new(File::create(&Path::new("output_report.txt")));
-
-    output_report.write_line("## OLIGO STATS ##").unwrap();
-    output_report.write_line("").unwrap();
-    output_report.write_line("oligo_id\tcount").unwrap();
-    output_report.write_line("--------\t-----").unwrap();
-    for (oligo_name, seqs) in seqs_sorted.iter() {
-        output_report.write_line(format!("{}\t{}", oligo_name, seqs.len()).as_slice()).unwrap();
-    }
-    output_report.write_line("").unwrap();
-    output_report.write_line("## READ STATS ##").unwrap();
-    output_report.write_line("").unwrap();
-
-    let num_deoligoed = seqs_sorted.values().fold(0, |count, seqs| count + seqs.len());
-
-    output_report.write_line(format!("Reads Successfully Deoligoed: {}", num_deoligoed).as_slice()).unwrap();
-    output_report.write_line(format!("Reads Failed Deoligoed: {}", seqs.len() - num_deoligoed).as_slice()).unwrap();
-    output_report.write_line("").unwrap();
-    output_report.write_line("List of reads that failed to deoligo:").unwrap();
-    
-    for seq in failed_seqs.iter() {
-        output_report.write_line(seq.header.as_slice()).unwrap();
-    }
+    write_report(&oligo_counts, seqs.len(), failed_seqs.iter().map(|seq| &seq.header[..]));
 
     // Output sorted fastq
-    let mut sorted_fastq = BufferedWriter::new(File::create(&Path::new("sorted.fastq")));
+    let mut sorted_fastq = BufWriter::new(File::create(&Path::new("sorted.fastq")).unwrap());
     fastq::write_fastq(&mut sorted_fastq, succeeded_seqs.iter()).ok().expect("Failed to write sorted fastq");
 
     // Output failed fastq
-    let mut failed_fastq = BufferedWriter::new(File::create(&Path::new("failed.fastq")));
+    let mut failed_fastq = BufWriter::new(File::create(&Path::new("failed.fastq")).unwrap());
     fastq::write_fastq(&mut failed_fastq, failed_seqs.iter()).ok().expect("Failed to write failed fastq");
 }
+
+/// Sorts read pairs carrying the forward barcode at the head of R1 and the reverse barcode at
+/// the head of R2, writing a pair of fastq files per oligo into sorted/
+fn deoligo_paired(forward_path: &Path, reverse_path: &Path, oligos: &HashMap<String, (bases::Bases, bases::Bases)>) {
+    let mut forward_fastq = BufReader::new(File::open(forward_path).unwrap());
+    let mut reverse_fastq = BufReader::new(File::open(reverse_path).unwrap());
+
+    let forward_seqs = fastq::read_fastq(&mut forward_fastq);
+    let reverse_seqs = fastq::read_fastq(&mut reverse_fastq);
+
+    if forward_seqs.len() != reverse_seqs.len() {
+        panic!("Forward and reverse fastq have different numbers of reads: {} and {}", forward_seqs.len(), reverse_seqs.len());
+    }
+    let num_pairs = forward_seqs.len();
+
+    // Maps oligo name to list of debarcoded pairs
+    let mut pairs_sorted: HashMap<String, Vec<(fastq::Sequence, fastq::Sequence)>> = HashMap::new();
+    let mut failed_pairs = vec!();
+
+    'pairs: for (forward_seq, reverse_seq) in forward_seqs.into_iter().zip(reverse_seqs.into_iter()) {
+        let mut sorted_forward = forward_seq.clone();
+        let mut sorted_reverse = reverse_seq.clone();
+        for (oligo_name, &(ref forward, ref reverse)) in oligos.iter() {
+            // Each mate has to carry its own barcode
+            if fastq::debarcode_pair(&mut sorted_forward, &mut sorted_reverse, forward, reverse, 0) {
+                sorted_forward.header.push_str(" ");
+                sorted_forward.header.push_str(oligo_name);
+                sorted_reverse.header.push_str(" ");
+                sorted_reverse.header.push_str(oligo_name);
+
+                pairs_sorted.entry(oligo_name.clone()).or_insert(vec!()).push((sorted_forward, sorted_reverse));
+
+                continue 'pairs;
+            }
+        }
+
+        failed_pairs.push((forward_seq, reverse_seq));
+    }
+
+    // Output report
+    let oligo_counts: HashMap<String, usize> = pairs_sorted.iter().map(|(name, pairs)| (name.clone(), pairs.len())).collect();
+    write_report(&oligo_counts, num_pairs, failed_pairs.iter().map(|&(ref forward, _)| &forward.header[..]));
+
+    // Output sorted fastq pairs
+    if !Path::new("sorted").is_dir() {
+        create_dir("sorted").ok().expect("Failed to create sorted directory");
+    }
+    for (oligo_name, pairs) in pairs_sorted.iter() {
+        write_pairs(&format!("sorted/{}", oligo_name), pairs);
+    }
+
+    // Output failed fastq pair
+    write_pairs("failed", &failed_pairs);
+}
+
+/// Writes read pairs to <prefix>_R1.fastq and <prefix>_R2.fastq
+fn write_pairs(prefix: &str, pairs: &[(fastq::Sequence, fastq::Sequence)]) {
+    let mut forward_fastq = BufWriter::new(File::create(&Path::new(&format!("{}_R1.fastq", prefix))).unwrap());
+    fastq::write_fastq(&mut forward_fastq, pairs.iter().map(|&(ref f, _)| f)).ok()
+        .expect("Failed to write forward fastq");
+
+    let mut reverse_fastq = BufWriter::new(File::create(&Path::new(&format!("{}_R2.fastq", prefix))).unwrap());
+    fastq::write_fastq(&mut reverse_fastq, pairs.iter().map(|&(_, ref r)| r)).ok()
+        .expect("Failed to write reverse fastq");
+}
+
+fn write_report<'a, I>(oligo_counts: &HashMap<String, usize>, num_reads: usize, failed_headers: I)
+    where
+        I: Iterator<Item=&'a str>,
+{
+    let mut output_report = BufWriter::new(File::create(&Path::new("output_report.txt")).unwrap());
+
+    writeln!(output_report, "## OLIGO STATS ##").unwrap();
+    writeln!(output_report, "").unwrap();
+    writeln!(output_report, "oligo_id\tcount").unwrap();
+    writeln!(output_report, "--------\t-----").unwrap();
+    for (oligo_name, count) in oligo_counts.iter() {
+        writeln!(output_report, "{}\t{}", oligo_name, count).unwrap();
+    }
+    writeln!(output_report, "").unwrap();
+    writeln!(output_report, "## READ STATS ##").unwrap();
+    writeln!(output_report, "").unwrap();
+
+    let num_deoligoed = oligo_counts.values().fold(0, |count, &oligo_count| count + oligo_count);
+
+    writeln!(output_report, "Reads Successfully Deoligoed: {}", num_deoligoed).unwrap();
+    writeln!(output_report, "Reads Failed Deoligoed: {}", num_reads - num_deoligoed).unwrap();
+    writeln!(output_report, "").unwrap();
+    writeln!(output_report, "List of reads that failed to deoligo:").unwrap();
+
+    for header in failed_headers {
+        writeln!(output_report, "{}", header).unwrap();
+    }
+}